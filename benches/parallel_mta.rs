@@ -0,0 +1,37 @@
+#[macro_use]
+extern crate criterion;
+extern crate gg18_etude;
+extern crate rayon;
+
+use criterion::{BenchmarkId, Criterion};
+use gg18_etude::etude::Etude;
+use gg18_etude::keygen::KeyGen;
+
+// Signing time is dominated by the pairwise MtA. A single thread pool gives the
+// sequential baseline, so the speedup is the ratio of the two lines for each n.
+fn bench_parallel_mta(c: &mut Criterion) {
+    let message = "Miku-san maji tenshi!".as_bytes();
+    let single = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("sign");
+    group.sample_size(10);
+    for n in 2..11 {
+        let shares = KeyGen::new(1, n).run().unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("single_thread", n),
+            &shares,
+            |b, shares| {
+                b.iter(|| single.install(|| Etude::with_key_shares(shares).unwrap().sign(&message)))
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("parallel", n), &shares, |b, shares| {
+            b.iter(|| Etude::with_key_shares(shares).unwrap().sign(&message))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parallel_mta);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+#[macro_use]
+extern crate criterion;
+extern crate curv;
+extern crate gg18_etude;
+extern crate paillier;
+extern crate rand;
+
+use criterion::{BatchSize, BenchmarkId, Criterion};
+use curv::elliptic::curves::traits::ECScalar;
+use curv::FE;
+use gg18_etude::etude::Etude;
+use gg18_etude::keygen::{KeyGen, KeyShare};
+use gg18_etude::mta;
+use gg18_etude::ring_pedersen::RingPedersenParams;
+use paillier::BigInt;
+use rand::thread_rng;
+
+const MESSAGE: &[u8] = b"Miku-san maji tenshi!";
+const NS: [usize; 3] = [2, 4, 6];
+
+fn bench_paillier_keygen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mta");
+    group.sample_size(10);
+    group.bench_function("Dec::new", |b| {
+        b.iter(|| mta::Dec::new(2048, &mut thread_rng()))
+    });
+    group.finish();
+}
+
+fn bench_mta_round_trip(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    let dec = mta::Dec::new(2048, &mut rng);
+    let (aux, _) = RingPedersenParams::generate(&mut rng);
+    let a = BigInt::from(12345);
+    let mut group = c.benchmark_group("mta");
+    group.sample_size(10);
+    group.bench_function("round_trip", |b| {
+        b.iter(|| {
+            let mut pa: mta::Party = mta::Party::with_dec(dec.clone(), a.clone());
+            let mut pb: mta::Party = mta::Party::new(0, a.clone());
+            let alice = pa.alicization(&mut rng);
+            let (e, c) = alice.to_bob(&mut rng);
+            let c = pb.bobization().from_alice(e, &c, &mut rng);
+            alice.from_bob(&c);
+        })
+    });
+    group.bench_function("round_trip_with_proof", |b| {
+        b.iter(|| {
+            let mut pa: mta::Party = mta::Party::with_dec(dec.clone(), a.clone());
+            let mut pb: mta::Party = mta::Party::new(0, a.clone());
+            let alice = pa.alicization(&mut rng);
+            let (e, c, proof) = alice.to_bob_with_proof(&aux, &mut rng);
+            assert!(proof.verify(&FE::q(), e, &c.0, &aux));
+            let c = pb.bobization().from_alice(e, &c, &mut rng);
+            alice.from_bob(&c);
+        })
+    });
+    group.finish();
+}
+
+// an Etude with the first `phases` steps of signing done
+fn prepare(shares: &[KeyShare], phases: usize) -> Etude {
+    let mut rng = thread_rng();
+    let mut gg18 = Etude::with_key_shares(shares).unwrap();
+    if phases > 0 {
+        gg18.phase1_begin(&mut rng);
+    }
+    if phases > 1 {
+        gg18.phase1_broadcast_gr(&mut rng);
+    }
+    if phases > 2 {
+        gg18.phase2_exchange_mta(&mut rng);
+    }
+    if phases > 3 {
+        gg18.phase3_broadcast_delta();
+    }
+    if phases > 4 {
+        gg18.phase4_local_sign(MESSAGE);
+    }
+    gg18
+}
+
+fn bench_sign(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sign");
+    group.sample_size(10);
+    for n in NS.iter() {
+        let shares = KeyGen::new(1, *n).run().unwrap();
+        let mut phase = |name: &str, phases: usize, f: fn(&mut Etude)| {
+            group.bench_with_input(BenchmarkId::new(name, n), &shares, |b, shares| {
+                b.iter_batched(
+                    || prepare(shares, phases),
+                    |mut gg18| f(&mut gg18),
+                    BatchSize::PerIteration,
+                )
+            });
+        };
+        phase("phase1_begin", 0, |e| e.phase1_begin(&mut thread_rng()));
+        phase("phase1_broadcast_gr", 1, |e| {
+            e.phase1_broadcast_gr(&mut thread_rng())
+        });
+        phase("phase2_exchange_mta", 2, |e| {
+            e.phase2_exchange_mta(&mut thread_rng())
+        });
+        phase("phase3_broadcast_delta", 3, |e| e.phase3_broadcast_delta());
+        phase("phase4_local_sign", 4, |e| e.phase4_local_sign(MESSAGE));
+        phase("phase5_gather_signatures", 5, |e| {
+            e.phase5_gather_signatures()
+        });
+        group.bench_with_input(BenchmarkId::new("full", n), &shares, |b, shares| {
+            b.iter(|| Etude::with_key_shares(shares).unwrap().sign(MESSAGE))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_paillier_keygen,
+    bench_mta_round_trip,
+    bench_sign
+);
+criterion_main!(benches);
@@ -0,0 +1,395 @@
+extern crate sha2;
+use self::sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::auth::IdentityKey;
+use crate::error::{Error, Result};
+use crate::eth::to_hex;
+use crate::network::{Handler, Outgoing};
+use crate::party_id::PartyId;
+use crate::schnorr;
+
+// Rounds carried between machines as files, for parties that are never online. A party
+// runs as far as it can, its messages are exported to a file, and the party is saved to
+// disk until the files of its peers are imported. Each file is signed with the sender's
+// identity key and numbered, so a party takes every file of a peer once and in order.
+// Nothing is encrypted; the files are assumed to travel by hand.
+
+fn file_error<E: ToString>(path: &Path, e: E) -> Error {
+    Error::Transport(format!("{}: {}", path.display(), e.to_string()))
+}
+
+// readable by the owner only, as a state file holds the party's secrets
+#[cfg(unix)]
+fn create(path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // mode only applies to a new file
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create(path: &Path) -> io::Result<fs::File> {
+    fs::File::create(path)
+}
+
+pub fn save<T: Serialize, P: AsRef<Path>>(path: P, value: &T) -> Result<()> {
+    let path = path.as_ref();
+    let json = serde_json::to_string(value).map_err(|e| file_error(path, e))?;
+    create(path)
+        .and_then(|mut file| file.write_all(json.as_bytes()))
+        .map_err(|e| file_error(path, e))
+}
+
+pub fn load<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path).map_err(|e| file_error(path, e))?;
+    serde_json::from_str(&json).map_err(|e| file_error(path, e))
+}
+
+// What one party sent since its last export. Broadcasts are listed once per recipient, so
+// the same file can be carried to every peer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub session: String,
+    pub from: usize,
+    pub seq: u64,         // bundles `from` exported before this one
+    pub messages: String, // JSON of (recipient, message)
+    pub sig: Vec<u8>,     // BIP340 by `from` over all of the above
+}
+
+impl Bundle {
+    fn digest(&self) -> Vec<u8> {
+        let from = (self.from as u64).to_be_bytes();
+        let seq = self.seq.to_be_bytes();
+        let parts = [
+            self.session.as_bytes(),
+            &from[..],
+            &seq[..],
+            self.messages.as_bytes(),
+        ];
+        let mut h = Sha256::new();
+        for part in parts.iter() {
+            h.input(&(part.len() as u64).to_be_bytes());
+            h.input(part);
+        }
+        h.result().to_vec()
+    }
+}
+
+// A party with the messages it has not exported yet.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "H: Serialize + DeserializeOwned")]
+pub struct AirGapped<H: Handler> {
+    pub i: usize,
+    pub n: usize,
+    session: String,
+    key: IdentityKey,
+    peers: Vec<PartyId>, // by position, ours included
+    party: H,
+    outbox: Vec<(usize, H::Message)>,
+    sent: u64,
+    seen: Vec<u64>, // bundles imported from each peer
+}
+
+impl<H: Handler> AirGapped<H> {
+    pub fn new(party: H, session: &str, key: IdentityKey, peers: &[PartyId]) -> Result<Self> {
+        let i = peers
+            .iter()
+            .position(|id| *id == key.party_id())
+            .ok_or(Error::UnknownParty)?;
+        Ok(AirGapped {
+            i: i,
+            n: peers.len(),
+            session: session.to_string(),
+            key: key,
+            peers: peers.to_vec(),
+            party: party,
+            outbox: Vec::new(),
+            sent: 0,
+            seen: vec![0; peers.len()],
+        })
+    }
+
+    pub fn party(&self) -> &H {
+        &self.party
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    fn queue(&mut self, out: Vec<Outgoing<H::Message>>) {
+        for o in out {
+            match o {
+                Outgoing::Broadcast(msg) => {
+                    for j in (0..self.n).filter(|j| *j != self.i) {
+                        self.outbox.push((j, msg.clone()));
+                    }
+                }
+                Outgoing::To(j, msg) => self.outbox.push((j, msg)),
+            }
+        }
+    }
+
+    pub fn start<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<()> {
+        let out = self.party.start(rng)?;
+        self.queue(out);
+        Ok(())
+    }
+
+    // takes the messages of the bundle that are for us; a peer's bundles have to come in
+    // the order it exported them, each one once
+    pub fn import<R: CryptoRng + RngCore>(
+        &mut self,
+        bundle: Bundle,
+        rng: &mut R,
+    ) -> Result<Option<H::Output>> {
+        let from = bundle.from;
+        if from >= self.n || from == self.i {
+            return Err(Error::UnknownParty);
+        }
+        if bundle.session != self.session {
+            return Err(Error::WrongSession);
+        }
+        let pk = self.peers[from].public_key().ok_or(Error::UnknownParty)?;
+        if !schnorr::verify(&schnorr::xonly(&pk), &bundle.digest(), &bundle.sig) {
+            return Err(Error::Unauthenticated);
+        }
+        if bundle.seq < self.seen[from] {
+            return Err(Error::StaleBundle { party: from });
+        }
+        if bundle.seq > self.seen[from] {
+            return Err(Error::MissingBundle { party: from });
+        }
+        let messages: Vec<(usize, H::Message)> =
+            serde_json::from_str(&bundle.messages).map_err(|e| Error::Transport(e.to_string()))?;
+        self.seen[from] += 1;
+        for (to, msg) in messages {
+            if to != self.i {
+                continue;
+            }
+            let out = self.party.handle(from, msg, rng)?;
+            self.queue(out);
+        }
+        Ok(self.party.output())
+    }
+
+    // None once there is nothing left to send
+    pub fn export<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<Option<Bundle>> {
+        if self.outbox.is_empty() {
+            return Ok(None);
+        }
+        let messages =
+            serde_json::to_string(&self.outbox).map_err(|e| Error::Transport(e.to_string()))?;
+        let mut bundle = Bundle {
+            session: self.session.clone(),
+            from: self.i,
+            seq: self.sent,
+            messages: messages,
+            sig: Vec::new(),
+        };
+        bundle.sig = self.key.sign(&bundle.digest(), rng);
+        self.outbox.clear();
+        self.sent += 1;
+        Ok(Some(bundle))
+    }
+
+    pub fn import_file<R: CryptoRng + RngCore, P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        rng: &mut R,
+    ) -> Result<Option<H::Output>> {
+        let bundle = load(path)?;
+        self.import(bundle, rng)
+    }
+
+    // the file is not written if there is nothing to send
+    pub fn export_file<R: CryptoRng + RngCore, P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        rng: &mut R,
+    ) -> Result<bool> {
+        match self.export(rng)? {
+            Some(bundle) => save(path, &bundle).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+// How many bundles of each peer the party has taken in the session. It sits next to the
+// state file, named after the session and the party, so an older copy of the state in that
+// directory is refused instead of taking the same bundles again.
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    seen: Vec<u64>,
+}
+
+fn journal_path<H: Handler>(state: &Path, party: &AirGapped<H>) -> PathBuf {
+    let dir = state.parent().unwrap_or_else(|| Path::new(""));
+    dir.join(format!(
+        "{}_{}.journal",
+        to_hex(party.session.as_bytes()),
+        party.peers[party.i]
+    ))
+}
+
+pub fn save_state<H, P>(path: P, party: &AirGapped<H>) -> Result<()>
+where
+    H: Handler + Serialize + DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    save(path, party)?;
+    // after the state: a crash in between leaves a journal that is behind, not ahead
+    save(
+        journal_path(path, party),
+        &Journal {
+            seen: party.seen.clone(),
+        },
+    )
+}
+
+pub fn load_state<H, P>(path: P) -> Result<AirGapped<H>>
+where
+    H: Handler + Serialize + DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let party: AirGapped<H> = load(path)?;
+    // save_state always writes one; without it, e.g. for a copy elsewhere, the state
+    // could be any old one
+    let journal = journal_path(path, &party);
+    if !journal.exists() {
+        return Err(Error::MissingJournal);
+    }
+    let journal: Journal = load(&journal)?;
+    if journal.seen.len() != party.seen.len()
+        || journal
+            .seen
+            .iter()
+            .zip(party.seen.iter())
+            .any(|(j, s)| j > s)
+    {
+        return Err(Error::StaleState);
+    }
+    Ok(party)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::airgap::*;
+    use crate::etude::{digest_message, Party};
+    use crate::keygen::KeyGen;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::env;
+
+    #[test]
+    fn test_airgap() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+        let keys: Vec<IdentityKey> = (0..2).map(|_| IdentityKey::new(&mut rng)).collect();
+        let ids: Vec<PartyId> = keys.iter().map(|k| k.party_id()).collect();
+        let dir = env::temp_dir().join("gg18_etude_test_airgap");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let state = |i: usize| dir.join(format!("state_{}.json", i));
+        let bundle = |i: usize, k: usize| dir.join(format!("round_{}_{}.json", k, i));
+
+        // signers 0 and 2 of three
+        let points = [1, 3];
+        for (i, share) in [&shares[0], &shares[2]].iter().enumerate() {
+            let mut p = Party::with_key_share(i, 2, share, &points);
+            p.set_digest(digest_message(message));
+            let mut p = AirGapped::new(p, "session 1", keys[i].clone(), &ids).unwrap();
+            p.start(&mut rng).unwrap();
+            assert!(p.export_file(bundle(i, 0), &mut rng).unwrap());
+            save_state(state(i), &p).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(state(0)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::copy(state(0), dir.join("state_0_old.json")).unwrap();
+
+        // each party is loaded from disk for every round, as if on its own machine, and
+        // reads the file its peer wrote the round before
+        let mut sigs = vec![None, None];
+        let mut k = 0;
+        while sigs.iter().any(|s| s.is_none()) {
+            assert!(k < 20);
+            for i in 0..2 {
+                if sigs[i].is_some() || !bundle(1 - i, k).exists() {
+                    continue;
+                }
+                let mut p: AirGapped<Party> = load_state(state(i)).unwrap();
+                sigs[i] = p.import_file(bundle(1 - i, k), &mut rng).unwrap();
+                p.export_file(bundle(i, k + 1), &mut rng).unwrap();
+                save_state(state(i), &p).unwrap();
+            }
+            k += 1;
+        }
+        for sig in sigs {
+            assert!(sig.unwrap().verify(&shares[0].y, &digest_message(message)));
+        }
+
+        // every bundle is taken once, in order, from its signer, in its session
+        let mut p: AirGapped<Party> = load_state(state(0)).unwrap();
+        assert_eq!(
+            p.import_file(bundle(1, 0), &mut rng).err(),
+            Some(Error::StaleBundle { party: 1 })
+        );
+        let mut b: Bundle = load(bundle(1, 0)).unwrap();
+        b.seq = 100;
+        assert_eq!(p.import(b, &mut rng).err(), Some(Error::Unauthenticated));
+        let mut b: Bundle = load(bundle(1, 0)).unwrap();
+        b.session = "session 2".to_string();
+        assert_eq!(p.import(b, &mut rng).err(), Some(Error::WrongSession));
+        let mut b: Bundle = load(bundle(1, 0)).unwrap();
+        b.from = 0;
+        assert_eq!(p.import(b, &mut rng).err(), Some(Error::UnknownParty));
+
+        let mut q = Party::with_key_share(1, 2, &shares[2], &points);
+        q.set_digest(digest_message(message));
+        let mut q = AirGapped::new(q, "session 1", keys[1].clone(), &ids).unwrap();
+        q.start(&mut rng).unwrap();
+        q.sent = 100;
+        let b = q.export(&mut rng).unwrap().unwrap();
+        assert_eq!(
+            p.import(b, &mut rng).err(),
+            Some(Error::MissingBundle { party: 1 })
+        );
+
+        // the copy taken after the first round is behind the journal
+        assert_eq!(
+            load_state::<Party, _>(dir.join("state_0_old.json")).err(),
+            Some(Error::StaleState)
+        );
+        // and one away from its journal is not loaded at all
+        let away = env::temp_dir().join("gg18_etude_test_airgap_away");
+        let _ = fs::remove_dir_all(&away);
+        fs::create_dir_all(&away).unwrap();
+        fs::copy(dir.join("state_0_old.json"), away.join("state_0.json")).unwrap();
+        assert_eq!(
+            load_state::<Party, _>(away.join("state_0.json")).err(),
+            Some(Error::MissingJournal)
+        );
+        fs::remove_dir_all(&away).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
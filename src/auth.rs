@@ -0,0 +1,407 @@
+extern crate curv;
+extern crate sha2;
+use self::curv::elliptic::curves::traits::ECPoint;
+use self::curv::{FE, GE};
+use self::sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::channel::ChannelKey;
+use crate::error::{Error, Result};
+use crate::eth::{from_hex, to_hex};
+use crate::network::{Handler, Outgoing};
+use crate::party_id::PartyId;
+use crate::rng;
+use crate::schnorr;
+
+// Messages signed with long-term identity keys. Authenticated wraps any Handler: what the
+// party sends is signed over (session id, round, sender, recipient, payload), and what it
+// receives is only handed on if that signature is from the claimed sender and for us.
+// Anything else is dropped, since anyone on the path could have sent it; a peer is only
+// blamed for what it signed.
+// Unicast payloads (VSS shares, MtA) are also encrypted on the pairwise channel, so the
+// messages can go through an untrusted relay.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdentityKey {
+    secret: FE,
+    public: GE,
+}
+
+impl IdentityKey {
+    pub fn new<R: CryptoRng + RngCore>(rng: &mut R) -> Self {
+        let secret = rng::random_fe(rng);
+        IdentityKey {
+            secret: secret,
+            public: GE::generator() * secret,
+        }
+    }
+
+    pub fn public_key(&self) -> GE {
+        self.public
+    }
+
+    pub fn party_id(&self) -> PartyId {
+        PartyId::from_public_key(&self.public)
+    }
+
+    // BIP340
+    pub fn sign<R: CryptoRng + RngCore>(&self, m: &[u8], rng: &mut R) -> Vec<u8> {
+        schnorr::sign(&self.secret, m, rng).to_bytes()
+    }
+}
+
+// broadcasts are signed for each recipient; the payload is the JSON of the inner message,
+// or the hex of it sealed with the ChannelKey when encrypted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub session: Vec<u8>,
+    pub round: String, // of the sender, once the message was out
+    pub from: usize,
+    pub to: usize,
+    pub encrypted: bool,
+    pub payload: String,
+    pub sig: Vec<u8>, // BIP340
+}
+
+fn signed_digest(
+    session: &[u8],
+    round: &str,
+    from: &GE,
+    to: &GE,
+    encrypted: bool,
+    payload: &str,
+) -> Vec<u8> {
+    let from = PartyId::from_public_key(from);
+    let to = PartyId::from_public_key(to);
+    let flag = [encrypted as u8];
+    let parts = [
+        session,
+        round.as_bytes(),
+        &from.0[..],
+        &to.0[..],
+        &flag[..],
+        payload.as_bytes(),
+    ];
+    let mut h = Sha256::new();
+    for part in parts.iter() {
+        h.input(&(part.len() as u64).to_be_bytes());
+        h.input(part);
+    }
+    h.result().to_vec()
+}
+
+// serializable with its party, e.g. to keep it between two relay syncs
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "H: Serialize + DeserializeOwned")]
+pub struct Authenticated<H: Handler> {
+    inner: H,
+    i: usize,
+    session: Vec<u8>,
+    key: IdentityKey,
+    peers: Vec<GE>, // identity keys by position, ours included
+    channels: Vec<ChannelKey>,
+}
+
+// the channel key is shared by both directions
+fn channel_aad(round: &str, from: usize, to: usize) -> Vec<u8> {
+    format!("{}/{}/{}", round, from, to).into_bytes()
+}
+
+impl<H: Handler> Authenticated<H> {
+    // session tells runs apart, so that a message cannot be replayed into another one
+    pub fn new(inner: H, i: usize, session: &[u8], key: IdentityKey, peers: &[GE]) -> Self {
+        let channels = peers
+            .iter()
+            .map(|pk| ChannelKey::derive(&key.secret, pk, session))
+            .collect();
+        Authenticated {
+            inner: inner,
+            i: i,
+            session: session.to_vec(),
+            key: key,
+            peers: peers.to_vec(),
+            channels: channels,
+        }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    fn seal<R: CryptoRng + RngCore>(
+        &self,
+        out: Vec<Outgoing<H::Message>>,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SignedMessage>>> {
+        let mut sealed = Vec::new();
+        for o in out {
+            let (to, msg, encrypted): (Vec<usize>, H::Message, bool) = match o {
+                Outgoing::Broadcast(msg) => (
+                    (0..self.peers.len()).filter(|j| *j != self.i).collect(),
+                    msg,
+                    false,
+                ),
+                Outgoing::To(j, msg) => (vec![j], msg, true),
+            };
+            let payload =
+                serde_json::to_string(&msg).map_err(|e| Error::Transport(e.to_string()))?;
+            for j in to {
+                let round = self.inner.round().to_string();
+                let payload = if encrypted {
+                    let aad = channel_aad(&round, self.i, j);
+                    to_hex(&self.channels[j].seal(&aad, payload.as_bytes(), rng))
+                } else {
+                    payload.clone()
+                };
+                let digest = signed_digest(
+                    &self.session,
+                    &round,
+                    &self.key.public,
+                    &self.peers[j],
+                    encrypted,
+                    &payload,
+                );
+                sealed.push(Outgoing::To(
+                    j,
+                    SignedMessage {
+                        session: self.session.clone(),
+                        round: round,
+                        from: self.i,
+                        to: j,
+                        encrypted: encrypted,
+                        payload: payload,
+                        sig: self.key.sign(&digest, rng),
+                    },
+                ));
+            }
+        }
+        Ok(sealed)
+    }
+
+    // None for a message that is not from `from` to us in this session
+    fn open(&self, from: usize, msg: SignedMessage) -> Result<Option<H::Message>> {
+        if from >= self.peers.len()
+            || msg.from != from
+            || msg.to != self.i
+            || msg.session != self.session
+        {
+            return Ok(None);
+        }
+        let digest = signed_digest(
+            &msg.session,
+            &msg.round,
+            &self.peers[from],
+            &self.key.public,
+            msg.encrypted,
+            &msg.payload,
+        );
+        if !schnorr::verify(&schnorr::xonly(&self.peers[from]), &digest, &msg.sig) {
+            return Ok(None);
+        }
+        if !msg.encrypted {
+            return serde_json::from_str(&msg.payload)
+                .map(Some)
+                .map_err(|e| Error::Transport(e.to_string()));
+        }
+        let aad = channel_aad(&msg.round, from, self.i);
+        let payload = from_hex(&msg.payload)
+            .and_then(|sealed| self.channels[from].open(&aad, &sealed))
+            .and_then(|pt| String::from_utf8(pt).ok())
+            .ok_or(Error::InvalidCiphertext { party: from })?;
+        serde_json::from_str(&payload)
+            .map(Some)
+            .map_err(|e| Error::Transport(e.to_string()))
+    }
+}
+
+impl<H: Handler> Handler for Authenticated<H> {
+    type Message = SignedMessage;
+    type Output = H::Output;
+
+    fn start<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SignedMessage>>> {
+        let out = self.inner.start(rng)?;
+        self.seal(out, rng)
+    }
+
+    fn handle<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: SignedMessage,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SignedMessage>>> {
+        let msg = match self.open(from, msg)? {
+            Some(msg) => msg,
+            None => return Ok(Vec::new()),
+        };
+        let out = self.inner.handle(from, msg, rng)?;
+        self.seal(out, rng)
+    }
+
+    fn output(&self) -> Option<H::Output> {
+        self.inner.output()
+    }
+
+    fn round(&self) -> &'static str {
+        self.inner.round()
+    }
+
+    fn missing(&self) -> Vec<usize> {
+        self.inner.missing()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::GE;
+    use crate::auth::*;
+    use crate::error::Error;
+    use crate::etude::{digest_message, Party};
+    use crate::keygen::{self, KeyGen, KeyGenMessage};
+    use crate::network::{Handler, Outgoing};
+    use crate::rng;
+    use crate::simulator::Simulator;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_authenticated() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+        let keys: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::new(&mut rng)).collect();
+        let pks: Vec<GE> = keys.iter().map(|k| k.public_key()).collect();
+        let parties = |session: &[u8]| -> Vec<Authenticated<Party>> {
+            (0..3)
+                .map(|i| {
+                    let mut p = Party::with_key_share(i, 3, &shares[i], &[1, 2, 3]);
+                    p.set_digest(digest_message(message));
+                    Authenticated::new(p, i, session, keys[i].clone(), &pks)
+                })
+                .collect()
+        };
+
+        let mut sim = Simulator::new(parties(b"session 1"));
+        for r in sim.run(&mut rng) {
+            assert!(r.unwrap().verify(&shares[0].y, &digest_message(message)));
+        }
+
+        // saved and loaded, with the channels
+        let saved: Vec<Authenticated<Party>> = parties(b"session 1")
+            .iter()
+            .map(|p| serde_json::from_str(&serde_json::to_string(p).unwrap()).unwrap())
+            .collect();
+        assert_eq!(saved[0].channels, parties(b"session 1")[0].channels);
+        for r in Simulator::new(saved).run(&mut rng) {
+            assert!(r.unwrap().verify(&shares[0].y, &digest_message(message)));
+        }
+
+        // a changed payload no longer carries the sender's signature, so it is dropped and
+        // the run waits for the real one
+        let mut sim = Simulator::new(parties(b"session 1"));
+        sim.set_tamper(
+            0,
+            Box::new(
+                |_: &mut Authenticated<Party>, out: Vec<Outgoing<SignedMessage>>| {
+                    out.into_iter()
+                        .map(|o| match o {
+                            Outgoing::To(j, mut m) => {
+                                m.payload = m.payload.replacen("1", "2", 1);
+                                Outgoing::To(j, m)
+                            }
+                            o => o,
+                        })
+                        .collect()
+                },
+            ),
+        );
+        let results = sim.run(&mut rng);
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&Error::Timeout {
+                round: "BroadcastingGr",
+                missing: vec![0],
+            })
+        );
+
+        // nor passes for another sender, recipient or session
+        let mut a = parties(b"session 1");
+        let out = a[0].start(&mut rng).unwrap();
+        let (to, msg) = match out.into_iter().next() {
+            Some(Outgoing::To(j, msg)) => (j, msg),
+            _ => panic!("expected a message to one peer"),
+        };
+        assert_eq!(to, 1);
+        assert!(a[1].open(2, msg.clone()).unwrap().is_none());
+        assert!(a[2].open(0, msg.clone()).unwrap().is_none());
+        assert!(a[1].open(5, msg.clone()).unwrap().is_none());
+        let b = parties(b"session 2");
+        assert!(b[1].open(0, msg.clone()).unwrap().is_none());
+        assert!(a[1].open(0, msg.clone()).unwrap().is_some());
+        assert!(a[1].handle(2, msg.clone(), &mut rng).unwrap().is_empty());
+        assert!(a[1].handle(0, msg, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn test_encrypted_channel() {
+        let mut rng = StdRng::seed_from_u64(39);
+        let keys: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::new(&mut rng)).collect();
+        let pks: Vec<GE> = keys.iter().map(|k| k.public_key()).collect();
+        let parties = || -> Vec<Authenticated<keygen::Party>> {
+            (0..3)
+                .map(|i| {
+                    Authenticated::new(
+                        keygen::Party::new(i, 1, 3),
+                        i,
+                        b"keygen",
+                        keys[i].clone(),
+                        &pks,
+                    )
+                })
+                .collect()
+        };
+        let shares = Simulator::new(parties()).run(&mut rng);
+        for r in shares.iter() {
+            assert_eq!(r.as_ref().unwrap().y, shares[0].as_ref().unwrap().y);
+        }
+
+        // a share is only readable by its recipient
+        let a = parties();
+        let x = rng::random_fe(&mut rng);
+        let out = a[0]
+            .seal(vec![Outgoing::To(1, KeyGenMessage::Share(x))], &mut rng)
+            .unwrap();
+        let mut msg = match out.into_iter().next() {
+            Some(Outgoing::To(1, msg)) => msg,
+            _ => panic!("expected a message to party 1"),
+        };
+        assert!(msg.encrypted);
+        assert!(!msg.payload.contains(&serde_json::to_string(&x).unwrap()));
+        match a[1].open(0, msg.clone()) {
+            Ok(Some(KeyGenMessage::Share(y))) => assert_eq!(x, y),
+            _ => panic!("expected the share"),
+        }
+
+        // a signed but corrupted ciphertext blames its sender
+        let last = if msg.payload.ends_with('0') { "1" } else { "0" };
+        msg.payload = format!("{}{}", &msg.payload[..msg.payload.len() - 1], last);
+        let digest = signed_digest(
+            &msg.session,
+            &msg.round,
+            &pks[0],
+            &pks[1],
+            true,
+            &msg.payload,
+        );
+        msg.sig = schnorr::sign(&keys[0].secret, &digest, &mut rng).to_bytes();
+        assert_eq!(
+            a[1].open(0, msg).err(),
+            Some(Error::InvalidCiphertext { party: 0 })
+        );
+    }
+}
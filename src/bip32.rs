@@ -0,0 +1,194 @@
+extern crate curv;
+extern crate hmac;
+extern crate sha2;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use self::curv::{BigInt, FE, GE};
+use self::hmac::{Hmac, Mac};
+use self::sha2::Sha512;
+
+use crate::curve::{compress, to_bytes32};
+use crate::error::{Error, Result};
+use crate::etude::{fe_from_bigint, fe_to_bigint};
+
+pub const HARDENED: u32 = 0x8000_0000;
+
+// serP: compressed SEC1 encoding
+fn ser_p(p: &GE) -> Vec<u8> {
+    compress(p)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtendedPublicKey {
+    pub public_key: GE,
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedPublicKey {
+    pub fn new(public_key: GE, chain_code: [u8; 32]) -> Self {
+        Self {
+            public_key: public_key,
+            chain_code: chain_code,
+        }
+    }
+
+    // There is no seed behind a threshold key, so the root chain code is bound to y itself:
+    // SHA-256 of serP(y), a choice of this repo. Wallets derive from the chain code of their
+    // own xpub, so they only agree with it when that chain code is given explicitly.
+    pub fn from_public_key(public_key: GE) -> Self {
+        let cc = HSha256::create_hash(&vec![&BigInt::from(&ser_p(&public_key)[..])]);
+        Self::new(public_key, to_bytes32(&cc))
+    }
+
+    // CKDpub. Returns the child key and the tweak IL such that child = parent + g^IL.
+    pub fn derive_child(&self, index: u32) -> Option<(ExtendedPublicKey, FE)> {
+        if index >= HARDENED {
+            return None;
+        }
+        let mut mac = Hmac::<Sha512>::new_varkey(&self.chain_code).unwrap();
+        mac.input(&ser_p(&self.public_key));
+        mac.input(&index.to_be_bytes());
+        let i = mac.result().code();
+
+        let il = BigInt::from(&i[..32]);
+        if il >= FE::q() {
+            return None;
+        }
+        let tweak = fe_from_bigint(&il);
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+        let child = self.public_key + GE::generator() * tweak;
+        Some((ExtendedPublicKey::new(child, chain_code), tweak))
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Option<(ExtendedPublicKey, FE)> {
+        let mut xpub = self.clone();
+        let mut tweak = BigInt::from(0);
+        for index in path {
+            let (child, t) = xpub.derive_child(*index)?;
+            tweak = (tweak + fe_to_bigint(&t)).mod_floor(&FE::q());
+            xpub = child;
+        }
+        Some((xpub, fe_from_bigint(&tweak)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Derivation {
+    // None uses ExtendedPublicKey::from_public_key, which matches no standard wallet
+    pub chain_code: Option<[u8; 32]>,
+    pub path: Vec<u32>,
+}
+
+impl Derivation {
+    pub fn new(path: Vec<u32>) -> Self {
+        Self {
+            chain_code: None,
+            path: path,
+        }
+    }
+
+    // hardened indexes never work; with y known, also an IL >= q somewhere on the path
+    pub fn check(&self, y: Option<&GE>) -> Result<()> {
+        if self.path.iter().any(|index| *index >= HARDENED) {
+            return Err(Error::InvalidDerivation);
+        }
+        match y {
+            Some(y) => self.derive(y).map(|_| ()).ok_or(Error::InvalidDerivation),
+            None => Ok(()),
+        }
+    }
+
+    pub fn derive(&self, y: &GE) -> Option<(ExtendedPublicKey, FE)> {
+        let root = match self.chain_code {
+            Some(cc) => ExtendedPublicKey::new(y.clone(), cc),
+            None => ExtendedPublicKey::from_public_key(y.clone()),
+        };
+        root.derive_path(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use self::curv::{FE, GE};
+    use crate::bip32::*;
+    use crate::etude::Etude;
+
+    #[test]
+    fn test_derive_path() {
+        let x: FE = FE::new_random();
+        let root = ExtendedPublicKey::from_public_key(GE::generator() * x);
+        let (child, tweak) = root.derive_path(&[1, 2, 3]).unwrap();
+        assert_eq!(GE::generator() * (x + tweak), child.public_key);
+
+        let (c1, _) = root.derive_child(1).unwrap();
+        let (c12, _) = c1.derive_child(2).unwrap();
+        let (c123, _) = c12.derive_child(3).unwrap();
+        assert_eq!(child, c123);
+
+        assert_eq!(None, root.derive_child(HARDENED));
+    }
+
+    fn point(hex: &str) -> GE {
+        ::curve::decompress(&::eth::from_hex(hex).unwrap()).unwrap()
+    }
+
+    // BIP32 test vector 1, the non-hardened steps from the published xpubs
+    #[test]
+    fn test_bip32_vector() {
+        let m0h = ExtendedPublicKey::new(
+            point("035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56"),
+            ::eth::hex32("47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141"),
+        );
+        let (m0h1, _) = m0h.derive_child(1).unwrap();
+        assert_eq!(
+            ::eth::to_hex(&ser_p(&m0h1.public_key)),
+            "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c"
+        );
+        assert_eq!(
+            m0h1.chain_code,
+            ::eth::hex32("2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19")
+        );
+
+        let m0h12h = ExtendedPublicKey::new(
+            point("0357bfe1e341d01c69fe5654309956cbea516822fba8a601743a012a7896ee8dc2"),
+            ::eth::hex32("04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f"),
+        );
+        let derivation = Derivation {
+            chain_code: Some(m0h12h.chain_code),
+            path: vec![2, 1000000000],
+        };
+        let (child, _) = derivation.derive(&m0h12h.public_key).unwrap();
+        assert_eq!(
+            ::eth::to_hex(&ser_p(&child.public_key)),
+            "022a471424da5e657499d1ff51cb43c47481a03b1e77f951fe64cec9f5a48f7011"
+        );
+        assert_eq!(
+            child.chain_code,
+            ::eth::hex32("c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e")
+        );
+    }
+
+    #[test]
+    fn test_sign_with_derivation() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut gg18 = Etude::new(3);
+        gg18.set_derivation(Derivation::new(vec![0, 7])).unwrap();
+        gg18.sign(&message);
+        let sig = gg18.signature().unwrap();
+        let y = gg18.public_key().unwrap();
+        let root = gg18.root_public_key().unwrap();
+        let (child, _) = Derivation::new(vec![0, 7]).derive(&root).unwrap();
+        assert_eq!(child.public_key, y);
+        assert!(sig.verify(&y, &::etude::digest_message(&message)));
+
+        let mut gg18 = Etude::new(3);
+        assert_eq!(
+            gg18.set_derivation(Derivation::new(vec![0, HARDENED])),
+            Err(Error::InvalidDerivation)
+        );
+    }
+}
@@ -0,0 +1,122 @@
+extern crate chacha20poly1305;
+extern crate curv;
+extern crate hkdf;
+extern crate sha2;
+use self::chacha20poly1305::aead::generic_array::GenericArray;
+use self::chacha20poly1305::aead::{Aead, NewAead, Payload};
+use self::chacha20poly1305::ChaCha20Poly1305;
+use self::curv::elliptic::curves::traits::ECPoint;
+use self::curv::{FE, GE};
+use self::hkdf::Hkdf;
+use self::sha2::Sha256;
+use rand::{CryptoRng, RngCore};
+
+use crate::curve::compress;
+use crate::schnorr::xonly;
+
+// Pairwise channels between identity keys: ECDH, then ChaCha20-Poly1305 under a key per
+// direction, which HKDF-SHA256 derives from the shared x with the session as salt and the
+// sender and recipient as info. Sealed is nonce || ciphertext || tag.
+
+const NONCE_LEN: usize = 12;
+
+// the key from `from` to `to`
+fn direction_key(shared: &[u8], session: &[u8], from: &GE, to: &GE) -> [u8; 32] {
+    let mut info = b"gg18-etude channel".to_vec();
+    info.extend(compress(from));
+    info.extend(compress(to));
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(session), shared)
+        .expand(&info, &mut key)
+        .unwrap();
+    key
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelKey {
+    send: [u8; 32],
+    recv: [u8; 32],
+}
+
+impl ChannelKey {
+    // one end's send key is the other end's receive key
+    pub fn derive(secret: &FE, peer: &GE, session: &[u8]) -> Self {
+        let me = GE::generator() * *secret;
+        let shared = xonly(&(*peer * *secret));
+        ChannelKey {
+            send: direction_key(&shared, session, &me, peer),
+            recv: direction_key(&shared, session, peer, &me),
+        }
+    }
+
+    // aad is authenticated but not encrypted
+    pub fn seal<R: CryptoRng + RngCore>(
+        &self,
+        aad: &[u8],
+        plaintext: &[u8],
+        rng: &mut R,
+    ) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&self.send));
+        let payload = Payload {
+            msg: plaintext,
+            aad: aad,
+        };
+        let ct = cipher
+            .encrypt(GenericArray::from_slice(&nonce), payload)
+            .unwrap();
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ct);
+        sealed
+    }
+
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ct) = sealed.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&self.recv));
+        let payload = Payload { msg: ct, aad: aad };
+        cipher
+            .decrypt(GenericArray::from_slice(nonce), payload)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::GE;
+    use crate::channel::*;
+    use crate::rng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_channel() {
+        let mut rng = StdRng::seed_from_u64(39);
+        let a = rng::random_fe(&mut rng);
+        let b = rng::random_fe(&mut rng);
+        let ka = ChannelKey::derive(&a, &(GE::generator() * b), b"session 1");
+        let kb = ChannelKey::derive(&b, &(GE::generator() * a), b"session 1");
+        assert_eq!((ka.send, ka.recv), (kb.recv, kb.send));
+        assert!(ka.send != ka.recv);
+        assert!(ka != ChannelKey::derive(&a, &(GE::generator() * b), b"session 2"));
+
+        let share = "a Feldman share, a little longer than one 32 byte block".as_bytes();
+        let sealed = ka.seal(b"round 2", share, &mut rng);
+        assert_eq!(sealed.len(), 12 + share.len() + 16);
+        assert!(sealed.windows(share.len()).all(|w| w != share));
+        assert_eq!(kb.open(b"round 2", &sealed), Some(share.to_vec()));
+        assert_eq!(kb.open(b"round 3", &sealed), None);
+        // not back to its sender
+        assert_eq!(ka.open(b"round 2", &sealed), None);
+        let mut flipped = sealed.clone();
+        flipped[20] ^= 1;
+        assert_eq!(kb.open(b"round 2", &flipped), None);
+        assert_eq!(kb.open(b"round 2", &sealed[..40]), None);
+        assert_eq!(kb.open(b"round 2", &sealed[..8]), None);
+    }
+}
@@ -0,0 +1,388 @@
+extern crate curv;
+use self::curv::arithmetic::traits::Converter;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use self::curv::{BigInt, FE, GE, SK};
+extern crate p256;
+use self::p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use self::p256::elliptic_curve::PrimeField;
+use self::p256::EncodedPoint;
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer};
+use serde::{Serialize, Serializer};
+use std::fmt::Debug;
+use std::ops::{Add, Mul};
+
+// The group the protocol runs in. Keygen, VSS, MtA and the typestate rounds are written
+// against these traits; secp256k1 uses curv's types, P-256 those of the p256 crate.
+// Everything else (etude::Party, schnorr, two_party, bip32, eth, auth and the CLI) is
+// secp256k1 only.
+
+pub trait ScalarOps:
+    Copy
+    + Debug
+    + PartialEq
+    + Send
+    + Sync
+    + Serialize
+    + DeserializeOwned
+    + Add<Output = Self>
+    + Mul<Output = Self>
+{
+    fn order() -> BigInt;
+    // reduced mod the order
+    fn from_bigint(x: &BigInt) -> Self;
+    fn to_bigint(&self) -> BigInt;
+    fn inverse(&self) -> Self;
+}
+
+pub trait PointOps<S: ScalarOps>:
+    Copy
+    + Debug
+    + PartialEq
+    + Send
+    + Sync
+    + Serialize
+    + DeserializeOwned
+    + Add<Output = Self>
+    + Mul<S, Output = Self>
+{
+    fn base_point() -> Self;
+    // None for the identity
+    fn affine_x(&self) -> Option<BigInt>;
+    fn affine_y(&self) -> Option<BigInt>;
+}
+
+pub trait Curve: Clone + Copy + Debug + PartialEq + Send + Sync + 'static {
+    type Scalar: ScalarOps;
+    type Point: PointOps<Self::Scalar>;
+    const NAME: &'static str;
+}
+
+// SHA-256 of the message, reduced mod q
+pub fn hash_to_scalar<S: ScalarOps>(message: &[u8]) -> S {
+    S::from_bigint(&HSha256::create_hash(&vec![&BigInt::from(message)]))
+}
+
+// x mod q, as used for r in ECDSA; None for the identity
+pub fn x_scalar<S: ScalarOps, P: PointOps<S>>(p: &P) -> Option<S> {
+    p.affine_x().map(|x| S::from_bigint(&x))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Secp256k1;
+
+impl Curve for Secp256k1 {
+    type Scalar = FE;
+    type Point = GE;
+    const NAME: &'static str = "secp256k1";
+}
+
+impl ScalarOps for FE {
+    fn order() -> BigInt {
+        FE::q()
+    }
+    fn from_bigint(x: &BigInt) -> Self {
+        let x = x.mod_floor(&FE::q());
+        // not a valid secret key, so curv has a separate constructor for it
+        if x == BigInt::from(0) {
+            return FE::zero();
+        }
+        <FE as ECScalar<SK>>::from(&x)
+    }
+    fn to_bigint(&self) -> BigInt {
+        self.to_big_int()
+    }
+    fn inverse(&self) -> Self {
+        self.invert()
+    }
+}
+
+impl PointOps<FE> for GE {
+    fn base_point() -> Self {
+        GE::generator()
+    }
+    fn affine_x(&self) -> Option<BigInt> {
+        self.x_coor()
+    }
+    fn affine_y(&self) -> Option<BigInt> {
+        self.y_coor()
+    }
+}
+
+// big-endian, left padded; x must be below 2^256
+pub fn to_bytes32(x: &BigInt) -> [u8; 32] {
+    let v = Vec::<u8>::from(x);
+    let mut r = [0u8; 32];
+    r[32 - v.len()..].copy_from_slice(&v);
+    r
+}
+
+pub const SECP256K1_P: &str = "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+
+// the secp256k1 point with this x and parity of y, if x is on the curve
+pub fn lift_x(x: &BigInt, odd: bool) -> Option<GE> {
+    let p = BigInt::from_hex(SECP256K1_P);
+    if *x >= p {
+        return None;
+    }
+    let c = (x * x * x + BigInt::from(7)).mod_floor(&p);
+    let y = c.powm(&((&p + BigInt::from(1)) / BigInt::from(4)), &p);
+    if (&y * &y).mod_floor(&p) != c {
+        return None;
+    }
+    let y = if (y.mod_floor(&BigInt::from(2)) == BigInt::from(1)) == odd {
+        y
+    } else {
+        &p - &y
+    };
+    Some(GE::from_coor(x, &y))
+}
+
+// compressed SEC1
+pub fn compress(p: &GE) -> Vec<u8> {
+    let odd = p.y_coor().unwrap().mod_floor(&BigInt::from(2)) == BigInt::from(1);
+    let mut v = vec![if odd { 0x03 } else { 0x02 }];
+    v.extend_from_slice(&to_bytes32(&p.x_coor().unwrap()));
+    v
+}
+
+pub fn decompress(bytes: &[u8]) -> Option<GE> {
+    if bytes.len() != 33 || (bytes[0] != 0x02 && bytes[0] != 0x03) {
+        return None;
+    }
+    lift_x(&BigInt::from(&bytes[1..]), bytes[0] == 0x03)
+}
+
+// NIST P-256 from the p256 crate, whose scalar multiplication is constant time. Only what
+// the traits need is wrapped; serialized as big-endian bytes like before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct P256;
+
+impl Curve for P256 {
+    type Scalar = P256Scalar;
+    type Point = P256Point;
+    const NAME: &'static str = "P-256";
+}
+
+fn from_bytes32(b: &[u8]) -> BigInt {
+    BigInt::from(b)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct P256Scalar(p256::Scalar);
+
+impl P256Scalar {
+    // None unless below n
+    fn from_bytes(b: &[u8; 32]) -> Option<Self> {
+        Option::from(p256::Scalar::from_repr((*b).into())).map(P256Scalar)
+    }
+}
+
+impl ScalarOps for P256Scalar {
+    fn order() -> BigInt {
+        BigInt::from_hex(P256_N)
+    }
+    fn from_bigint(x: &BigInt) -> Self {
+        Self::from_bytes(&to_bytes32(&x.mod_floor(&Self::order()))).unwrap()
+    }
+    fn to_bigint(&self) -> BigInt {
+        from_bytes32(&self.0.to_repr())
+    }
+    fn inverse(&self) -> Self {
+        // zero has no inverse and stays zero
+        P256Scalar(Option::from(self.0.invert()).unwrap_or(self.0))
+    }
+}
+
+impl Add for P256Scalar {
+    type Output = P256Scalar;
+    fn add(self, other: P256Scalar) -> P256Scalar {
+        P256Scalar(self.0 + other.0)
+    }
+}
+
+impl Mul for P256Scalar {
+    type Output = P256Scalar;
+    fn mul(self, other: P256Scalar) -> P256Scalar {
+        P256Scalar(self.0 * other.0)
+    }
+}
+
+impl Serialize for P256Scalar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut b = [0u8; 32];
+        b.copy_from_slice(&self.0.to_repr());
+        b.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for P256Scalar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let b: [u8; 32] = Deserialize::deserialize(deserializer)?;
+        Self::from_bytes(&b).ok_or_else(|| de::Error::custom("not below the P-256 order"))
+    }
+}
+
+const P256_N: &str = "ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct P256Point(p256::ProjectivePoint);
+
+impl P256Point {
+    pub fn from_coor(x: &BigInt, y: &BigInt) -> Option<Self> {
+        let zero = BigInt::from(0);
+        if x < &zero || y < &zero || x.bit_length() > 256 || y.bit_length() > 256 {
+            return None;
+        }
+        let (x, y) = (to_bytes32(x), to_bytes32(y));
+        let ep = EncodedPoint::from_affine_coordinates(&x.into(), &y.into(), false);
+        Option::<p256::AffinePoint>::from(p256::AffinePoint::from_encoded_point(&ep))
+            .map(|a| P256Point(a.into()))
+    }
+
+    // affine coordinates, None for the identity
+    fn coor(&self) -> Option<([u8; 32], [u8; 32])> {
+        let ep = self.0.to_affine().to_encoded_point(false);
+        match (ep.x(), ep.y()) {
+            (Some(x), Some(y)) => {
+                let (mut bx, mut by) = ([0u8; 32], [0u8; 32]);
+                bx.copy_from_slice(x);
+                by.copy_from_slice(y);
+                Some((bx, by))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for P256Point {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.coor().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for P256Point {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let c: Option<([u8; 32], [u8; 32])> = Deserialize::deserialize(deserializer)?;
+        match c {
+            // like a secp256k1 point, never the identity
+            None => Err(de::Error::custom("the identity on P-256")),
+            Some((x, y)) => P256Point::from_coor(&from_bytes32(&x), &from_bytes32(&y))
+                .ok_or_else(|| de::Error::custom("not a point on P-256")),
+        }
+    }
+}
+
+impl Add for P256Point {
+    type Output = P256Point;
+    fn add(self, other: P256Point) -> P256Point {
+        P256Point(self.0 + other.0)
+    }
+}
+
+impl Mul<P256Scalar> for P256Point {
+    type Output = P256Point;
+    fn mul(self, k: P256Scalar) -> P256Point {
+        P256Point(self.0 * k.0)
+    }
+}
+
+impl PointOps<P256Scalar> for P256Point {
+    fn base_point() -> Self {
+        P256Point(p256::ProjectivePoint::GENERATOR)
+    }
+    fn affine_x(&self) -> Option<BigInt> {
+        self.coor().map(|c| from_bytes32(&c.0))
+    }
+    fn affine_y(&self) -> Option<BigInt> {
+        self.coor().map(|c| from_bytes32(&c.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::arithmetic::traits::Converter;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::{BigInt, GE};
+    use crate::curve::*;
+
+    #[test]
+    fn test_p256() {
+        let g = P256Point::base_point();
+        let gx = g.affine_x().unwrap();
+        let gy = g.affine_y().unwrap();
+        assert_eq!(Some(g), P256Point::from_coor(&gx, &gy));
+        assert_eq!(None, P256Point::from_coor(&gx, &(gy + BigInt::from(1))));
+        let json = ::serde_json::to_string(&g).unwrap();
+        assert_eq!(g, ::serde_json::from_str(&json).unwrap());
+
+        // 2G from the published test vectors
+        let two = P256Scalar::from_bigint(&BigInt::from(2));
+        assert_eq!(
+            (g * two).affine_x().unwrap(),
+            BigInt::from_hex("7cf27b188d034f7e8a52380304b51ac3c08969e277f21b35a60b48fc47669978")
+        );
+        assert_eq!(g + g, g * two);
+
+        // nG is the identity and (n-1)G is -G
+        let n = P256Scalar::order();
+        let minus_one = P256Scalar::from_bigint(&(&n - BigInt::from(1)));
+        let identity = g * minus_one + g;
+        assert_eq!(None, identity.affine_x());
+        let json = ::serde_json::to_string(&identity).unwrap();
+        assert!(::serde_json::from_str::<P256Point>(&json).is_err());
+        let a = P256Scalar::from_bigint(&BigInt::from(12345));
+        let b = P256Scalar::from_bigint(&BigInt::from(67890));
+        assert_eq!(g * (a * b), (g * a) * b);
+        assert_eq!(g * (a + b), g * a + g * b);
+        assert_eq!(a * a.inverse(), P256Scalar::from_bigint(&BigInt::from(1)));
+    }
+
+    // RFC 6979 A.2.5, SHA-256 and "sample"
+    #[test]
+    fn test_p256_ecdsa_vector() {
+        let h = |s: &str| P256Scalar::from_bigint(&BigInt::from_hex(s));
+        let x = h("c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721");
+        let k = h("a6e3c57dd01abe90086538398355dd4c3b17aa873382b0f24d6129493d8aad60");
+        let y = P256Point::from_coor(
+            &BigInt::from_hex("60fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb6"),
+            &BigInt::from_hex("7903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299"),
+        )
+        .unwrap();
+        assert_eq!(y, P256Point::base_point() * x);
+
+        let m = hash_to_scalar::<P256Scalar>("sample".as_bytes());
+        // GG18's nonce is the inverse of the usual ECDSA one
+        let ki = k.inverse();
+        let sign_r = P256Point::base_point() * k;
+        let si = ::etude::local_si(&m, &ki, &sign_r, &(ki * x));
+        let sig = ::etude::Signature::new(&sign_r, &si);
+        assert_eq!(
+            sig.r,
+            h("efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716")
+        );
+        // low-s of f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8
+        let s =
+            BigInt::from_hex("f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8");
+        assert_eq!(sig.s, P256Scalar::from_bigint(&(P256Scalar::order() - s)));
+        assert!(sig.verify(&y, &m));
+    }
+
+    #[test]
+    fn test_decompress() {
+        let g = GE::generator();
+        let mut bytes = compress(&g);
+        assert_eq!(0x02, bytes[0]);
+        assert_eq!(Some(g), decompress(&bytes));
+        bytes[0] = 0x03;
+        let minus_g = decompress(&bytes).unwrap();
+        assert_eq!(minus_g.x_coor(), g.x_coor());
+        assert!(minus_g != g);
+        bytes[0] = 0x04;
+        assert_eq!(None, decompress(&bytes));
+        // x = 5 is not on secp256k1
+        assert_eq!(None, lift_x(&BigInt::from(5), false));
+    }
+}
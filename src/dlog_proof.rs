@@ -0,0 +1,143 @@
+extern crate curv;
+extern crate sha2;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::BigInt;
+use self::sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+
+use crate::curve::{Curve, PointOps, ScalarOps, Secp256k1};
+use crate::rng;
+
+// Fiat-Shamir Schnorr proof of knowledge of x such that pk = g^x.
+// salt binds the proof to its prover (e.g. the party index) to prevent copying.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct DLogProof<C: Curve = Secp256k1> {
+    pub a: C::Point,
+    pub z: C::Scalar,
+}
+
+// the salt of a proof that belongs to one signing run: the session id, the signers'
+// evaluation points and the prover's position, so it cannot be replayed into another run
+pub fn session_salt(session: &[u8], points: &[usize], i: usize) -> Vec<u8> {
+    let mut h = Sha256::new();
+    h.input(&(session.len() as u64).to_be_bytes());
+    h.input(session);
+    h.input(&(points.len() as u64).to_be_bytes());
+    for x in points.iter() {
+        h.input(&(*x as u64).to_be_bytes());
+    }
+    h.input(&(i as u64).to_be_bytes());
+    h.result().to_vec()
+}
+
+fn challenge<C: Curve>(points: &[C::Point], salt: &[u8]) -> C::Scalar {
+    let mut input = vec![BigInt::from(C::NAME.as_bytes())];
+    for p in points.iter() {
+        input.push(p.affine_x().unwrap_or_else(|| BigInt::from(0)));
+        input.push(p.affine_y().unwrap_or_else(|| BigInt::from(0)));
+    }
+    input.push(BigInt::from(salt));
+    let e = HSha256::create_hash(&input.iter().collect::<Vec<&BigInt>>());
+    C::Scalar::from_bigint(&e)
+}
+
+impl<C: Curve> DLogProof<C> {
+    pub fn prove<R: CryptoRng + RngCore>(x: &C::Scalar, salt: &[u8], rng: &mut R) -> Self {
+        let g = C::Point::base_point();
+        let r: C::Scalar = rng::random_scalar(rng);
+        let a = g * r;
+        let e = challenge::<C>(&[g, g * *x, a], salt);
+        Self {
+            a: a,
+            z: r + e * *x,
+        }
+    }
+
+    // the identity is refused: its discrete log is known to everyone
+    pub fn verify(&self, pk: &C::Point, salt: &[u8]) -> bool {
+        if pk.affine_x().is_none() {
+            return false;
+        }
+        let g = C::Point::base_point();
+        let e = challenge::<C>(&[g, *pk, self.a], salt);
+        g * self.z == self.a + *pk * e
+    }
+}
+
+// Chaum-Pedersen proof that pk = g^x and q = h^x for the same x
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct DLogEqProof<C: Curve = Secp256k1> {
+    pub a1: C::Point,
+    pub a2: C::Point,
+    pub z: C::Scalar,
+}
+
+impl<C: Curve> DLogEqProof<C> {
+    pub fn prove<R: CryptoRng + RngCore>(
+        x: &C::Scalar,
+        h: &C::Point,
+        salt: &[u8],
+        rng: &mut R,
+    ) -> Self {
+        let g = C::Point::base_point();
+        let r: C::Scalar = rng::random_scalar(rng);
+        let (a1, a2) = (g * r, *h * r);
+        let e = challenge::<C>(&[g, g * *x, *h, *h * *x, a1, a2], salt);
+        Self {
+            a1: a1,
+            a2: a2,
+            z: r + e * *x,
+        }
+    }
+
+    pub fn verify(&self, pk: &C::Point, h: &C::Point, q: &C::Point, salt: &[u8]) -> bool {
+        let g = C::Point::base_point();
+        let e = challenge::<C>(&[g, *pk, *h, *q, self.a1, self.a2], salt);
+        g * self.z == self.a1 + *pk * e && *h * self.z == self.a2 + *q * e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use self::curv::{FE, GE};
+    use crate::curve::{P256Point, P256Scalar, Secp256k1, P256};
+    use crate::dlog_proof::*;
+
+    #[test]
+    fn test_dlog_proof() {
+        let x: FE = FE::new_random();
+        let pk = GE::generator() * x;
+        let proof = DLogProof::<Secp256k1>::prove(&x, b"0", &mut ::rand::thread_rng());
+        assert!(proof.verify(&pk, b"0"));
+        assert!(!proof.verify(&pk, b"1"));
+        assert!(!proof.verify(&(pk + GE::generator()), b"0"));
+        // a proof for x = 0 says nothing
+        let zero = P256Scalar::from_bigint(&BigInt::from(0));
+        let proof = DLogProof::<P256>::prove(&zero, b"0", &mut ::rand::thread_rng());
+        assert!(!proof.verify(&(P256Point::base_point() * zero), b"0"));
+
+        // not valid in another session, for another signer set or position
+        let salt = session_salt(b"session 1", &[1, 3], 0);
+        let proof = DLogProof::<Secp256k1>::prove(&x, &salt, &mut ::rand::thread_rng());
+        assert!(proof.verify(&pk, &salt));
+        assert!(!proof.verify(&pk, &session_salt(b"session 2", &[1, 3], 0)));
+        assert!(!proof.verify(&pk, &session_salt(b"session 1", &[1, 2], 0)));
+        assert!(!proof.verify(&pk, &session_salt(b"session 1", &[1, 3], 1)));
+    }
+
+    #[test]
+    fn test_dlog_eq_proof() {
+        let x: FE = FE::new_random();
+        let h = GE::generator() * FE::new_random();
+        let (pk, q) = (GE::generator() * x, h * x);
+        let proof = DLogEqProof::<Secp256k1>::prove(&x, &h, b"0", &mut ::rand::thread_rng());
+        assert!(proof.verify(&pk, &h, &q, b"0"));
+        assert!(!proof.verify(&pk, &h, &q, b"1"));
+        assert!(!proof.verify(&pk, &h, &(q + h), b"0"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    InvalidState,
+    NotEnoughShares,
+    InvalidCommitment { party: usize },
+    InvalidShare { party: usize },
+    InvalidPublicKey,
+    InvalidPaillierKey { party: usize },
+    InvalidAuxParams { party: usize },
+    InvalidRangeProof { party: usize },
+    InvalidDLogProof { party: usize },
+    InconsistentBroadcast { party: usize },
+    InconsistentEcho { party: usize, echoer: usize },
+    InvalidDelta { party: usize },
+    InvalidReveal { party: usize },
+    InconsistentMta { party: usize, peer: usize },
+    InvalidMta { party: usize },
+    InvalidPartialSignature { party: usize },
+    AbortedKey,
+    InvalidSignature,
+    InvalidDerivation,
+    InvalidMessageSignature { party: usize },
+    InvalidCiphertext { party: usize },
+    UnknownParty,
+    DuplicateParty,
+    TooManyMessages { party: usize },
+    Unauthenticated,
+    WrongSession,
+    StaleBundle { party: usize },
+    MissingBundle { party: usize },
+    StaleState,
+    MissingJournal,
+    MailboxFull,
+    Timeout { round: &'static str, missing: Vec<usize> },
+    Transport(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidState => write!(f, "invalid state"),
+            Error::NotEnoughShares => write!(f, "not enough shares"),
+            Error::InvalidCommitment { party } => write!(f, "invalid commitment from {}", party),
+            Error::InvalidShare { party } => write!(f, "invalid share from {}", party),
+            Error::InvalidPublicKey => write!(f, "invalid public key"),
+            Error::InvalidPaillierKey { party } => {
+                write!(f, "invalid paillier key from {}", party)
+            }
+            Error::InvalidAuxParams { party } => write!(f, "invalid aux params from {}", party),
+            Error::InvalidRangeProof { party } => write!(f, "invalid range proof from {}", party),
+            Error::InvalidDLogProof { party } => write!(f, "invalid dlog proof from {}", party),
+            Error::InconsistentBroadcast { party } => {
+                write!(f, "inconsistent broadcast from {}", party)
+            }
+            Error::InconsistentEcho { party, echoer } => {
+                write!(f, "broadcast from {} echoed differently by {}", party, echoer)
+            }
+            Error::InvalidDelta { party } => write!(f, "invalid delta from {}", party),
+            Error::InvalidReveal { party } => write!(f, "invalid reveal from {}", party),
+            Error::InconsistentMta { party, peer } => {
+                write!(f, "inconsistent mta between {} and {}", party, peer)
+            }
+            Error::InvalidMta { party } => write!(f, "invalid mta from {}", party),
+            Error::InvalidPartialSignature { party } => {
+                write!(f, "invalid partial signature from {}", party)
+            }
+            Error::AbortedKey => write!(f, "key aborted after a bad partial signature"),
+            Error::InvalidSignature => write!(f, "invalid signature"),
+            Error::InvalidDerivation => write!(f, "invalid derivation"),
+            Error::InvalidMessageSignature { party } => {
+                write!(f, "invalid message signature from {}", party)
+            }
+            Error::InvalidCiphertext { party } => write!(f, "invalid ciphertext from {}", party),
+            Error::UnknownParty => write!(f, "unknown party"),
+            Error::DuplicateParty => write!(f, "duplicate party"),
+            Error::TooManyMessages { party } => write!(f, "too many messages from {}", party),
+            Error::Unauthenticated => write!(f, "unauthenticated request"),
+            Error::WrongSession => write!(f, "wrong session"),
+            Error::StaleBundle { party } => write!(f, "bundle from {} already imported", party),
+            Error::MissingBundle { party } => write!(f, "earlier bundle from {} missing", party),
+            Error::StaleState => write!(f, "state older than its journal"),
+            Error::MissingJournal => write!(f, "journal of the state missing"),
+            Error::MailboxFull => write!(f, "mailbox full"),
+            Error::Timeout { round, missing } => {
+                write!(f, "timeout in {} waiting for {:?}", round, missing)
+            }
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
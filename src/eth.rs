@@ -0,0 +1,466 @@
+extern crate curv;
+extern crate tiny_keccak;
+use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use self::curv::{BigInt, FE, GE};
+
+use crate::curve::{lift_x, to_bytes32};
+use crate::etude::{fe_from_bigint, fe_to_bigint, Etude, Signature};
+
+pub type Address = [u8; 20];
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    tiny_keccak::keccak256(data)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|k| u8::from_str_radix(s.get(k..k + 2)?, 16).ok())
+        .collect()
+}
+
+// test vectors of 32 bytes
+#[cfg(test)]
+pub(crate) fn hex32(s: &str) -> [u8; 32] {
+    let mut r = [0u8; 32];
+    r.copy_from_slice(&from_hex(s).unwrap());
+    r
+}
+
+pub fn address(y: &GE) -> Address {
+    let pk = y.pk_to_key_slice(); // 0x04 || x || y
+    let h = keccak256(&pk[1..]);
+    let mut a = [0u8; 20];
+    a.copy_from_slice(&h[12..]);
+    a
+}
+
+fn hash_to_fe(hash: &[u8; 32]) -> FE {
+    fe_from_bigint(&BigInt::from(&hash[..]).mod_floor(&FE::q()))
+}
+
+fn bigint_to_bytes(x: &BigInt) -> Vec<u8> {
+    if *x == BigInt::from(0) {
+        Vec::new()
+    } else {
+        Vec::<u8>::from(x)
+    }
+}
+
+fn u64_to_bytes(x: u64) -> Vec<u8> {
+    x.to_be_bytes()
+        .iter()
+        .cloned()
+        .skip_while(|b| *b == 0)
+        .collect()
+}
+
+fn rlp_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let l = u64_to_bytes(len as u64);
+        let mut v = vec![offset + 55 + l.len() as u8];
+        v.extend(l);
+        v
+    }
+}
+
+pub fn rlp_bytes(b: &[u8]) -> Vec<u8> {
+    if b.len() == 1 && b[0] < 0x80 {
+        return b.to_vec();
+    }
+    let mut v = rlp_length(b.len(), 0x80);
+    v.extend_from_slice(b);
+    v
+}
+
+pub fn rlp_uint(x: u64) -> Vec<u8> {
+    rlp_bytes(&u64_to_bytes(x))
+}
+
+pub fn rlp_bigint(x: &BigInt) -> Vec<u8> {
+    rlp_bytes(&bigint_to_bytes(x))
+}
+
+pub fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut v = rlp_length(payload.len(), 0xc0);
+    v.extend(payload);
+    v
+}
+
+fn rlp_address(to: &Option<Address>) -> Vec<u8> {
+    match to {
+        Some(a) => rlp_bytes(a),
+        None => rlp_bytes(&[]),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EthSignature {
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+    pub v: u64,
+}
+
+impl EthSignature {
+    fn from_signature(sig: &Signature, v: u64) -> Self {
+        Self {
+            r: to_bytes32(&fe_to_bigint(&sig.r)),
+            s: to_bytes32(&fe_to_bigint(&sig.s)),
+            v: v,
+        }
+    }
+    fn rlp_rs(&self) -> Vec<Vec<u8>> {
+        vec![
+            rlp_bigint(&BigInt::from(&self.r[..])),
+            rlp_bigint(&BigInt::from(&self.s[..])),
+        ]
+    }
+}
+
+// EIP-155
+#[derive(Clone, Debug)]
+pub struct LegacyTransaction {
+    pub nonce: u64,
+    pub gas_price: BigInt,
+    pub gas_limit: u64,
+    pub to: Option<Address>,
+    pub value: BigInt,
+    pub data: Vec<u8>,
+}
+
+impl LegacyTransaction {
+    fn fields(&self) -> Vec<Vec<u8>> {
+        vec![
+            rlp_uint(self.nonce),
+            rlp_bigint(&self.gas_price),
+            rlp_uint(self.gas_limit),
+            rlp_address(&self.to),
+            rlp_bigint(&self.value),
+            rlp_bytes(&self.data),
+        ]
+    }
+    pub fn signing_payload(&self, chain_id: u64) -> Vec<u8> {
+        let mut f = self.fields();
+        f.push(rlp_uint(chain_id));
+        f.push(rlp_uint(0));
+        f.push(rlp_uint(0));
+        rlp_list(&f)
+    }
+    pub fn signing_hash(&self, chain_id: u64) -> [u8; 32] {
+        keccak256(&self.signing_payload(chain_id))
+    }
+    pub fn encode_signed(&self, sig: &EthSignature) -> Vec<u8> {
+        let mut f = self.fields();
+        f.push(rlp_uint(sig.v));
+        f.extend(sig.rlp_rs());
+        rlp_list(&f)
+    }
+}
+
+// EIP-1559 (typed transaction 0x02)
+#[derive(Clone, Debug)]
+pub struct Eip1559Transaction {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: BigInt,
+    pub max_fee_per_gas: BigInt,
+    pub gas_limit: u64,
+    pub to: Option<Address>,
+    pub value: BigInt,
+    pub data: Vec<u8>,
+    pub access_list: Vec<(Address, Vec<[u8; 32]>)>,
+}
+
+impl Eip1559Transaction {
+    const TX_TYPE: u8 = 0x02;
+
+    fn fields(&self) -> Vec<Vec<u8>> {
+        let access_list: Vec<Vec<u8>> = self
+            .access_list
+            .iter()
+            .map(|(a, keys)| {
+                let keys: Vec<Vec<u8>> = keys.iter().map(|k| rlp_bytes(k)).collect();
+                rlp_list(&[rlp_bytes(a), rlp_list(&keys)])
+            })
+            .collect();
+        vec![
+            rlp_uint(self.chain_id),
+            rlp_uint(self.nonce),
+            rlp_bigint(&self.max_priority_fee_per_gas),
+            rlp_bigint(&self.max_fee_per_gas),
+            rlp_uint(self.gas_limit),
+            rlp_address(&self.to),
+            rlp_bigint(&self.value),
+            rlp_bytes(&self.data),
+            rlp_list(&access_list),
+        ]
+    }
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut v = vec![Self::TX_TYPE];
+        v.extend(rlp_list(&self.fields()));
+        v
+    }
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.signing_payload())
+    }
+    pub fn encode_signed(&self, sig: &EthSignature) -> Vec<u8> {
+        let mut f = self.fields();
+        f.push(rlp_uint(sig.v));
+        f.extend(sig.rlp_rs());
+        let mut v = vec![Self::TX_TYPE];
+        v.extend(rlp_list(&f));
+        v
+    }
+}
+
+// EIP-191 version 0x45
+pub fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut v = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    v.extend_from_slice(message);
+    keccak256(&v)
+}
+
+pub fn eip712_domain_separator(
+    name: &str,
+    version: &str,
+    chain_id: u64,
+    verifying_contract: &Address,
+) -> [u8; 32] {
+    let type_hash = keccak256(
+        "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)"
+            .as_bytes(),
+    );
+    let mut v = Vec::with_capacity(32 * 5);
+    v.extend_from_slice(&type_hash);
+    v.extend_from_slice(&keccak256(name.as_bytes()));
+    v.extend_from_slice(&keccak256(version.as_bytes()));
+    v.extend_from_slice(&to_bytes32(&BigInt::from(chain_id)));
+    v.extend_from_slice(&[0u8; 12]);
+    v.extend_from_slice(verifying_contract);
+    keccak256(&v)
+}
+
+// struct_hash is hashStruct(message) as defined in EIP-712
+pub fn eip712_hash(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut v = vec![0x19, 0x01];
+    v.extend_from_slice(domain_separator);
+    v.extend_from_slice(struct_hash);
+    keccak256(&v)
+}
+
+pub fn sign_hash(etude: &mut Etude, hash: &[u8; 32]) -> Signature {
+    etude.sign_digest(&hash_to_fe(hash));
+    etude.signature().unwrap()
+}
+
+pub fn sign_legacy(etude: &mut Etude, tx: &LegacyTransaction, chain_id: u64) -> EthSignature {
+    let sig = sign_hash(etude, &tx.signing_hash(chain_id));
+    EthSignature::from_signature(&sig, (sig.recid & 1) as u64 + 35 + chain_id * 2)
+}
+
+pub fn sign_eip1559(etude: &mut Etude, tx: &Eip1559Transaction) -> EthSignature {
+    let sig = sign_hash(etude, &tx.signing_hash());
+    EthSignature::from_signature(&sig, (sig.recid & 1) as u64)
+}
+
+pub fn sign_personal_message(etude: &mut Etude, message: &[u8]) -> EthSignature {
+    let sig = sign_hash(etude, &personal_message_hash(message));
+    EthSignature::from_signature(&sig, (sig.recid & 1) as u64 + 27)
+}
+
+pub fn sign_typed_data(
+    etude: &mut Etude,
+    domain_separator: &[u8; 32],
+    struct_hash: &[u8; 32],
+) -> EthSignature {
+    let sig = sign_hash(etude, &eip712_hash(domain_separator, struct_hash));
+    EthSignature::from_signature(&sig, (sig.recid & 1) as u64 + 27)
+}
+
+pub fn recover_public_key(hash: &[u8; 32], sig: &Signature) -> Option<GE> {
+    let q = FE::q();
+    let mut x = fe_to_bigint(&sig.r);
+    if sig.recid & 2 != 0 {
+        x = &x + &q;
+    }
+    let point_r = lift_x(&x, sig.recid & 1 == 1)?;
+
+    //Q = r^-1 (sR - mG)
+    let m = fe_to_bigint(&hash_to_fe(hash));
+    let r_inv = sig.r.invert();
+    let u1 = fe_from_bigint(&(&q - &m).mod_floor(&q)) * r_inv;
+    let u2 = sig.s * r_inv;
+    Some(GE::generator() * u1 + point_r * u2)
+}
+
+pub fn recover_address(hash: &[u8; 32], sig: &Signature) -> Option<Address> {
+    recover_public_key(hash, sig).map(|y| address(&y))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use self::curv::{BigInt, FE, GE, SK};
+    use crate::eth::*;
+    use crate::etude::Etude;
+
+    #[test]
+    fn test_address() {
+        let one = <FE as ECScalar<SK>>::from(&BigInt::from(1));
+        let y = GE::generator() * one;
+        assert_eq!(
+            "7e5f4552091a69125d5dfcb7b8c2659029395bdf",
+            to_hex(&address(&y))
+        );
+    }
+
+    #[test]
+    fn test_eip155_signing_payload() {
+        let tx = LegacyTransaction {
+            nonce: 9,
+            gas_price: BigInt::from(20_000_000_000u64),
+            gas_limit: 21000,
+            to: Some([0x35; 20]),
+            value: BigInt::from(1_000_000_000_000_000_000u64),
+            data: Vec::new(),
+        };
+        assert_eq!(
+            "ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080",
+            to_hex(&tx.signing_payload(1))
+        );
+        assert_eq!(
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53",
+            to_hex(&tx.signing_hash(1))
+        );
+    }
+
+    #[test]
+    fn test_sign_legacy() {
+        let tx = LegacyTransaction {
+            nonce: 0,
+            gas_price: BigInt::from(1_000_000_000u64),
+            gas_limit: 21000,
+            to: Some([0x35; 20]),
+            value: BigInt::from(1),
+            data: Vec::new(),
+        };
+        let mut gg18 = Etude::new(3);
+        let sig = sign_legacy(&mut gg18, &tx, 5);
+        assert!(sig.v == 45 || sig.v == 46);
+
+        let y = gg18.public_key().unwrap();
+        let recovered = recover_address(&tx.signing_hash(5), &gg18.signature().unwrap());
+        assert_eq!(Some(address(&y)), recovered);
+    }
+
+    fn address_of(s: &str) -> Address {
+        let mut a = [0u8; 20];
+        a.copy_from_slice(&from_hex(s).unwrap());
+        a
+    }
+
+    #[test]
+    fn test_eip1559_signing_payload() {
+        // the RLP and Keccak-256 were cross-checked against an independent implementation
+        let tx = Eip1559Transaction {
+            chain_id: 1,
+            nonce: 9,
+            max_priority_fee_per_gas: BigInt::from(2_000_000_000u64),
+            max_fee_per_gas: BigInt::from(100_000_000_000u64),
+            gas_limit: 30000,
+            to: Some([0x35; 20]),
+            value: BigInt::from(1_000_000_000_000_000_000u64),
+            data: vec![0xa9, 0x05, 0x9c, 0xbb],
+            access_list: vec![(
+                address_of("de0b295669a9fd93d5f28d9ec85e40f4cb697bae"),
+                vec![to_bytes32(&BigInt::from(3)), to_bytes32(&BigInt::from(7))],
+            )],
+        };
+        assert_eq!(
+            "02f8900109847735940085174876e800827530943535353535353535353535353535353535353535\
+             880de0b6b3a764000084a9059cbbf85bf85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842\
+             a00000000000000000000000000000000000000000000000000000000000000003a000000000000000\
+             00000000000000000000000000000000000000000000000007",
+            to_hex(&tx.signing_payload())
+        );
+        assert_eq!(
+            "c3fd132416cc76d6a0ccd1ba7ef9dfaac7f5b67d6b40072e2669346c5893aa9f",
+            to_hex(&tx.signing_hash())
+        );
+
+        // contract creation, empty fields
+        let tx = Eip1559Transaction {
+            chain_id: 1,
+            nonce: 0,
+            max_priority_fee_per_gas: BigInt::from(0),
+            max_fee_per_gas: BigInt::from(0),
+            gas_limit: 21000,
+            to: None,
+            value: BigInt::from(0),
+            data: Vec::new(),
+            access_list: Vec::new(),
+        };
+        assert_eq!("02cb01808080825208808080c0", to_hex(&tx.signing_payload()));
+    }
+
+    #[test]
+    fn test_personal_message_hash() {
+        // ethers.js hashMessage("Hello World")
+        assert_eq!(
+            "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2",
+            to_hex(&personal_message_hash("Hello World".as_bytes()))
+        );
+    }
+
+    #[test]
+    fn test_eip712_mail() {
+        // the Mail example of EIP-712
+        let domain = eip712_domain_separator(
+            "Ether Mail",
+            "1",
+            1,
+            &address_of("cccccccccccccccccccccccccccccccccccccccc"),
+        );
+        assert_eq!(
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f",
+            to_hex(&domain)
+        );
+
+        let person_type = keccak256("Person(string name,address wallet)".as_bytes());
+        let person = |name: &str, wallet: &str| {
+            let mut v = person_type.to_vec();
+            v.extend_from_slice(&keccak256(name.as_bytes()));
+            v.extend_from_slice(&[0u8; 12]);
+            v.extend_from_slice(&address_of(wallet));
+            keccak256(&v)
+        };
+        let mut v = keccak256(
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+                .as_bytes(),
+        )
+        .to_vec();
+        v.extend_from_slice(&person("Cow", "cd2a3d9f938e13cd947ec05abc7fe734df8dd826"));
+        v.extend_from_slice(&person("Bob", "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"));
+        v.extend_from_slice(&keccak256("Hello, Bob!".as_bytes()));
+        let mail = keccak256(&v);
+        assert_eq!(
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e",
+            to_hex(&mail)
+        );
+        assert_eq!(
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2",
+            to_hex(&eip712_hash(&domain, &mail))
+        );
+    }
+}
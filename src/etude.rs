@@ -3,54 +3,584 @@ use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
 use self::curv::cryptographic_primitives::hashing::traits::Hash;
 use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
 use self::curv::{BigInt, FE, GE, SK};
-
+use rand::rngs::StdRng;
+use rand::{thread_rng, CryptoRng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::cmp;
+
+use crate::bip32;
+use crate::curve::{self, PointOps, ScalarOps};
+use crate::dlog_proof::{session_salt, DLogEqProof, DLogProof};
+use crate::error::{Error, Result};
+use crate::keygen::KeyShare;
 use crate::mta;
-
-fn fe_to_bigint(fe: &FE) -> BigInt {
+use crate::network::{handle_buffered, replay_backlog, undelivered, Buffered, Handler, Outgoing};
+use crate::party_id::{PartyId, Session};
+use crate::ring_pedersen::RingPedersenParams;
+use crate::rng;
+use crate::schnorr;
+use crate::vss;
+
+pub(crate) fn fe_to_bigint(fe: &FE) -> BigInt {
     fe.to_big_int()
 }
-fn fe_from_bigint(z: &BigInt) -> FE {
+pub(crate) fn fe_from_bigint(z: &BigInt) -> FE {
     <FE as ECScalar<SK>>::from(&z)
 }
 
 #[allow(dead_code)]
-fn digest_message(message: &[u8]) -> FE {
-    let bn = HSha256::create_hash(&vec![&BigInt::from(message)]);
-    <FE as ECScalar<SK>>::from(&bn)
+pub fn digest_message(message: &[u8]) -> FE {
+    curve::hash_to_scalar(message)
 }
 #[allow(dead_code)]
-fn get_x(p: &GE) -> FE {
+pub(crate) fn get_x(p: &GE) -> FE {
     let x: BigInt = p.x_coor().unwrap().mod_floor(&FE::q());
     <FE as ECScalar<SK>>::from(&x)
 }
 
+// Alice's ciphertext, with a range proof when the peer published aux params
+pub type MtaRequest = (
+    mta::Enc,
+    mta::RawCiphertext<'static>,
+    Option<mta::AliceProof>,
+);
+
+pub(crate) fn mta_to_bob<S: ScalarOps, R: CryptoRng + RngCore>(
+    p: &mut mta::Party<S>,
+    aux: Option<&RingPedersenParams>,
+    rng: &mut R,
+) -> MtaRequest {
+    let alice = p.alicization(rng);
+    match aux {
+        Some(aux) => {
+            let (e, c, proof) = alice.to_bob_with_proof(aux, rng);
+            (e.clone(), c, Some(proof))
+        }
+        None => {
+            let (e, c) = alice.to_bob(rng);
+            (e.clone(), c, None)
+        }
+    }
+}
+
+// independent rngs for work done in parallel, so that a seeded run stays reproducible
+fn fork_rngs<R: CryptoRng + RngCore>(rng: &mut R, n: usize) -> Vec<StdRng> {
+    (0..n)
+        .map(|_| StdRng::from_rng(&mut *rng).expect("rng failure"))
+        .collect()
+}
+
+// the four MtA instances run with one peer
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct MtaPeer<S: ScalarOps = FE> {
+    pub(crate) kr: mta::Party<S>,
+    pub(crate) rk: mta::Party<S>,
+    pub(crate) ku: mta::Party<S>,
+    pub(crate) uk: mta::Party<S>,
+    pub(crate) sent: Vec<(mta::Enc, BigInt)>, // Enc(k) and Enc(w) of ku and uk as Alice
+}
+
+impl<S: ScalarOps> MtaPeer<S> {
+    pub(crate) fn new(dec: &mta::Dec, ki: &BigInt, ri: &BigInt, ui: &BigInt) -> Self {
+        MtaPeer {
+            kr: mta::Party::with_dec(dec.clone(), ki.clone()),
+            rk: mta::Party::with_dec(dec.clone(), ri.clone()),
+            ku: mta::Party::with_dec(dec.clone(), ki.clone()),
+            uk: mta::Party::with_dec(dec.clone(), ui.clone()),
+            sent: Vec::new(),
+        }
+    }
+
+    pub(crate) fn to_bob<R: CryptoRng + RngCore>(
+        &mut self,
+        aux: Option<&RingPedersenParams>,
+        rng: &mut R,
+    ) -> Vec<MtaRequest> {
+        let reqs = vec![
+            mta_to_bob(&mut self.kr, aux, rng),
+            mta_to_bob(&mut self.rk, aux, rng),
+            mta_to_bob(&mut self.ku, aux, rng),
+            mta_to_bob(&mut self.uk, aux, rng),
+        ];
+        self.sent = reqs[2..]
+            .iter()
+            .map(|(e, c, _)| (e.clone(), raw(c)))
+            .collect();
+        reqs
+    }
+
+    // set kr[i][j] to rk[j][i]
+    pub(crate) fn from_alice<R: CryptoRng + RngCore>(
+        &mut self,
+        inp: &[MtaRequest],
+        rng: &mut R,
+    ) -> Vec<mta::RawCiphertext<'static>> {
+        vec![
+            self.rk.bobization().from_alice(&inp[0].0, &inp[0].1, rng),
+            self.kr.bobization().from_alice(&inp[1].0, &inp[1].1, rng),
+            self.uk.bobization().from_alice(&inp[2].0, &inp[2].1, rng),
+            self.ku.bobization().from_alice(&inp[3].0, &inp[3].1, rng),
+        ]
+    }
+
+    pub(crate) fn from_bob(&mut self, inp: &[mta::RawCiphertext]) {
+        self.kr.as_alice().from_bob(&inp[0]);
+        self.rk.as_alice().from_bob(&inp[1]);
+        self.ku.as_alice().from_bob(&inp[2]);
+        self.uk.as_alice().from_bob(&inp[3]);
+    }
+
+    // this party's shares of ki·γj, γi·kj, ki·wj and wi·kj, once all four are done
+    pub(crate) fn shares(&self) -> Option<(S, S, S, S)> {
+        match (
+            self.kr.get_result(),
+            self.rk.get_result(),
+            self.ku.get_result(),
+            self.uk.get_result(),
+        ) {
+            (Some(kr), Some(rk), Some(ku), Some(uk)) => Some((*kr.1, *rk.1, *ku.1, *uk.1)),
+            _ => None,
+        }
+    }
+}
+
+fn raw(c: &mta::RawCiphertext) -> BigInt {
+    c.0.clone().into_owned()
+}
+
+// the peer's Paillier key must be the one from keygen and each ciphertext needs a
+// range proof against our aux params; q is the curve order
+pub(crate) fn check_mta_request(
+    q: &BigInt,
+    eks: Option<&Vec<mta::Enc>>,
+    auxs: Option<&Vec<RingPedersenParams>>,
+    (me, n): (usize, usize),
+    from: usize,
+    inp: &[MtaRequest],
+) -> Result<()> {
+    if from >= n || from == me {
+        return Err(Error::UnknownParty);
+    }
+    // kr, rk, ku and uk
+    if inp.len() != 4 {
+        return Err(Error::InvalidMta { party: from });
+    }
+    if let Some(eks) = eks {
+        if inp.iter().any(|(e, _, _)| *e != eks[from]) {
+            return Err(Error::InvalidPaillierKey { party: from });
+        }
+    }
+    if let Some(auxs) = auxs {
+        for (e, c, proof) in inp.iter() {
+            match proof {
+                Some(proof) if proof.verify(q, e, &c.0, &auxs[me]) => {}
+                _ => return Err(Error::InvalidRangeProof { party: from }),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn check_mta_response(
+    (me, n): (usize, usize),
+    from: usize,
+    inp: &[mta::RawCiphertext],
+) -> Result<()> {
+    if from >= n || from == me {
+        return Err(Error::UnknownParty);
+    }
+    if inp.len() != 4 {
+        return Err(Error::InvalidMta { party: from });
+    }
+    Ok(())
+}
+
+// The rounds after the MtA, shared with the typestate in rounds.
+
+// R = (Π g^γj)^(1/δ) = g^(1/k)
+pub(crate) fn compute_r<S: ScalarOps, P: PointOps<S>>(grs: &[P], delta: &S) -> P {
+    grs[1..].iter().fold(grs[0], |acc, gr| acc + *gr) * delta.inverse()
+}
+
+// what a party received in every broadcast before R, one hash per sender
+pub(crate) fn echo<S: ScalarOps, P: PointOps<S>>(
+    gus: &[P],
+    grs: &[P],
+    deltas: &[S],
+) -> Vec<BigInt> {
+    (0..gus.len())
+        .map(|k| {
+            HSha256::create_hash(&vec![
+                &gus[k].affine_x().unwrap(),
+                &gus[k].affine_y().unwrap(),
+                &grs[k].affine_x().unwrap(),
+                &grs[k].affine_y().unwrap(),
+                &deltas[k].to_bigint(),
+            ])
+        })
+        .collect()
+}
+
+// the echo of `from` against ours
+pub(crate) fn check_echo(me: usize, from: usize, echo: &[BigInt], mine: &[BigInt]) -> Result<()> {
+    if echo.len() != mine.len() {
+        return Err(Error::InconsistentBroadcast { party: from });
+    }
+    if let Some(k) = (0..mine.len()).find(|k| echo[*k] != mine[*k]) {
+        // we know what we sent ourselves; otherwise k may have sent `from` something else,
+        // or `from` may misreport it, and the unsigned broadcasts cannot tell which
+        if k == me {
+            return Err(Error::InconsistentBroadcast { party: from });
+        }
+        return Err(Error::InconsistentEcho {
+            party: k,
+            echoer: from,
+        });
+    }
+    Ok(())
+}
+
+// a broadcast may arrive twice, but not with another value
+pub(crate) fn set_once<T: PartialEq>(slot: &mut Option<T>, value: T, from: usize) -> Result<()> {
+    match *slot {
+        Some(ref old) if *old != value => Err(Error::InconsistentBroadcast { party: from }),
+        _ => {
+            *slot = Some(value);
+            Ok(())
+        }
+    }
+}
+
+// Π R^kj = g exactly when δ = kγ
+pub(crate) fn check_r<S: ScalarOps, P: PointOps<S>>(ris: &[P]) -> bool {
+    ris[1..].iter().fold(ris[0], |acc, r| acc + *r) == P::base_point()
+}
+
+// si = m·ki + r·σi; R is not the identity, as every g^γj came with a proof
+pub(crate) fn local_si<S: ScalarOps, P: PointOps<S>>(m: &S, ki: &S, sign_r: &P, sigma_i: &S) -> S {
+    *m * *ki + curve::x_scalar(sign_r).unwrap() * *sigma_i
+}
+
+// after the R check failed, with everyone's reveal: the party whose reveal does not match
+// what it broadcast, whose δj does not add up, or a pair whose MtA disagrees
+pub(crate) fn blame_reveals<S: ScalarOps, P: PointOps<S>>(
+    grs: &[P],
+    deltas: &[S],
+    sign_r: &P,
+    ris: &[P],
+    reveals: &[&Reveal<S>],
+) -> Error {
+    let n = reveals.len();
+    for (j, r) in reveals.iter().enumerate() {
+        if r.kgamma.len() != n
+            || r.gammak.len() != n
+            || P::base_point() * r.gamma != grs[j]
+            || *sign_r * r.k != ris[j]
+        {
+            return Error::InvalidReveal { party: j };
+        }
+    }
+    for (j, r) in reveals.iter().enumerate() {
+        let delta_j = (0..n)
+            .filter(|l| *l != j)
+            .fold(r.k * r.gamma, |acc, l| acc + r.kgamma[l] + r.gammak[l]);
+        if delta_j != deltas[j] {
+            return Error::InvalidDelta { party: j };
+        }
+    }
+    // every δj adds up, so some pair disagrees on k_j·γ_l
+    for j in 0..n {
+        for l in 0..n {
+            if j != l
+                && reveals[j].kgamma[l] + reveals[l].gammak[j] != reveals[j].k * reveals[l].gamma
+            {
+                return Error::InconsistentMta { party: j, peer: l };
+            }
+        }
+    }
+    Error::InvalidState
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signature<S: ScalarOps = FE> {
+    pub r: S,
+    pub s: S,
+    pub recid: u8,
+}
+
+impl<S: ScalarOps> Signature<S> {
+    // low-s normalized, recid taken from R
+    pub(crate) fn new<P: PointOps<S>>(sign_r: &P, sign_s: &S) -> Self {
+        let q = S::order();
+        let x = sign_r.affine_x().unwrap();
+        let y = sign_r.affine_y().unwrap();
+        let mut recid: u8 = 0;
+        if y.mod_floor(&BigInt::from(2)) == BigInt::from(1) {
+            recid |= 1;
+        }
+        if x >= q {
+            recid |= 2;
+        }
+        let mut s = sign_s.to_bigint();
+        if &s * BigInt::from(2) > q {
+            s = &q - &s;
+            recid ^= 1;
+        }
+        Signature {
+            r: S::from_bigint(&x),
+            s: S::from_bigint(&s),
+            recid: recid,
+        }
+    }
+
+    pub fn verify<P: PointOps<S>>(&self, y: &P, m: &S) -> bool {
+        let inv_s = self.s.inverse();
+        let g_m_s = P::base_point() * (*m * inv_s);
+        let y_r_s = *y * (self.r * inv_s);
+        curve::x_scalar(&(g_m_s + y_r_s)) == Some(self.r)
+    }
+}
+
+// GG18 needs the message before R is checked and cannot tell who sent a bad si. GG20 checks
+// Π R^σi = y before any si goes out, so the message is only needed for the last round and
+// each si can be checked alone against R^ki and R^σi.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Protocol {
+    Gg18,
+    Gg20,
+}
+
+// Messages of a networked signing party. Ciphertexts are carried as plain integers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SignMessage {
+    Gr {
+        gu: GE,
+        gr: GE,
+        gu_proof: DLogProof,
+        gr_proof: DLogProof,
+    },
+    MtaRequest(Vec<(mta::Enc, BigInt, Option<mta::AliceProof>)>),
+    // with Bob's signature over the σ MtA transcript
+    MtaResponse(Vec<BigInt>, Vec<u8>),
+    Delta(FE),
+    Ri {
+        ri: GE,
+        echo: Vec<BigInt>,
+    },
+    Reveal(Reveal),
+    // GG20: R^σi and g^σi, proven to have the same exponent
+    SigmaR {
+        s: GE,
+        t: GE,
+        proof: DLogEqProof,
+    },
+    SigmaReveal(SigmaReveal),
+    Si(FE),
+}
+
+// the nonce shares and δ MtA shares of an aborted run, opened to find who cheated
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Reveal<S: ScalarOps = FE> {
+    pub k: S,
+    pub gamma: S,
+    pub kgamma: Vec<S>, // share of k_i·γ_j for every j
+    pub gammak: Vec<S>, // share of γ_i·k_j for every j
+}
+
+// after Π R^σi != y: ki and the σ MtA shares in the exponent, enough to check them
+// against g^wj without opening any wj. The holder of k also opens its side of each k·w MtA,
+// so that a pair that disagrees names the other one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigmaReveal {
+    pub k: FE,
+    pub kw: Vec<Option<GE>>, // g^(share of k_i·w_j) for every j but i
+    pub wk: Vec<Option<GE>>, // g^(share of w_i·k_j) for every j but i
+    pub transcripts: Vec<Option<MtaTranscript>>, // for every j after i
+    pub openings: Vec<Option<MtaOpening>>, // of k_i·w_j for every j but i
+}
+
+// The σ MtAs of a < b as Alice a got them: her key, Enc(k_a) and Bob's answer for k_a·w_b,
+// then her key, Enc(w_a) and his answer for w_a·k_b. Bob signs it under w_b.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MtaTranscript {
+    pub kw: (mta::Enc, BigInt, BigInt),
+    pub wk: (mta::Enc, BigInt, BigInt),
+    pub sig: Vec<u8>,
+}
+
+impl MtaTranscript {
+    // g^γa and g^γb tie it to this run
+    fn message(&self, a: usize, b: usize, grs: &[GE]) -> Vec<u8> {
+        let mut v = vec![BigInt::from(a as u64), BigInt::from(b as u64)];
+        for p in [grs[a], grs[b]].iter() {
+            v.push(p.x_coor().unwrap());
+            v.push(p.y_coor().unwrap());
+        }
+        for t in [&self.kw, &self.wk].iter() {
+            v.push(t.0.n());
+            v.push(t.1.clone());
+            v.push(t.2.clone());
+        }
+        curve::to_bytes32(&HSha256::create_hash(&v.iter().collect::<Vec<_>>())).to_vec()
+    }
+
+    fn sign<R: CryptoRng + RngCore>(
+        &mut self,
+        a: usize,
+        b: usize,
+        grs: &[GE],
+        w: &FE,
+        rng: &mut R,
+    ) {
+        self.sig = schnorr::sign(w, &self.message(a, b, grs), rng).to_bytes();
+    }
+
+    fn verify(&self, a: usize, b: usize, grs: &[GE], gw: &GE) -> bool {
+        schnorr::verify(&schnorr::xonly(gw), &self.message(a, b, grs), &self.sig)
+    }
+
+    // Bob b's, from Alice a's request and his answer
+    fn answered<R: CryptoRng + RngCore>(
+        (a, b): (usize, usize),
+        grs: &[GE],
+        wb: &FE,
+        inp: &[MtaRequest],
+        cs: &[mta::RawCiphertext],
+        rng: &mut R,
+    ) -> Self {
+        let mut t = MtaTranscript {
+            kw: (inp[2].0.clone(), raw(&inp[2].1), raw(&cs[2])),
+            wk: (inp[3].0.clone(), raw(&inp[3].1), raw(&cs[3])),
+            sig: Vec::new(),
+        };
+        t.sign(a, b, grs, wb, rng);
+        t
+    }
+
+    // Alice a's, from what she sent and Bob b's answer, if his signature holds
+    fn received(
+        (a, b): (usize, usize),
+        grs: &[GE],
+        gwb: &GE,
+        sent: &[(mta::Enc, BigInt)],
+        inp: &[mta::RawCiphertext],
+        sig: Vec<u8>,
+    ) -> Result<Self> {
+        let t = MtaTranscript {
+            kw: (sent[0].0.clone(), sent[0].1.clone(), raw(&inp[2])),
+            wk: (sent[1].0.clone(), sent[1].1.clone(), raw(&inp[3])),
+            sig: sig,
+        };
+        if !t.verify(a, b, grs, gwb) {
+            return Err(Error::InvalidMessageSignature { party: b });
+        }
+        Ok(t)
+    }
+}
+
+// The side of k·w its k holder can open once k is public: Alice the randomness of Enc(k)
+// and Dec(D) with its randomness, Bob β' and the randomness of Enc(β').
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MtaOpening {
+    Alice {
+        rc: BigInt,
+        alpha: BigInt,
+        rd: BigInt,
+    },
+    Bob {
+        beta: BigInt,
+        rb: BigInt,
+    },
+}
+
+// whether `opening` of k·w in t matches g^share, the holder of k being Alice or Bob
+fn check_opening(alice: bool, k: &FE, share: &GE, t: &MtaTranscript, opening: &MtaOpening) -> bool {
+    let q = FE::q();
+    let zero = BigInt::from(0);
+    let k = fe_to_bigint(k);
+    match (alice, opening) {
+        (true, MtaOpening::Alice { rc, alpha, rd }) => {
+            let (ref ek, ref c, ref d) = t.kw;
+            *alpha >= zero
+                && *alpha < ek.n()
+                && raw(&ek.encrypt_with_randomness(&k, rc)) == *c
+                && raw(&ek.encrypt_with_randomness(alpha, rd)) == *d
+                && GE::generator() * &FE::from_bigint(alpha) == *share
+        }
+        (false, MtaOpening::Bob { beta, rb }) => {
+            // below q^5 as Bob draws it, so that Alice's decryption cannot wrap
+            let (ref ek, ref c, ref d) = t.wk;
+            let answer = ek.add(
+                ek.mul(to_ciphertext(c.clone()), &k),
+                ek.encrypt_with_randomness(beta, rb),
+            );
+            *beta >= zero
+                && *beta < q.pow(5)
+                && raw(&answer) == *d
+                && GE::generator() * &FE::from_bigint(&(&q - beta.mod_floor(&q))) == *share
+        }
+        _ => false,
+    }
+}
+
+fn to_ciphertext(c: BigInt) -> mta::RawCiphertext<'static> {
+    mta::RawCiphertext(Cow::Owned(c))
+}
+
+// serializable to keep it on disk between rounds, so the file holds ki, ri and ui
 #[allow(dead_code)]
-struct Party {
+#[derive(Serialize, Deserialize)]
+pub struct Party {
     pub n: usize,
     pub i: usize,
 
+    protocol: Protocol,
     g: GE,
+    digest: Option<FE>,
     ui: Option<FE>,
+    wi: Option<FE>, // ui with the derivation tweak in party 0's
     gui: Option<GE>,
+    session: Vec<u8>,
+    points: Vec<usize>,    // of all signers, for the proof salts
+    pubs: Option<Vec<GE>>, // g^wj each signer must send, from the key share
     y: Option<GE>,
+    root_y: Option<GE>,
+    derivation: Option<bip32::Derivation>,
+    dec: Option<mta::Dec>,
+    eks: Option<Vec<mta::Enc>>,
+    auxs: Option<Vec<RingPedersenParams>>,
 
     ki: Option<FE>,
     ri: Option<FE>,
     gri: Option<GE>,
+    gus: Vec<GE>,
     grs: Vec<GE>,
+    gws: Vec<GE>, // g^wj, with the derivation tweak in party 0's
 
     delta_i: Option<FE>,
     sigma_i: Option<FE>,
-
+    mta_shares: Vec<(FE, FE)>,
+    sigma_shares: Vec<(FE, FE)>,
+    transcripts: Vec<Option<MtaTranscript>>, // as Alice or as Bob, for a σ blame
+    openings: Vec<Option<MtaOpening>>,
+    deltas: Vec<FE>,
+
+    ris: Vec<GE>,
+    sigma_rs: Vec<GE>,
     sign_r: Option<GE>,
     sign_rx: Option<FE>,
     sign_si: Option<FE>,
     sign_s: Option<FE>,
 
     state: PartyState,
+    backlog: Vec<(usize, SignMessage)>,
 }
 
 #[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
 enum PartyState {
     Void {},
     BroadcastingGr {
@@ -58,10 +588,7 @@ enum PartyState {
         grs: Vec<Option<GE>>,
     },
     Mta {
-        kr: Vec<mta::Party>,
-        rk: Vec<mta::Party>,
-        ku: Vec<mta::Party>,
-        uk: Vec<mta::Party>,
+        peers: Vec<MtaPeer>,
     },
     BroadcastingDelta {
         deltas: Vec<Option<BigInt>>,
@@ -69,6 +596,26 @@ enum PartyState {
     CalculatingLocalSign {
         delta: FE,
     },
+    CheckingR {
+        delta: FE,
+        sign_r: GE,
+        ris: Vec<Option<GE>>,
+    },
+    Revealing {
+        sign_r: GE,
+        ris: Vec<GE>,
+        reveals: Vec<Option<Reveal>>,
+    },
+    CheckingSigma {
+        sign_r: GE,
+        sigmas: Vec<Option<(GE, GE)>>,
+    },
+    RevealingSigma {
+        sign_r: GE,
+        sigmas: Vec<(GE, GE)>,
+        reveals: Vec<Option<SigmaReveal>>,
+    },
+    Presigned {},
     BroadcastingSi {
         sis: Vec<Option<FE>>,
     },
@@ -84,6 +631,11 @@ impl Party {
             PartyState::Mta { .. } => "Mta",
             PartyState::BroadcastingDelta { .. } => "BroadcastingDelta",
             PartyState::CalculatingLocalSign { .. } => "CalculatingLocalSign",
+            PartyState::CheckingR { .. } => "CheckingR",
+            PartyState::Revealing { .. } => "Revealing",
+            PartyState::CheckingSigma { .. } => "CheckingSigma",
+            PartyState::RevealingSigma { .. } => "RevealingSigma",
+            PartyState::Presigned {} => "Presigned",
             PartyState::BroadcastingSi { .. } => "BroadcastingSi",
             PartyState::Fin { .. } => "Fin",
         }
@@ -91,34 +643,119 @@ impl Party {
 
     pub fn new(i: usize, n: usize) -> Self {
         Party {
+            protocol: Protocol::Gg18,
             g: GE::generator(),
             n: n,
             i: i,
+            digest: None,
             ui: None,
+            wi: None,
             gui: None,
+            session: Vec::new(),
+            points: (1..(n + 1)).collect(),
+            pubs: None,
             y: None,
+            root_y: None,
+            derivation: None,
+            dec: None,
+            eks: None,
+            auxs: None,
             ki: None,
             ri: None,
             gri: None,
+            gus: Vec::new(),
             grs: Vec::new(),
+            gws: Vec::new(),
             delta_i: None,
             sigma_i: None,
+            mta_shares: Vec::new(),
+            sigma_shares: Vec::new(),
+            transcripts: Vec::new(),
+            openings: Vec::new(),
+            deltas: Vec::new(),
+            ris: Vec::new(),
+            sigma_rs: Vec::new(),
             sign_r: None,
             sign_rx: None,
             sign_si: None,
             sign_s: None,
             state: PartyState::Void {},
+            backlog: Vec::new(),
+        }
+    }
+
+    // points are the Shamir evaluation points of all signers
+    pub fn with_key_share(i: usize, n: usize, share: &KeyShare, points: &[usize]) -> Self {
+        let mut p = Party::new(i, n);
+        let lambda = |x: usize| vss::lagrange_coefficient::<FE>(x, points);
+        p.ui = Some(share.xi * lambda(share.i + 1));
+        p.pubs = Some(
+            points
+                .iter()
+                .map(|x| share.public_share(x - 1) * lambda(*x))
+                .collect(),
+        );
+        p.y = Some(share.y);
+        p.points = points.to_vec();
+        p.dec = Some(share.dec.clone());
+        // verified by everyone at keygen
+        p.eks = Some(points.iter().map(|x| share.eks[x - 1].clone()).collect());
+        p.auxs = Some(points.iter().map(|x| share.auxs[x - 1].clone()).collect());
+        p
+    }
+
+    // `me` in a session of signers; the share must be the one keygen made for `me`
+    pub fn for_session(session: &Session, me: &PartyId, share: &KeyShare) -> Result<Self> {
+        if session.n() <= share.t {
+            return Err(Error::NotEnoughShares);
+        }
+        let i = session.index_of(me)?;
+        if session.points()[i] != share.i + 1 {
+            return Err(Error::UnknownParty);
         }
+        Ok(Party::with_key_share(
+            i,
+            session.n(),
+            share,
+            session.points(),
+        ))
+    }
+
+    // the message to sign when driven as a Handler; with GG20 it may wait until Presigned
+    pub fn set_digest(&mut self, m: FE) {
+        self.digest = Some(m);
+    }
+
+    // signs for a child of y; only non-hardened paths can be derived from a public key
+    pub fn set_derivation(&mut self, derivation: bip32::Derivation) -> Result<()> {
+        derivation.check(self.y.as_ref())?;
+        self.derivation = Some(derivation);
+        Ok(())
     }
 
-    pub fn begin(&mut self) {
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    // an id all signers agree on for this run, which the proofs are bound to
+    pub fn set_session(&mut self, session: &[u8]) {
+        self.session = session.to_vec();
+    }
+
+    fn salt(&self, i: usize) -> Vec<u8> {
+        session_salt(&self.session, &self.points, i)
+    }
+
+    pub fn begin<R: CryptoRng + RngCore>(&mut self, rng: &mut R) {
         let mut gus: Vec<Option<GE>> = vec![None; self.n];
         let mut grs: Vec<Option<GE>> = vec![None; self.n];
 
         if let PartyState::Void {} = self.state {
-            self.ui = Some(FE::new_random());
-            self.ki = Some(FE::new_random());
-            self.ri = Some(FE::new_random());
+            if self.ui.is_none() {
+                self.ui = Some(rng::random_fe(rng));
+            }
+            self.ki = Some(rng::random_fe(rng));
+            self.ri = Some(rng::random_fe(rng));
             self.gri = Some(self.g.clone() * &self.ri.unwrap());
             self.gui = Some(self.g.clone() * &self.ui.unwrap());
             gus[self.i] = self.gui.clone();
@@ -129,141 +766,337 @@ impl Party {
         self.state = PartyState::BroadcastingGr { gus: gus, grs: grs };
     }
 
-    pub fn on_gri(&mut self, i: usize, gu: GE, gr: GE) {
+    // g^ui and g^γi with proofs of knowledge of ui and γi
+    pub fn gri_message<R: CryptoRng + RngCore>(
+        &self,
+        rng: &mut R,
+    ) -> (GE, GE, DLogProof, DLogProof) {
+        let salt = self.salt(self.i);
+        (
+            self.gui.unwrap(),
+            self.gri.unwrap(),
+            DLogProof::prove(&self.ui.unwrap(), &salt, rng),
+            DLogProof::prove(&self.ri.unwrap(), &salt, rng),
+        )
+    }
+
+    pub fn on_gri(
+        &mut self,
+        i: usize,
+        gu: GE,
+        gr: GE,
+        gu_proof: DLogProof,
+        gr_proof: DLogProof,
+    ) -> Result<()> {
+        let salt = self.salt(i);
+        if !gu_proof.verify(&gu, &salt) || !gr_proof.verify(&gr, &salt) {
+            return Err(Error::InvalidDLogProof { party: i });
+        }
+        // with a key share, g^ui is fixed by the keygen commitments
+        if let Some(ref pubs) = self.pubs {
+            if gu != pubs[i] {
+                return Err(Error::InvalidShare { party: i });
+            }
+        }
         if let PartyState::BroadcastingGr {
             ref mut gus,
             ref mut grs,
         } = self.state
         {
-            gus[i] = Some(gu);
-            grs[i] = Some(gr);
+            set_once(&mut gus[i], gu, i)?;
+            set_once(&mut grs[i], gr, i)?;
             if grs.iter().find(|o| o.is_none()).is_some() {
-                return;
+                return Ok(());
             }
-            self.y = gus.iter().fold(None, |acc, gui| match acc {
-                Some(a) => Some(a + gui.unwrap()),
-                None => Some(gui.clone().unwrap()),
-            });
+            if self.y.is_none() {
+                self.y = gus.iter().fold(None, |acc, gui| match acc {
+                    Some(a) => Some(a + gui.unwrap()),
+                    None => Some(gui.clone().unwrap()),
+                });
+            }
+            self.gus = gus.iter().map(|o| o.unwrap()).collect();
             self.grs = grs.into_iter().map(|o| o.unwrap()).collect();
         } else {
             panic!("invalid state");
         }
+        self.root_y = self.y;
+        self.gws = self.gus.clone();
+        let mut wi = self.ui.unwrap();
+        if let Some(ref derivation) = self.derivation {
+            // child = y + g^tweak, so exactly one party adds the tweak to its share
+            let (child, tweak) = derivation
+                .derive(&self.y.unwrap())
+                .ok_or(Error::InvalidDerivation)?;
+            self.y = Some(child.public_key);
+            self.gws[0] = self.gws[0] + self.g * &tweak;
+            if self.i == 0 {
+                wi = wi + tweak;
+            }
+        }
+        self.wi = Some(wi);
+        self.transcripts = vec![None; self.n];
         let ki = fe_to_bigint(&self.ki.unwrap());
         let ri = fe_to_bigint(&self.ri.unwrap());
-        let ui = fe_to_bigint(&self.ui.unwrap());
+        let ui = fe_to_bigint(&wi);
+        let dec = self.dec.clone();
+        let new_mta = |m: &BigInt| match dec {
+            Some(ref dec) => mta::Party::with_dec(dec.clone(), m.clone()),
+            None => mta::Party::new(mta::plaintext_bits(&FE::q()), m.clone()),
+        };
         self.state = PartyState::Mta {
-            kr: (0..self.n)
-                .map(|_| mta::Party::new(256, ki.clone()))
-                .collect(),
-            rk: (0..self.n)
-                .map(|_| mta::Party::new(256, ri.clone()))
-                .collect(),
-            ku: (0..self.n)
-                .map(|_| mta::Party::new(256, ki.clone()))
-                .collect(),
-            uk: (0..self.n)
-                .map(|_| mta::Party::new(256, ui.clone()))
+            peers: (0..self.n)
+                .map(|_| MtaPeer {
+                    kr: new_mta(&ki),
+                    rk: new_mta(&ri),
+                    ku: new_mta(&ki),
+                    uk: new_mta(&ui),
+                    sent: Vec::new(),
+                })
                 .collect(),
         };
+        Ok(())
     }
 
-    pub fn on_mta_1(&mut self, from: usize) -> Vec<(mta::Enc, mta::RawCiphertext)> {
-        let mut vec = Vec::<(mta::Enc, mta::RawCiphertext)>::with_capacity(4);
-        if let PartyState::Mta { kr, rk, ku, uk } = &mut self.state {
-            {
-                let (e, c) = kr[from].alicization().to_bob();
-                vec.push((e.clone(), c));
-            }
-            {
-                let (e, c) = rk[from].alicization().to_bob();
-                vec.push((e.clone(), c));
-            }
-            {
-                let (e, c) = ku[from].alicization().to_bob();
-                vec.push((e.clone(), c));
-            }
-            {
-                let (e, c) = uk[from].alicization().to_bob();
-                vec.push((e.clone(), c));
-            }
+    pub fn on_mta_1<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        rng: &mut R,
+    ) -> Vec<MtaRequest> {
+        let aux = self.auxs.as_ref().map(|auxs| &auxs[from]);
+        if let PartyState::Mta { ref mut peers } = self.state {
+            peers[from].to_bob(aux, rng)
         } else {
             panic!("invalid state");
         }
-        vec
     }
 
-    pub fn on_mta_2(
+    // requests to every peer in `to`, computed in parallel
+    pub fn on_mta_1_many<R: CryptoRng + RngCore>(
+        &mut self,
+        to: &[usize],
+        rng: &mut R,
+    ) -> Vec<(usize, Vec<MtaRequest>)> {
+        let mut rngs = fork_rngs(rng, self.n);
+        let auxs = &self.auxs;
+        if let PartyState::Mta { ref mut peers } = self.state {
+            peers
+                .par_iter_mut()
+                .zip(rngs.par_iter_mut())
+                .enumerate()
+                .filter(|(j, _)| to.contains(j))
+                .map(|(j, (peer, rng))| (j, peer.to_bob(auxs.as_ref().map(|a| &a[j]), rng)))
+                .collect()
+        } else {
+            panic!("invalid state");
+        }
+    }
+
+    fn check_mta_request(&self, from: usize, inp: &[MtaRequest]) -> Result<()> {
+        check_mta_request(
+            &FE::q(),
+            self.eks.as_ref(),
+            self.auxs.as_ref(),
+            (self.i, self.n),
+            from,
+            inp,
+        )
+    }
+
+    // the answers with a signature over the σ MtA transcript
+    pub fn on_mta_2<R: CryptoRng + RngCore>(
         &mut self,
         from: usize,
-        inp: Vec<(mta::Enc, mta::RawCiphertext)>,
-    ) -> Vec<mta::RawCiphertext> {
-        let mut vec = Vec::<mta::RawCiphertext>::with_capacity(4);
-        if let PartyState::Mta { kr, rk, ku, uk } = &mut self.state {
-            {
-                let bob = rk[from].bobization();
-                let c = bob.from_alice(&inp[0].0, &inp[0].1); // set kr[i][j] to rk[j][i]
-                vec.push(c);
-            }
-            {
-                let bob = kr[from].bobization();
-                let c = bob.from_alice(&inp[1].0, &inp[1].1);
-                vec.push(c);
-            }
-            {
-                let bob = uk[from].bobization();
-                let c = bob.from_alice(&inp[2].0, &inp[2].1);
-                vec.push(c);
-            }
-            {
-                let bob = ku[from].bobization();
-                let c = bob.from_alice(&inp[3].0, &inp[3].1);
-                vec.push(c);
-            }
+        inp: Vec<MtaRequest>,
+        rng: &mut R,
+    ) -> Result<(Vec<mta::RawCiphertext<'static>>, Vec<u8>)> {
+        self.check_mta_request(from, &inp)?;
+        let cs = if let PartyState::Mta { ref mut peers } = self.state {
+            peers[from].from_alice(&inp, rng)
         } else {
             panic!("invalid state");
+        };
+        let t =
+            MtaTranscript::answered((from, self.i), &self.grs, &self.wi.unwrap(), &inp, &cs, rng);
+        let sig = t.sig.clone();
+        self.transcripts[from] = Some(t);
+        Ok((cs, sig))
+    }
+
+    // on_mta_2 for requests of several peers, in parallel
+    pub fn on_mta_2_many<R: CryptoRng + RngCore>(
+        &mut self,
+        inps: Vec<(usize, Vec<MtaRequest>)>,
+        rng: &mut R,
+    ) -> Result<Vec<(usize, Vec<mta::RawCiphertext<'static>>, Vec<u8>)>> {
+        {
+            let this = &*self;
+            inps.par_iter()
+                .map(|(from, inp)| this.check_mta_request(*from, inp))
+                .collect::<Result<()>>()?;
+        }
+        let mut rngs = fork_rngs(rng, self.n);
+        let mut by_peer: Vec<Option<Vec<MtaRequest>>> = (0..self.n).map(|_| None).collect();
+        for (from, inp) in inps {
+            by_peer[from] = Some(inp);
         }
-        vec
+        let (me, grs, wi) = (self.i, &self.grs, self.wi.unwrap());
+        let answers: Vec<(usize, Vec<mta::RawCiphertext>, MtaTranscript)> =
+            if let PartyState::Mta { ref mut peers } = self.state {
+                peers
+                    .par_iter_mut()
+                    .zip(rngs.par_iter_mut())
+                    .zip(by_peer.into_par_iter())
+                    .enumerate()
+                    .filter_map(|(j, ((peer, rng), inp))| {
+                        inp.map(|inp| {
+                            let cs = peer.from_alice(&inp, rng);
+                            let t = MtaTranscript::answered((j, me), grs, &wi, &inp, &cs, rng);
+                            (j, cs, t)
+                        })
+                    })
+                    .collect()
+            } else {
+                panic!("invalid state");
+            };
+        Ok(answers
+            .into_iter()
+            .map(|(j, cs, t)| {
+                let sig = t.sig.clone();
+                self.transcripts[j] = Some(t);
+                (j, cs, sig)
+            })
+            .collect())
     }
-    pub fn on_mta_3(&mut self, from: usize, inp: Vec<mta::RawCiphertext>) {
-        if let PartyState::Mta { kr, rk, ku, uk } = &mut self.state {
-            {
-                let alice = kr[from].as_alice();
-                alice.from_bob(&inp[0]);
-            }
-            {
-                let alice = rk[from].as_alice();
-                alice.from_bob(&inp[1]);
-            }
-            {
-                let alice = ku[from].as_alice();
-                alice.from_bob(&inp[2]);
-            }
-            {
-                let alice = uk[from].as_alice();
-                alice.from_bob(&inp[3]);
-            }
+
+    // sig is Bob's over what we sent and what he answered
+    pub fn on_mta_3(
+        &mut self,
+        from: usize,
+        inp: Vec<mta::RawCiphertext>,
+        sig: Vec<u8>,
+    ) -> Result<()> {
+        check_mta_response((self.i, self.n), from, &inp)?;
+        let t = if let PartyState::Mta { ref mut peers } = self.state {
+            let t = MtaTranscript::received(
+                (self.i, from),
+                &self.grs,
+                &self.gws[from],
+                &peers[from].sent,
+                &inp,
+                sig,
+            )?;
+            peers[from].from_bob(&inp);
+            t
+        } else {
+            panic!("invalid state");
+        };
+        self.transcripts[from] = Some(t);
+        Ok(())
+    }
+
+    // on_mta_3 for responses of several peers, in parallel
+    pub fn on_mta_3_many(
+        &mut self,
+        inps: Vec<(usize, Vec<mta::RawCiphertext<'static>>, Vec<u8>)>,
+    ) -> Result<()> {
+        let me = (self.i, self.n);
+        inps.par_iter()
+            .map(|(from, inp, _)| check_mta_response(me, *from, inp))
+            .collect::<Result<()>>()?;
+        let mut by_peer: Vec<Option<(Vec<mta::RawCiphertext>, Vec<u8>)>> =
+            (0..self.n).map(|_| None).collect();
+        for (from, inp, sig) in inps {
+            by_peer[from] = Some((inp, sig));
+        }
+        let (grs, gws) = (&self.grs, &self.gws);
+        let ts: Vec<(usize, MtaTranscript)> = if let PartyState::Mta { ref mut peers } = self.state
+        {
+            peers
+                .par_iter_mut()
+                .zip(by_peer.into_par_iter())
+                .enumerate()
+                .filter_map(|(j, (peer, inp))| {
+                    inp.map(|(inp, sig)| {
+                        let t = MtaTranscript::received(
+                            (me.0, j),
+                            grs,
+                            &gws[j],
+                            &peer.sent,
+                            &inp,
+                            sig,
+                        )?;
+                        peer.from_bob(&inp);
+                        Ok((j, t))
+                    })
+                })
+                .collect::<Result<_>>()?
         } else {
             panic!("invalid state");
+        };
+        for (j, t) in ts {
+            self.transcripts[j] = Some(t);
         }
+        Ok(())
     }
+
+    fn mta_peer(&self, j: usize) -> &MtaPeer {
+        if let PartyState::Mta { ref peers } = self.state {
+            &peers[j]
+        } else {
+            panic!("invalid state");
+        }
+    }
+
+    // our side of k_i·w_j, only needed if GG20 has to blame someone for σ
+    fn mta_opening(&self, j: usize, peer: &MtaPeer) -> Option<MtaOpening> {
+        if self.protocol != Protocol::Gg20 {
+            return None;
+        }
+        if j < self.i {
+            let bob = peer.ku.bob()?;
+            return Some(MtaOpening::Bob {
+                beta: bob.beta.clone(),
+                rb: bob.rho.clone(),
+            });
+        }
+        let t = self.transcripts[j].as_ref()?;
+        let alice = peer.ku.alice()?;
+        let (_, rc) = alice.open(&t.kw.1);
+        let (alpha, rd) = alice.open(&t.kw.2);
+        Some(MtaOpening::Alice {
+            rc: rc,
+            alpha: alpha,
+            rd: rd,
+        })
+    }
+
     fn on_mta_fin(&mut self) {
-        let mut delta_i = BigInt::from(0);
-        let mut sigma_i = BigInt::from(0);
-        if let PartyState::Mta { kr, rk, ku, uk } = &mut self.state {
-            for i in 0..(self.n) {
+        let mut delta_i = FE::zero();
+        let mut sigma_i = FE::zero();
+        let mut mta_shares = Vec::with_capacity(self.n);
+        let mut sigma_shares = Vec::with_capacity(self.n);
+        let mut openings = Vec::with_capacity(self.n);
+        if let PartyState::Mta { ref peers } = self.state {
+            for (i, peer) in peers.iter().enumerate() {
                 if i == self.i {
-                    delta_i += &kr[i].m * &rk[i].m;
-                    sigma_i += &ku[i].m * &uk[i].m;
+                    delta_i = delta_i + fe_from_bigint(&peer.kr.m) * fe_from_bigint(&peer.rk.m);
+                    sigma_i = sigma_i + fe_from_bigint(&peer.ku.m) * fe_from_bigint(&peer.uk.m);
+                    mta_shares.push((FE::zero(), FE::zero()));
+                    sigma_shares.push((FE::zero(), FE::zero()));
+                    openings.push(None);
                 } else {
-                    let r_kr = kr[i].get_result();
-                    let r_rk = rk[i].get_result();
-                    let r_ku = ku[i].get_result();
-                    let r_uk = uk[i].get_result();
+                    let r_kr = peer.kr.get_result();
+                    let r_rk = peer.rk.get_result();
+                    let r_ku = peer.ku.get_result();
+                    let r_uk = peer.uk.get_result();
                     if let (Some(r_kr), Some(r_rk), Some(r_ku), Some(r_uk)) =
                         (r_kr, r_rk, r_ku, r_uk)
                     {
-                        delta_i += r_kr.1 + r_rk.1;
-                        sigma_i += r_ku.1 + r_uk.1;
+                        delta_i = delta_i + r_kr.1 + r_rk.1;
+                        sigma_i = sigma_i + r_ku.1 + r_uk.1;
+                        mta_shares.push((*r_kr.1, *r_rk.1));
+                        sigma_shares.push((*r_ku.1, *r_uk.1));
+                        openings.push(self.mta_opening(i, peer));
                     } else {
                         return;
                     }
@@ -272,55 +1105,174 @@ impl Party {
         } else {
             panic!("invalid state");
         }
-        self.delta_i = Some(fe_from_bigint(&delta_i));
-        self.sigma_i = Some(fe_from_bigint(&sigma_i));
+        self.delta_i = Some(delta_i);
+        self.sigma_i = Some(sigma_i);
+        self.mta_shares = mta_shares;
+        self.sigma_shares = sigma_shares;
+        self.openings = openings;
         let mut deltas: Vec<Option<BigInt>> = vec![None; self.n];
-        deltas[self.i] = Some(delta_i.clone());
+        deltas[self.i] = Some(fe_to_bigint(&delta_i));
         self.state = PartyState::BroadcastingDelta { deltas: deltas }
     }
 
-    pub fn on_delta_i(&mut self, i: usize, di: BigInt) {
+    // for the simulator's misbehaving parties
+    pub(crate) fn set_delta_i(&mut self, delta_i: FE) {
+        self.delta_i = Some(delta_i);
+        if let PartyState::BroadcastingDelta { ref mut deltas } = self.state {
+            deltas[self.i] = Some(fe_to_bigint(&delta_i));
+        }
+    }
+
+    pub(crate) fn add_to_sigma_i(&mut self, x: FE) {
+        self.sigma_i = self.sigma_i.map(|s| s + x);
+    }
+
+    // adds one to Alice's share of w_to·k_i in our answer to `to`, signed again
+    pub(crate) fn skew_mta_response(
+        &mut self,
+        to: usize,
+        mut cs: Vec<BigInt>,
+    ) -> (Vec<BigInt>, Vec<u8>) {
+        let mut t = self.transcripts[to].take().unwrap();
+        let n = t.wk.0.n();
+        cs[3] = (&cs[3] * (&n + BigInt::from(1))).mod_floor(&(&n * &n));
+        t.wk.2 = cs[3].clone();
+        t.sign(to, self.i, &self.grs, &self.wi.unwrap(), &mut thread_rng());
+        let sig = t.sig.clone();
+        self.transcripts[to] = Some(t);
+        (cs, sig)
+    }
+
+    pub fn on_delta_i(&mut self, i: usize, di: BigInt) -> Result<()> {
         let mut delta = BigInt::new();
         if let PartyState::BroadcastingDelta { ref mut deltas } = self.state {
-            deltas[i] = Some(di);
+            set_once(&mut deltas[i], di, i)?;
             if deltas.iter().find(|o| o.is_none()).is_some() {
-                return;
+                return Ok(());
             }
             for i in 0..(self.n) {
                 if let Some(ref d) = deltas[i] {
                     delta += d;
                 } else {
-                    return;
+                    return Ok(());
                 }
             }
+            self.deltas = deltas
+                .iter()
+                .map(|d| fe_from_bigint(&d.as_ref().unwrap().mod_floor(&FE::q())))
+                .collect();
+        } else {
+            panic!("invalid state");
+        }
+        self.state = PartyState::CalculatingLocalSign {
+            delta: fe_from_bigint(&delta.mod_floor(&FE::q())),
+        };
+        Ok(())
+    }
+
+    fn echo(&self) -> Vec<BigInt> {
+        echo(&self.gus, &self.grs, &self.deltas)
+    }
+
+    // R = (Π g^γ i)^(1/δ) and R^ki, whose product is g exactly when δ = kγ
+    pub fn ri_message(&mut self) -> (GE, Vec<BigInt>) {
+        let (delta, sign_r) = if let PartyState::CalculatingLocalSign { ref delta } = self.state {
+            (*delta, compute_r(&self.grs, delta))
+        } else {
+            panic!("invalid state");
+        };
+        let ri = sign_r * &self.ki.unwrap();
+        let mut ris: Vec<Option<GE>> = vec![None; self.n];
+        ris[self.i] = Some(ri);
+        self.state = PartyState::CheckingR {
+            delta: delta,
+            sign_r: sign_r,
+            ris: ris,
+        };
+        (ri, self.echo())
+    }
+
+    pub fn on_ri(&mut self, i: usize, ri: GE, echo: Vec<BigInt>) -> Result<()> {
+        check_echo(self.i, i, &echo, &self.echo())?;
+        let (delta, sign_r, ris) = if let PartyState::CheckingR {
+            ref delta,
+            ref sign_r,
+            ref mut ris,
+        } = self.state
+        {
+            set_once(&mut ris[i], ri, i)?;
+            if ris.iter().any(|o| o.is_none()) {
+                return Ok(());
+            }
+            let ris: Vec<GE> = ris.iter().map(|o| o.unwrap()).collect();
+            (*delta, *sign_r, ris)
+        } else {
+            panic!("invalid state");
+        };
+        if check_r(&ris) {
+            self.sign_r = Some(sign_r);
+            self.ris = ris;
+            self.state = PartyState::CalculatingLocalSign { delta: delta };
+        } else {
+            let mut reveals: Vec<Option<Reveal>> = vec![None; self.n];
+            reveals[self.i] = Some(self.reveal_message());
+            self.state = PartyState::Revealing {
+                sign_r: sign_r,
+                ris: ris,
+                reveals: reveals,
+            };
+        }
+        Ok(())
+    }
+
+    // only after the R check failed: ki and γi are thrown away with this run
+    pub fn reveal_message(&self) -> Reveal {
+        Reveal {
+            k: self.ki.unwrap(),
+            gamma: self.ri.unwrap(),
+            kgamma: self.mta_shares.iter().map(|s| s.0).collect(),
+            gammak: self.mta_shares.iter().map(|s| s.1).collect(),
+        }
+    }
+
+    // Err with the blamed party once every reveal is in
+    pub fn on_reveal(&mut self, i: usize, reveal: Reveal) -> Result<()> {
+        if let PartyState::Revealing {
+            ref mut reveals, ..
+        } = self.state
+        {
+            reveals[i] = Some(reveal);
+            if reveals.iter().any(|o| o.is_none()) {
+                return Ok(());
+            }
         } else {
             panic!("invalid state");
         }
-        self.state = PartyState::CalculatingLocalSign {
-            delta: fe_from_bigint(&delta),
-        }
+        Err(self.blame())
     }
 
-    pub fn calc_local_signature(&mut self, m: &FE) {
-        let sign_r: GE;
-        let sign_si: FE;
-        if let PartyState::CalculatingLocalSign { ref delta } = self.state {
-            //R = (Π g^γ i)^(1/δ ) // = g^(1/k)
-            let sum_gri = self
-                .grs
-                .iter()
-                .fold(None, |acc, gr| match acc {
-                    Some(a) => Some(a + gr),
-                    None => Some(gr.clone()),
-                })
-                .unwrap();
-            sign_r = sum_gri * delta.invert();
-            //si = m*ki + r*σ i
-            let rx: FE = get_x(&sign_r);
-            sign_si = *m * &self.ki.unwrap() + rx * self.sigma_i.unwrap();
+    fn blame(&self) -> Error {
+        let (sign_r, ris, reveals) = if let PartyState::Revealing {
+            ref sign_r,
+            ref ris,
+            ref reveals,
+        } = self.state
+        {
+            let reveals: Vec<&Reveal> = reveals.iter().map(|r| r.as_ref().unwrap()).collect();
+            (sign_r, ris, reveals)
         } else {
             panic!("invalid state");
-        }
+        };
+        blame_reveals(&self.grs, &self.deltas, sign_r, ris, &reveals)
+    }
+
+    pub fn calc_local_signature(&mut self, m: &FE) {
+        let sign_r: GE = match self.state {
+            PartyState::CalculatingLocalSign { ref delta } => compute_r(&self.grs, delta),
+            PartyState::Presigned {} => self.sign_r.unwrap(),
+            _ => panic!("invalid state"),
+        };
+        let sign_si = local_si(m, &self.ki.unwrap(), &sign_r, &self.sigma_i.unwrap());
 
         self.sign_r = Some(sign_r);
         self.sign_rx = Some(get_x(&sign_r));
@@ -330,11 +1282,11 @@ impl Party {
         self.state = PartyState::BroadcastingSi { sis: sis };
     }
 
-    pub fn on_si(&mut self, i: usize, si: FE) {
+    pub fn on_si(&mut self, i: usize, si: FE) -> Result<()> {
         if let PartyState::BroadcastingSi { ref mut sis } = self.state {
-            sis[i] = Some(si);
+            set_once(&mut sis[i], si, i)?;
             if sis.iter().find(|o| o.is_none()).is_some() {
-                return;
+                return Ok(());
             }
 
             self.sign_s = sis.into_iter().fold(None, |acc, si| match acc {
@@ -346,6 +1298,187 @@ impl Party {
         }
 
         self.state = PartyState::Fin {};
+        Ok(())
+    }
+
+    // GG20: R^σi and g^σi with a proof that they share σi
+    pub fn sigma_message<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> (GE, GE, DLogEqProof) {
+        let sign_r = match self.state {
+            PartyState::CalculatingLocalSign { .. } => self.sign_r.unwrap(),
+            _ => panic!("invalid state"),
+        };
+        let sigma_i = self.sigma_i.unwrap();
+        let (s, t) = (sign_r * &sigma_i, self.g * &sigma_i);
+        let mut sigmas: Vec<Option<(GE, GE)>> = vec![None; self.n];
+        sigmas[self.i] = Some((s, t));
+        self.state = PartyState::CheckingSigma {
+            sign_r: sign_r,
+            sigmas: sigmas,
+        };
+        let proof = DLogEqProof::prove(&sigma_i, &sign_r, &self.salt(self.i), rng);
+        (s, t, proof)
+    }
+
+    // Presigned once Π R^σi = y, since R = g^(1/k) and σ = k·x
+    pub fn on_sigma(&mut self, i: usize, s: GE, t: GE, proof: DLogEqProof) -> Result<()> {
+        let salt = self.salt(i);
+        let (sign_r, sigmas) = if let PartyState::CheckingSigma {
+            ref sign_r,
+            ref mut sigmas,
+        } = self.state
+        {
+            if !proof.verify(&t, sign_r, &s, &salt) {
+                return Err(Error::InvalidDLogProof { party: i });
+            }
+            set_once(&mut sigmas[i], (s, t), i)?;
+            if sigmas.iter().any(|o| o.is_none()) {
+                return Ok(());
+            }
+            let sigmas: Vec<(GE, GE)> = sigmas.iter().map(|o| o.unwrap()).collect();
+            (*sign_r, sigmas)
+        } else {
+            panic!("invalid state");
+        };
+        let sum_s = sigmas[1..].iter().fold(sigmas[0].0, |acc, st| acc + st.0);
+        if sum_s == self.y.unwrap() {
+            self.sigma_rs = sigmas.iter().map(|st| st.0).collect();
+            self.state = PartyState::Presigned {};
+        } else {
+            let mut reveals: Vec<Option<SigmaReveal>> = vec![None; self.n];
+            reveals[self.i] = Some(self.sigma_reveal_message());
+            self.state = PartyState::RevealingSigma {
+                sign_r: sign_r,
+                sigmas: sigmas,
+                reveals: reveals,
+            };
+        }
+        Ok(())
+    }
+
+    // only after the R^σi check failed, so ki goes with an aborted run
+    pub fn sigma_reveal_message(&self) -> SigmaReveal {
+        let exp = |j: usize, x: &FE| if j == self.i { None } else { Some(self.g * x) };
+        SigmaReveal {
+            k: self.ki.unwrap(),
+            kw: (0..self.n)
+                .map(|j| exp(j, &self.sigma_shares[j].0))
+                .collect(),
+            wk: (0..self.n)
+                .map(|j| exp(j, &self.sigma_shares[j].1))
+                .collect(),
+            transcripts: (0..self.n)
+                .map(|j| {
+                    if j > self.i {
+                        self.transcripts[j].clone()
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            openings: self.openings.clone(),
+        }
+    }
+
+    // Err with the blamed party once every reveal is in
+    pub fn on_sigma_reveal(&mut self, i: usize, reveal: SigmaReveal) -> Result<()> {
+        if let PartyState::RevealingSigma {
+            ref mut reveals, ..
+        } = self.state
+        {
+            reveals[i] = Some(reveal);
+            if reveals.iter().any(|o| o.is_none()) {
+                return Ok(());
+            }
+        } else {
+            panic!("invalid state");
+        }
+        Err(self.blame_sigma())
+    }
+
+    fn blame_sigma(&self) -> Error {
+        let (sign_r, sigmas, reveals) = if let PartyState::RevealingSigma {
+            ref sign_r,
+            ref sigmas,
+            ref reveals,
+        } = self.state
+        {
+            let reveals: Vec<&SigmaReveal> = reveals.iter().map(|r| r.as_ref().unwrap()).collect();
+            (sign_r, sigmas, reveals)
+        } else {
+            panic!("invalid state");
+        };
+        let n = self.n;
+        for (j, r) in reveals.iter().enumerate() {
+            let well_formed = r.kw.len() == n
+                && r.wk.len() == n
+                && r.transcripts.len() == n
+                && r.openings.len() == n
+                && (0..n).all(|l| {
+                    (l == j) == r.kw[l].is_none()
+                        && (l == j) == r.wk[l].is_none()
+                        && (l > j) == r.transcripts[l].is_some()
+                        && (l == j) == r.openings[l].is_none()
+                });
+            if !well_formed || *sign_r * &r.k != self.ris[j] {
+                return Error::InvalidReveal { party: j };
+            }
+            // Alice checked Bob's signature when his answer came
+            let signed = ((j + 1)..n).all(|l| {
+                r.transcripts[l]
+                    .as_ref()
+                    .unwrap()
+                    .verify(j, l, &self.grs, &self.gws[l])
+            });
+            if !signed {
+                return Error::InvalidReveal { party: j };
+            }
+        }
+        // g^σj = g^(kj·wj) times its MtA shares
+        for (j, r) in reveals.iter().enumerate() {
+            let t = (0..n)
+                .filter(|l| *l != j)
+                .fold(self.gws[j] * &r.k, |acc, l| {
+                    acc + r.kw[l].unwrap() + r.wk[l].unwrap()
+                });
+            if t != sigmas[j].1 {
+                return Error::InvalidReveal { party: j };
+            }
+        }
+        // j holds k in k_j·w_l and opens its side of it against the transcript, so once
+        // that matches, a pair that does not add up is l's doing
+        for j in 0..n {
+            for l in (0..n).filter(|l| *l != j) {
+                let t = reveals[cmp::min(j, l)].transcripts[cmp::max(j, l)]
+                    .as_ref()
+                    .unwrap();
+                let kw = reveals[j].kw[l].unwrap();
+                let opening = reveals[j].openings[l].as_ref().unwrap();
+                if !check_opening(j < l, &reveals[j].k, &kw, t, opening) {
+                    return Error::InvalidReveal { party: j };
+                }
+                if kw + reveals[l].wk[j].unwrap() != self.gws[l] * &reveals[j].k {
+                    return Error::InvalidMta { party: l };
+                }
+            }
+        }
+        Error::InvalidState
+    }
+
+    // GG20: R^sj = (R^kj)^m · (R^σj)^r
+    pub fn check_si(&self, j: usize, sj: &FE) -> Result<()> {
+        let sign_r = self.sign_r.unwrap();
+        let rx = get_x(&sign_r);
+        if sign_r * sj != self.ris[j] * &self.digest.unwrap() + self.sigma_rs[j] * &rx {
+            return Err(Error::InvalidPartialSignature { party: j });
+        }
+        Ok(())
+    }
+
+    pub fn signature(&self) -> Option<Signature> {
+        match (self.sign_r, self.sign_s) {
+            (Some(r), Some(s)) => Some(Signature::new(&r, &s)),
+            _ => None,
+        }
     }
 
     pub fn verify(&self, m: &FE) -> bool {
@@ -358,6 +1491,311 @@ impl Party {
     }
 }
 
+impl SignMessage {
+    fn is_mta_request(&self) -> bool {
+        match self {
+            SignMessage::MtaRequest(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Party {
+    // requests come from the peers before i and responses from the ones after,
+    // each only once
+    fn expects_mta(&self, from: usize, request: bool) -> bool {
+        if from >= self.n || from == self.i || (from < self.i) != request {
+            return false;
+        }
+        match self.state {
+            PartyState::Mta { ref peers } => peers[from].kr.get_result().is_none(),
+            _ => false,
+        }
+    }
+
+    // the one online round, as soon as there is a message
+    fn online_message(&mut self) -> Vec<Outgoing<SignMessage>> {
+        match self.digest {
+            Some(m) => {
+                self.calc_local_signature(&m);
+                vec![Outgoing::Broadcast(SignMessage::Si(self.sign_si.unwrap()))]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // GG20 started without a message: what to send once it is known
+    pub fn sign_presigned<R: CryptoRng + RngCore>(
+        &mut self,
+        m: FE,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SignMessage>>> {
+        match self.state {
+            PartyState::Presigned {} => {}
+            _ => return Err(Error::InvalidState),
+        }
+        self.set_digest(m);
+        let mut out = self.online_message();
+        out.extend(replay_backlog(self, rng)?);
+        Ok(out)
+    }
+
+    // i is Alice towards every peer after it
+    fn try_finish_mta(&mut self) -> Vec<Outgoing<SignMessage>> {
+        self.on_mta_fin();
+        match self.delta_i {
+            Some(delta_i) if self.get_state_name() == "BroadcastingDelta" => {
+                vec![Outgoing::Broadcast(SignMessage::Delta(delta_i))]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Handler for Party {
+    type Message = SignMessage;
+    type Output = Signature;
+
+    fn start<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<Vec<Outgoing<SignMessage>>> {
+        if self.digest.is_none() && self.protocol == Protocol::Gg18 {
+            return Err(Error::InvalidState);
+        }
+        self.begin(rng);
+        let (gu, gr, gu_proof, gr_proof) = self.gri_message(rng);
+        let mut out = vec![Outgoing::Broadcast(SignMessage::Gr {
+            gu: gu,
+            gr: gr,
+            gu_proof: gu_proof,
+            gr_proof: gr_proof,
+        })];
+        out.extend(replay_backlog(self, rng)?);
+        Ok(out)
+    }
+
+    fn handle<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: SignMessage,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SignMessage>>> {
+        handle_buffered(self, from, msg, rng)
+    }
+
+    fn output(&self) -> Option<Signature> {
+        match self.state {
+            PartyState::Fin {} => self.signature(),
+            _ => None,
+        }
+    }
+
+    fn round(&self) -> &'static str {
+        self.get_state_name()
+    }
+
+    fn missing(&self) -> Vec<usize> {
+        let delivered: Vec<bool> = match self.state {
+            PartyState::BroadcastingGr { ref grs, .. } => grs.iter().map(|o| o.is_some()).collect(),
+            PartyState::Mta { ref peers } => {
+                peers.iter().map(|p| p.kr.get_result().is_some()).collect()
+            }
+            PartyState::BroadcastingDelta { ref deltas } => {
+                deltas.iter().map(|o| o.is_some()).collect()
+            }
+            PartyState::CheckingR { ref ris, .. } => ris.iter().map(|o| o.is_some()).collect(),
+            PartyState::Revealing { ref reveals, .. } => {
+                reveals.iter().map(|o| o.is_some()).collect()
+            }
+            PartyState::CheckingSigma { ref sigmas, .. } => {
+                sigmas.iter().map(|o| o.is_some()).collect()
+            }
+            PartyState::RevealingSigma { ref reveals, .. } => {
+                reveals.iter().map(|o| o.is_some()).collect()
+            }
+            PartyState::BroadcastingSi { ref sis } => sis.iter().map(|o| o.is_some()).collect(),
+            _ => return Vec::new(),
+        };
+        undelivered(self.i, &delivered)
+    }
+}
+
+impl Buffered for Party {
+    type Message = SignMessage;
+
+    fn party(&self) -> (usize, usize) {
+        (self.i, self.n)
+    }
+
+    fn round_of(msg: &SignMessage) -> usize {
+        match msg {
+            SignMessage::Gr { .. } => 1,
+            SignMessage::MtaRequest(_) | SignMessage::MtaResponse(..) => 2,
+            SignMessage::Delta(_) => 3,
+            SignMessage::Ri { .. } => 4,
+            SignMessage::Reveal(_) | SignMessage::SigmaR { .. } => 5,
+            SignMessage::SigmaReveal(_) => 6,
+            SignMessage::Si(_) => 7,
+        }
+    }
+
+    // the GG18 and GG20 branches after R share numbers; CalculatingLocalSign comes
+    // twice, so it takes the lower one
+    fn round_no(&self) -> usize {
+        match self.state {
+            PartyState::Void {} => 0,
+            PartyState::BroadcastingGr { .. } => 1,
+            PartyState::Mta { .. } => 2,
+            PartyState::BroadcastingDelta { .. } => 3,
+            PartyState::CalculatingLocalSign { .. } => 3,
+            PartyState::CheckingR { .. } => 4,
+            PartyState::Revealing { .. } | PartyState::CheckingSigma { .. } => 5,
+            PartyState::RevealingSigma { .. } | PartyState::Presigned {} => 6,
+            PartyState::BroadcastingSi { .. } => 7,
+            PartyState::Fin {} => 8,
+        }
+    }
+
+    fn backlog(&self) -> &Vec<(usize, SignMessage)> {
+        &self.backlog
+    }
+
+    fn backlog_mut(&mut self) -> &mut Vec<(usize, SignMessage)> {
+        &mut self.backlog
+    }
+
+    fn ready_for(&self, msg: &SignMessage) -> bool {
+        match (msg, &self.state) {
+            (SignMessage::Gr { .. }, PartyState::BroadcastingGr { .. }) => true,
+            (SignMessage::MtaRequest(_), PartyState::Mta { .. }) => true,
+            (SignMessage::MtaResponse(..), PartyState::Mta { .. }) => true,
+            (SignMessage::Delta(_), PartyState::BroadcastingDelta { .. }) => true,
+            (SignMessage::Ri { .. }, PartyState::CheckingR { .. }) => true,
+            (SignMessage::Reveal(_), PartyState::Revealing { .. }) => true,
+            (SignMessage::SigmaR { .. }, PartyState::CheckingSigma { .. }) => true,
+            (SignMessage::SigmaReveal(_), PartyState::RevealingSigma { .. }) => true,
+            (SignMessage::Si(_), PartyState::BroadcastingSi { .. }) => true,
+            _ => false,
+        }
+    }
+
+    fn handle_now<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: SignMessage,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SignMessage>>> {
+        let request = msg.is_mta_request();
+        match msg {
+            SignMessage::Gr {
+                gu,
+                gr,
+                gu_proof,
+                gr_proof,
+            } => {
+                self.on_gri(from, gu, gr, gu_proof, gr_proof)?;
+                if self.get_state_name() != "Mta" {
+                    return Ok(Vec::new());
+                }
+                let to: Vec<usize> = ((self.i + 1)..self.n).collect();
+                let mut out: Vec<Outgoing<SignMessage>> = self
+                    .on_mta_1_many(&to, rng)
+                    .into_iter()
+                    .map(|(j, reqs)| {
+                        let reqs = reqs
+                            .into_iter()
+                            .map(|(e, c, proof)| (e, c.0.into_owned(), proof))
+                            .collect();
+                        Outgoing::To(j, SignMessage::MtaRequest(reqs))
+                    })
+                    .collect();
+                // the last party only answers
+                out.extend(self.try_finish_mta());
+                Ok(out)
+            }
+            SignMessage::MtaRequest(_) | SignMessage::MtaResponse(..)
+                if !self.expects_mta(from, request) =>
+            {
+                // duplicated or misdirected
+                Ok(Vec::new())
+            }
+            SignMessage::MtaRequest(reqs) => {
+                let reqs = reqs
+                    .into_iter()
+                    .map(|(e, c, proof)| (e, to_ciphertext(c), proof))
+                    .collect();
+                let (cs, sig) = self.on_mta_2(from, reqs, rng)?;
+                let mut out = vec![Outgoing::To(
+                    from,
+                    SignMessage::MtaResponse(cs.iter().map(raw).collect(), sig),
+                )];
+                out.extend(self.try_finish_mta());
+                Ok(out)
+            }
+            SignMessage::MtaResponse(cs, sig) => {
+                self.on_mta_3(from, cs.into_iter().map(to_ciphertext).collect(), sig)?;
+                Ok(self.try_finish_mta())
+            }
+            SignMessage::Delta(delta) => {
+                self.on_delta_i(from, fe_to_bigint(&delta))?;
+                if self.get_state_name() != "CalculatingLocalSign" {
+                    return Ok(Vec::new());
+                }
+                let (ri, echo) = self.ri_message();
+                Ok(vec![Outgoing::Broadcast(SignMessage::Ri {
+                    ri: ri,
+                    echo: echo,
+                })])
+            }
+            SignMessage::Ri { ri, echo } => {
+                self.on_ri(from, ri, echo)?;
+                match self.get_state_name() {
+                    "CalculatingLocalSign" if self.protocol == Protocol::Gg20 => {
+                        let (s, t, proof) = self.sigma_message(rng);
+                        Ok(vec![Outgoing::Broadcast(SignMessage::SigmaR {
+                            s: s,
+                            t: t,
+                            proof: proof,
+                        })])
+                    }
+                    "CalculatingLocalSign" => Ok(self.online_message()),
+                    "Revealing" => Ok(vec![Outgoing::Broadcast(SignMessage::Reveal(
+                        self.reveal_message(),
+                    ))]),
+                    _ => Ok(Vec::new()),
+                }
+            }
+            SignMessage::Reveal(reveal) => {
+                self.on_reveal(from, reveal)?;
+                Ok(Vec::new())
+            }
+            SignMessage::SigmaR { s, t, proof } => {
+                self.on_sigma(from, s, t, proof)?;
+                match self.get_state_name() {
+                    "Presigned" => Ok(self.online_message()),
+                    "RevealingSigma" => Ok(vec![Outgoing::Broadcast(SignMessage::SigmaReveal(
+                        self.sigma_reveal_message(),
+                    ))]),
+                    _ => Ok(Vec::new()),
+                }
+            }
+            SignMessage::SigmaReveal(reveal) => {
+                self.on_sigma_reveal(from, reveal)?;
+                Ok(Vec::new())
+            }
+            SignMessage::Si(si) => {
+                if self.protocol == Protocol::Gg20 {
+                    self.check_si(from, &si)?;
+                }
+                self.on_si(from, si)?;
+                // a bad si cannot be attributed, but it must not come out as a signature
+                if self.get_state_name() == "Fin" && !self.verify(&self.digest.unwrap()) {
+                    return Err(Error::InvalidSignature);
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
 pub struct Etude {
     n: usize,
     parties: Vec<Party>,
@@ -373,109 +1811,190 @@ impl Etude {
         }
     }
 
+    // shares of any t+1 or more members of the committee
+    pub fn with_key_shares(shares: &[KeyShare]) -> Result<Self> {
+        let n = shares.len();
+        if n == 0 || n <= shares[0].t {
+            return Err(Error::NotEnoughShares);
+        }
+        let points: Vec<usize> = shares.iter().map(|s| s.i + 1).collect();
+        let parties = shares
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Party::with_key_share(i, n, s, &points))
+            .collect();
+        Ok(Self {
+            n: n,
+            parties: parties,
+        })
+    }
+
     pub fn sign(&mut self, message: &[u8]) {
-        self.phase1_begin();
-        self.phase1_broadcast_gr();
-        self.phase2_exchange_mta();
+        self.sign_digest(&digest_message(message));
+    }
+
+    pub fn sign_digest(&mut self, m: &FE) {
+        self.sign_digest_with_rng(m, &mut thread_rng());
+    }
+
+    pub fn sign_with_rng<R: CryptoRng + RngCore>(&mut self, message: &[u8], rng: &mut R) {
+        self.sign_digest_with_rng(&digest_message(message), rng);
+    }
+
+    // a seeded rng reproduces the whole run
+    pub fn sign_digest_with_rng<R: CryptoRng + RngCore>(&mut self, m: &FE, rng: &mut R) {
+        if self.parties[0].protocol == Protocol::Gg20 {
+            self.presign_with_rng(rng);
+            self.sign_presigned(m);
+            return;
+        }
+        self.phase1_begin(rng);
+        self.phase1_broadcast_gr(rng);
+        self.phase2_exchange_mta(rng);
+        self.phase3_broadcast_delta();
+        self.phase4_local_sign_digest(m);
+        self.phase5_gather_signatures();
+    }
+
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.parties
+            .iter_mut()
+            .for_each(|p| p.set_protocol(protocol));
+    }
+
+    // GG20 offline rounds, everything but the message
+    pub fn presign_with_rng<R: CryptoRng + RngCore>(&mut self, rng: &mut R) {
+        self.phase1_begin(rng);
+        self.phase1_broadcast_gr(rng);
+        self.phase2_exchange_mta(rng);
         self.phase3_broadcast_delta();
-        self.phase4_local_sign(&message);
+        self.presign_broadcast_ri();
+        self.presign_broadcast_sigma(rng);
+    }
+
+    // GG20 online round
+    pub fn sign_presigned(&mut self, m: &FE) {
+        self.parties.iter_mut().for_each(|p| p.set_digest(*m));
+        self.phase4_local_sign_digest(m);
         self.phase5_gather_signatures();
     }
 
-    fn phase1_begin(&mut self) {
+    pub fn presign_broadcast_ri(&mut self) {
+        let msgs: Vec<(GE, Vec<BigInt>)> =
+            self.parties.iter_mut().map(|p| p.ri_message()).collect();
+        for (i, (ri, echo)) in msgs.into_iter().enumerate() {
+            for j in (0..self.n).filter(|j| *j != i) {
+                self.parties[j].on_ri(i, ri, echo.clone()).unwrap();
+            }
+        }
+    }
+
+    pub fn presign_broadcast_sigma<R: CryptoRng + RngCore>(&mut self, rng: &mut R) {
+        let msgs: Vec<(GE, GE, DLogEqProof)> = self
+            .parties
+            .iter_mut()
+            .map(|p| p.sigma_message(rng))
+            .collect();
+        for (i, (s, t, proof)) in msgs.into_iter().enumerate() {
+            for j in (0..self.n).filter(|j| *j != i) {
+                self.parties[j].on_sigma(i, s, t, proof.clone()).unwrap();
+            }
+        }
+    }
+
+    pub fn set_derivation(&mut self, derivation: bip32::Derivation) -> Result<()> {
+        for p in self.parties.iter_mut() {
+            p.set_derivation(derivation.clone())?;
+        }
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> Option<GE> {
+        self.parties[0].y
+    }
+
+    pub fn root_public_key(&self) -> Option<GE> {
+        self.parties[0].root_y
+    }
+
+    pub fn signature(&self) -> Option<Signature> {
+        self.parties[0].signature()
+    }
+
+    pub fn phase1_begin<R: CryptoRng + RngCore>(&mut self, rng: &mut R) {
+        let mut session = [0u8; 32];
+        rng.fill_bytes(&mut session);
         self.parties.iter_mut().for_each(|p| {
-            p.begin();
+            p.set_session(&session);
+            p.begin(rng);
         });
     }
-    fn phase1_broadcast_gr(&mut self) {
+    pub fn phase1_broadcast_gr<R: CryptoRng + RngCore>(&mut self, rng: &mut R) {
         for i in 0..(self.n) {
             for j in 0..(self.n) {
                 if i != j {
-                    let gui = self.parties[i].gui.unwrap().clone();
-                    let gri = self.parties[i].gri.unwrap().clone();
-                    self.parties[j].on_gri(i, gui, gri);
+                    let (gui, gri, gu_proof, gr_proof) = self.parties[i].gri_message(rng);
+                    self.parties[j]
+                        .on_gri(i, gui, gri, gu_proof, gr_proof)
+                        .unwrap();
                 }
             }
         }
     }
 
-    fn phase2_exchange_mta(&mut self) {
-        for i in 0..(self.n - 1) {
-            let (left, right) = self.parties.as_mut_slice().split_at_mut(i + 1);
-            let pi = &mut left[i];
-            for j in (i + 1)..(self.n) {
-                let pj = &mut right[j - i - 1];
-                {
-                    let from_bob = {
-                        let from_alice = pi.on_mta_1(j);
-                        pj.on_mta_2(i, from_alice)
-                    };
-                    pi.on_mta_3(j, from_bob);
-                }
-                // check mta
-                #[allow(unused_variables)]
-                let ki_rj = match &pi.state {
-                    PartyState::Mta { ref kr, .. } => kr[j].get_result().unwrap().clone(),
-                    _ => {
-                        panic!("");
-                    }
-                };
-                #[allow(unused_variables)]
-                let rj_ki = match &pj.state {
-                    PartyState::Mta { ref rk, .. } => rk[i].get_result().unwrap().clone(),
-                    _ => {
-                        panic!("");
-                    }
-                };
-                if cfg!(feature = "debug") {
-                    println!("mta i={}, j={}", i, j);
-                    assert_eq!(pi.ki.unwrap(), fe_from_bigint(&ki_rj.0));
-                    assert_eq!(pj.ri.unwrap(), fe_from_bigint(&rj_ki.0));
-                    let m = pi.ki.clone().unwrap() * pj.ri.unwrap();
-                    let a = fe_from_bigint(&(ki_rj.1 + rj_ki.1));
-                    println!(
-                        "  pi.ki = {}",
-                        serde_json::to_string(&pi.ki.unwrap()).unwrap()
-                    );
-                    println!(
-                        "  pj.ri = {}",
-                        serde_json::to_string(&pj.ri.unwrap()).unwrap()
-                    );
-                    println!("      m = {}", serde_json::to_string(&m).unwrap());
-                    println!("  ki.rj = {}", serde_json::to_string(&ki_rj.1).unwrap());
-                    println!("  rj.ki = {}", serde_json::to_string(&rj_ki.1).unwrap());
-                    println!("      a = {}", serde_json::to_string(&a).unwrap());
-                    assert_eq!(m, a);
-                }
+    // every party is Alice towards the peers after it; each step runs in parallel
+    // over parties, and each party in parallel over its peers
+    pub fn phase2_exchange_mta<R: CryptoRng + RngCore>(&mut self, rng: &mut R) {
+        let n = self.n;
+        let mut rngs = fork_rngs(rng, n);
+        let requests: Vec<Vec<(usize, Vec<MtaRequest>)>> = self
+            .parties
+            .par_iter_mut()
+            .zip(rngs.par_iter_mut())
+            .map(|(p, rng)| {
+                let to: Vec<usize> = ((p.i + 1)..n).collect();
+                p.on_mta_1_many(&to, rng)
+            })
+            .collect();
+        let mut inbox: Vec<Vec<(usize, Vec<MtaRequest>)>> = (0..n).map(|_| Vec::new()).collect();
+        for (i, reqs) in requests.into_iter().enumerate() {
+            for (j, req) in reqs {
+                inbox[j].push((i, req));
+            }
+        }
+
+        let responses: Vec<Vec<(usize, Vec<mta::RawCiphertext>, Vec<u8>)>> = self
+            .parties
+            .par_iter_mut()
+            .zip(rngs.par_iter_mut())
+            .zip(inbox.into_par_iter())
+            .map(|((p, rng), inp)| p.on_mta_2_many(inp, rng).unwrap())
+            .collect();
+        let mut inbox: Vec<Vec<(usize, Vec<mta::RawCiphertext>, Vec<u8>)>> =
+            (0..n).map(|_| Vec::new()).collect();
+        for (j, resps) in responses.into_iter().enumerate() {
+            for (i, resp, sig) in resps {
+                inbox[i].push((j, resp, sig));
             }
         }
+        self.parties
+            .par_iter_mut()
+            .zip(inbox.into_par_iter())
+            .for_each(|(p, inp)| p.on_mta_3_many(inp).unwrap());
+
         if cfg!(feature = "debug") {
             for i in 0..self.n {
-                let ki = &self.parties[i].ki.unwrap();
+                let ki = self.parties[i].ki.unwrap();
                 for j in 0..self.n {
-                    let rj = &self.parties[j].ri.unwrap();
+                    let rj = self.parties[j].ri.unwrap();
                     if i != j {
-                        let ki_rj_a = match &self.parties[i].state {
-                            #[allow(unused_variables)]
-                            PartyState::Mta { kr, rk, ku, uk } => {
-                                kr[j].get_result().unwrap().1.clone()
-                            }
-                            _ => {
-                                panic!("");
-                            }
-                        };
-                        let ki_rj_b = match &self.parties[j].state {
-                            #[allow(unused_variables)]
-                            PartyState::Mta { kr, rk, ku, uk } => {
-                                rk[i].get_result().unwrap().1.clone()
-                            }
-                            _ => {
-                                panic!("");
-                            }
-                        };
+                        let ki_rj_a = self.parties[i].mta_peer(j).kr.get_result().unwrap();
+                        let ki_rj_b = self.parties[j].mta_peer(i).rk.get_result().unwrap();
+                        assert_eq!(ki, fe_from_bigint(ki_rj_a.0));
+                        assert_eq!(rj, fe_from_bigint(ki_rj_b.0));
                         println!("mtafin i={}, j={}", i, j);
-                        let m = ki.clone() * rj;
-                        let a = fe_from_bigint(&(ki_rj_a + ki_rj_b));
+                        let m = ki * rj;
+                        let a = *ki_rj_a.1 + ki_rj_b.1;
                         println!(
                             "mtafin m={}, a={}",
                             serde_json::to_string(&m).unwrap(),
@@ -492,30 +2011,35 @@ impl Etude {
         });
     }
 
-    fn phase3_broadcast_delta(&mut self) {
+    pub fn phase3_broadcast_delta(&mut self) {
         for i in 0..(self.n) {
             for j in 0..(self.n) {
                 if i != j {
                     let kri = self.parties[i].delta_i.clone().unwrap();
-                    self.parties[j].on_delta_i(i, fe_to_bigint(&kri));
+                    self.parties[j].on_delta_i(i, fe_to_bigint(&kri)).unwrap();
                 }
             }
         }
     }
 
-    fn phase4_local_sign(&mut self, msg: &[u8]) {
-        let m = digest_message(msg);
+    pub fn phase4_local_sign(&mut self, msg: &[u8]) {
+        self.phase4_local_sign_digest(&digest_message(msg));
+    }
+    pub fn phase4_local_sign_digest(&mut self, m: &FE) {
         for i in 0..(self.n) {
-            self.parties[i].calc_local_signature(&m);
+            self.parties[i].calc_local_signature(m);
         }
     }
 
-    fn phase5_gather_signatures(&mut self) {
+    pub fn phase5_gather_signatures(&mut self) {
         for i in 0..(self.n) {
             for j in 0..(self.n) {
                 if i != j {
                     let si = self.parties[i].sign_si.clone().unwrap();
-                    self.parties[j].on_si(i, si);
+                    if self.parties[j].protocol == Protocol::Gg20 {
+                        self.parties[j].check_si(i, &si).unwrap();
+                    }
+                    self.parties[j].on_si(i, si).unwrap();
                 }
             }
         }
@@ -535,9 +2059,11 @@ impl Etude {
     }
 
     fn verify_signature(&self, msg: &[u8]) -> bool {
-        let m = digest_message(msg);
+        self.verify_signature_digest(&digest_message(msg))
+    }
+    pub fn verify_signature_digest(&self, m: &FE) -> bool {
         for i in 0..(self.n) {
-            let r = self.parties[i].verify(&m);
+            let r = self.parties[i].verify(m);
             //println!("verify {}...{}", i, r);
             if r != true {
                 panic!("verify failed at {}", i);
@@ -700,7 +2226,8 @@ mod tests {
             parties: parties,
         };
 
-        gg18.phase1_broadcast_gr();
+        let mut rng = ::rand::thread_rng();
+        gg18.phase1_broadcast_gr(&mut rng);
         gg18.parties
             .iter()
             .for_each(|p| assert_eq!(p.get_state_name(), "Mta"));
@@ -711,7 +2238,7 @@ mod tests {
             }
         }
 
-        gg18.phase2_exchange_mta();
+        gg18.phase2_exchange_mta(&mut rng);
         gg18.parties
             .iter()
             .for_each(|p| assert_eq!(p.get_state_name(), "BroadcastingDelta"));
@@ -751,6 +2278,141 @@ mod tests {
         assert_eq!(true, gg18.verify_signature(&MESSAGE));
     }
 
+    #[test]
+    fn test_reject_dlog_proof() {
+        let mut p0 = etude::Party::new(0, 2);
+        let mut p1 = etude::Party::new(1, 2);
+        let mut rng = ::rand::thread_rng();
+        p0.begin(&mut rng);
+        p1.begin(&mut rng);
+        let (gu, gr, gu_proof, _) = p1.gri_message(&mut rng);
+        // γ proof replaced by the proof for u
+        assert_eq!(
+            Err(::error::Error::InvalidDLogProof { party: 1 }),
+            p0.on_gri(1, gu, gr, gu_proof.clone(), gu_proof)
+        );
+        assert_eq!(p0.get_state_name(), "BroadcastingGr");
+    }
+
+    #[test]
+    fn test_reject_wrong_public_share() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = ::keygen::KeyGen::new(1, 2).run_with_rng(&mut rng).unwrap();
+        let mut p0 = etude::Party::with_key_share(0, 2, &shares[0], &[1, 2]);
+        assert_eq!(p0.y, Some(shares[0].y));
+        // a g^u of its own choosing, with a valid proof, instead of the one keygen fixed
+        let mut p1 = etude::Party::new(1, 2);
+        p0.begin(&mut rng);
+        p1.begin(&mut rng);
+        let (gu, gr, gu_proof, gr_proof) = p1.gri_message(&mut rng);
+        assert_eq!(
+            Err(::error::Error::InvalidShare { party: 1 }),
+            p0.on_gri(1, gu, gr, gu_proof, gr_proof)
+        );
+    }
+
+    #[test]
+    fn test_reject_malformed_mta() {
+        let mut rng = ::rand::thread_rng();
+        let mut gg18 = etude::Etude::new(2);
+        gg18.phase1_begin(&mut rng);
+        gg18.phase1_broadcast_gr(&mut rng);
+        let mut reqs = gg18.parties[1].on_mta_1(0, &mut rng);
+        reqs.truncate(3);
+        let p0 = &mut gg18.parties[0];
+        assert_eq!(
+            p0.on_mta_2(1, reqs.clone(), &mut rng).err(),
+            Some(::error::Error::InvalidMta { party: 1 })
+        );
+        assert_eq!(
+            p0.on_mta_2(2, reqs, &mut rng).err(),
+            Some(::error::Error::UnknownParty)
+        );
+        assert_eq!(
+            p0.on_mta_3(1, vec![], vec![]),
+            Err(::error::Error::InvalidMta { party: 1 })
+        );
+    }
+
+    #[test]
+    fn test_reject_unsigned_mta_many() {
+        let mut rng = ::rand::thread_rng();
+        let mut gg18 = etude::Etude::new(2);
+        gg18.phase1_begin(&mut rng);
+        gg18.phase1_broadcast_gr(&mut rng);
+        let reqs = gg18.parties[0].on_mta_1_many(&[1], &mut rng);
+        let mut resps = gg18.parties[1]
+            .on_mta_2_many(vec![(0, reqs[0].1.clone())], &mut rng)
+            .unwrap();
+        assert_eq!(resps[0].0, 0);
+        let (_, cs, mut sig) = resps.pop().unwrap();
+        sig[63] ^= 1;
+        assert_eq!(
+            gg18.parties[0].on_mta_3_many(vec![(1, cs, sig)]),
+            Err(::error::Error::InvalidMessageSignature { party: 1 })
+        );
+    }
+
+    #[test]
+    fn test_reject_inconsistent_delta() {
+        let mut rng = ::rand::thread_rng();
+        let mut gg18 = etude::Etude::new(3);
+        gg18.phase1_begin(&mut rng);
+        gg18.phase1_broadcast_gr(&mut rng);
+        gg18.phase2_exchange_mta(&mut rng);
+        let d1 = etude::fe_to_bigint(&gg18.parties[1].delta_i.unwrap());
+        let p0 = &mut gg18.parties[0];
+        // a repeated delivery is fine, another value is not
+        assert_eq!(p0.on_delta_i(1, d1.clone()), Ok(()));
+        assert_eq!(p0.on_delta_i(1, d1.clone()), Ok(()));
+        assert_eq!(
+            p0.on_delta_i(1, d1 + BigInt::from(1)),
+            Err(::error::Error::InconsistentBroadcast { party: 1 })
+        );
+        assert_eq!(p0.get_state_name(), "BroadcastingDelta");
+    }
+
+    #[test]
+    fn test_seeded_run() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let run = || {
+            let mut gg18 = etude::Etude::new(2);
+            gg18.sign_with_rng(&message, &mut StdRng::seed_from_u64(39));
+            (gg18.public_key().unwrap(), gg18.signature().unwrap())
+        };
+        let (y, sig) = run();
+        assert!(sig.verify(&y, &etude::digest_message(&message)));
+        assert_eq!((y, sig), run());
+    }
+
+    #[test]
+    fn test_gg20() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut gg20 = etude::Etude::new(3);
+        gg20.set_protocol(etude::Protocol::Gg20);
+        // the message is only needed after the offline rounds
+        gg20.presign_with_rng(&mut ::rand::thread_rng());
+        for p in gg20.parties.iter() {
+            assert_eq!(p.get_state_name(), "Presigned");
+        }
+        gg20.sign_presigned(&etude::digest_message(&message));
+        assert_eq!(true, gg20.verify_r());
+        assert_eq!(true, gg20.verify_signature(&message));
+
+        // a bad si is pinned on its sender
+        let p = &gg20.parties[1];
+        let si = p.sign_si.unwrap() + etude::fe_from_bigint(&BigInt::from(1));
+        assert_eq!(
+            gg20.parties[0].check_si(1, &si),
+            Err(::error::Error::InvalidPartialSignature { party: 1 })
+        );
+        assert_eq!(gg20.parties[0].check_si(1, &p.sign_si.unwrap()), Ok(()));
+    }
+
     #[test]
     fn test_full_phases() {
         let message = "Miku-san maji tenshi!".as_bytes();
@@ -759,4 +2421,48 @@ mod tests {
         assert_eq!(true, gg18.verify_r());
         assert_eq!(true, gg18.verify_signature(&message));
     }
+
+    #[test]
+    fn test_out_of_order() {
+        use crate::network::{Handler, Outgoing};
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let n = 3;
+        let mut rng = ::rand::thread_rng();
+        let mut parties: Vec<etude::Party> = (0..n)
+            .map(|i| {
+                let mut p = etude::Party::new(i, n);
+                p.set_digest(etude::digest_message(&message));
+                p
+            })
+            .collect();
+        let post = |from: usize,
+                    out: Vec<Outgoing<etude::SignMessage>>,
+                    stack: &mut Vec<(usize, usize, etude::SignMessage)>| {
+            for o in out {
+                match o {
+                    Outgoing::Broadcast(msg) => {
+                        for j in (0..n).filter(|j| *j != from) {
+                            stack.push((from, j, msg.clone()));
+                        }
+                    }
+                    Outgoing::To(j, msg) => stack.push((from, j, msg)),
+                }
+            }
+        };
+        // last in, first out: every message overtakes the ones sent before it
+        let mut stack = Vec::new();
+        for i in 0..n {
+            let out = parties[i].start(&mut rng).unwrap();
+            post(i, out, &mut stack);
+        }
+        while let Some((from, to, msg)) = stack.pop() {
+            let out = parties[to].handle(from, msg, &mut rng).unwrap();
+            post(to, out, &mut stack);
+        }
+        let sig = parties[0].output().unwrap();
+        for p in parties.iter() {
+            assert_eq!(p.output(), Some(sig));
+            assert!(p.verify(&etude::digest_message(&message)));
+        }
+    }
 }
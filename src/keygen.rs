@@ -0,0 +1,620 @@
+extern crate curv;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::BigInt;
+use rand::{thread_rng, CryptoRng, RngCore};
+
+use crate::curve::{Curve, PointOps, ScalarOps, Secp256k1};
+use crate::error::{Error, Result};
+use crate::mta;
+use crate::network::{handle_buffered, replay_backlog, undelivered, Buffered, Handler, Outgoing};
+use crate::paillier_proof::{party_salt, PaillierKeyProof};
+use crate::party_id::{Committee, PartyId};
+use crate::ring_pedersen::{RingPedersenParams, RingPedersenProof};
+use crate::rng;
+use crate::vss;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct KeyShare<C: Curve = Secp256k1> {
+    pub t: usize,
+    pub n: usize,
+    pub i: usize, // Shamir evaluation point is i+1
+    pub xi: C::Scalar,
+    pub y: C::Point,
+    pub vss: Vec<C::Point>, // commitments to the joint polynomial, vss[0] == y
+    pub dec: mta::Dec,
+    pub eks: Vec<mta::Enc>,
+    pub auxs: Vec<RingPedersenParams>, // for range proofs towards each party
+}
+
+impl<C: Curve> KeyShare<C> {
+    // g^xj
+    pub fn public_share(&self, j: usize) -> C::Point {
+        vss::evaluate_in_exponent(&self.vss, j + 1, 0).unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct KeyGenBroadcast<C: Curve = Secp256k1> {
+    pub vss: Vec<C::Point>,
+    pub ek: mta::Enc,
+    pub ek_proof: PaillierKeyProof,
+    pub aux: RingPedersenParams,
+    pub aux_proof: RingPedersenProof,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum KeyGenMessage<C: Curve = Secp256k1> {
+    Commit(BigInt), // to the vss of the Broadcast that follows
+    Broadcast(KeyGenBroadcast<C>),
+    Share(C::Scalar), // sent privately
+}
+
+// the identity, which has no coordinates, hashes as (0, 0), on neither curve
+fn commitment<C: Curve>(i: usize, vss: &[C::Point]) -> BigInt {
+    let zero = BigInt::from(0);
+    let mut parts = vec![BigInt::from(i as u64)];
+    for p in vss {
+        parts.push(p.affine_x().unwrap_or_else(|| zero.clone()));
+        parts.push(p.affine_y().unwrap_or_else(|| zero.clone()));
+    }
+    HSha256::create_hash(&parts.iter().collect::<Vec<&BigInt>>())
+}
+
+pub struct Party<C: Curve = Secp256k1> {
+    pub t: usize,
+    pub n: usize,
+    pub i: usize,
+
+    ui: Option<C::Scalar>,
+    poly: Vec<C::Scalar>,
+    vss: Vec<C::Point>,
+    dec: Option<mta::Dec>,
+    ek_proof: Option<PaillierKeyProof>,
+    aux: Option<(RingPedersenParams, RingPedersenProof)>,
+    old: Option<KeyShare<C>>, // when refreshing
+
+    state: PartyState<C>,
+    backlog: Vec<(usize, KeyGenMessage<C>)>,
+}
+
+enum PartyState<C: Curve> {
+    Void {},
+    Committing {
+        commits: Vec<Option<BigInt>>,
+    },
+    BroadcastingVss {
+        commits: Vec<BigInt>,
+        vsss: Vec<Option<Vec<C::Point>>>,
+        eks: Vec<Option<mta::Enc>>,
+        auxs: Vec<Option<RingPedersenParams>>,
+    },
+    SendingShares {
+        vsss: Vec<Vec<C::Point>>,
+        eks: Vec<mta::Enc>,
+        auxs: Vec<RingPedersenParams>,
+        sijs: Vec<Option<C::Scalar>>,
+    },
+    Fin {
+        share: KeyShare<C>,
+    },
+}
+
+impl Party {
+    pub fn new(i: usize, t: usize, n: usize) -> Self {
+        Self::with_curve(i, t, n)
+    }
+
+    // the member `me` of a committee, whose share ends up at its evaluation point
+    pub fn for_member(committee: &Committee, me: &PartyId, t: usize) -> Result<Self> {
+        Ok(Self::new(committee.index_of(me)?, t, committee.n()))
+    }
+}
+
+impl<C: Curve> Party<C> {
+    pub fn get_state_name(&self) -> &'static str {
+        match self.state {
+            PartyState::Void {} => "Void",
+            PartyState::Committing { .. } => "Committing",
+            PartyState::BroadcastingVss { .. } => "BroadcastingVss",
+            PartyState::SendingShares { .. } => "SendingShares",
+            PartyState::Fin { .. } => "Fin",
+        }
+    }
+
+    pub fn with_curve(i: usize, t: usize, n: usize) -> Self {
+        Party {
+            t: t,
+            n: n,
+            i: i,
+            ui: None,
+            poly: Vec::new(),
+            vss: Vec::new(),
+            dec: None,
+            ek_proof: None,
+            aux: None,
+            old: None,
+            state: PartyState::Void {},
+            backlog: Vec::new(),
+        }
+    }
+
+    // The same rounds on a secret of zero: xi and the Paillier and aux keys change, y does
+    // not. g^0 cannot be sent, so the commitments are to a1..at only.
+    pub fn for_refresh(share: KeyShare<C>) -> Self {
+        let mut p = Self::with_curve(share.i, share.t, share.n);
+        p.old = Some(share);
+        p
+    }
+
+    // every party commits to its vss before any is opened, so the last one cannot pick its
+    // g^ui after seeing the others' and steer y
+    pub fn begin<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<BigInt> {
+        match self.state {
+            PartyState::Void {} => {}
+            _ => return Err(Error::InvalidState),
+        }
+        let ui: C::Scalar = match self.old {
+            Some(_) => C::Scalar::from_bigint(&BigInt::from(0)),
+            None => rng::random_scalar(rng),
+        };
+        let dec = mta::Dec::new(mta::plaintext_bits(&C::Scalar::order()), rng);
+        self.poly = vss::sample_polynomial(self.t, ui, rng);
+        self.vss = match self.old {
+            Some(_) => vss::commit(&self.poly[1..]),
+            None => vss::commit(&self.poly),
+        };
+        self.ui = Some(ui);
+        let (aux, aux_proof) = RingPedersenParams::generate(rng);
+        self.ek_proof = Some(PaillierKeyProof::prove(&dec, &party_salt(self.i)));
+        self.aux = Some((aux, aux_proof));
+        self.dec = Some(dec);
+
+        let c = commitment::<C>(self.i, &self.vss);
+        let mut commits = vec![None; self.n];
+        commits[self.i] = Some(c.clone());
+        self.state = PartyState::Committing { commits: commits };
+        Ok(c)
+    }
+
+    // once every commitment is in, the vss may be broadcast
+    pub fn on_commit(&mut self, from: usize, c: BigInt) -> Result<()> {
+        let commits = if let PartyState::Committing { ref mut commits } = self.state {
+            commits[from] = Some(c);
+            if commits.iter().any(|c| c.is_none()) {
+                return Ok(());
+            }
+            commits.iter().map(|c| c.clone().unwrap()).collect()
+        } else {
+            return Err(Error::InvalidState);
+        };
+        let mut vsss: Vec<Option<Vec<C::Point>>> = vec![None; self.n];
+        let mut eks: Vec<Option<mta::Enc>> = vec![None; self.n];
+        let mut auxs: Vec<Option<RingPedersenParams>> = vec![None; self.n];
+        vsss[self.i] = Some(self.vss.clone());
+        eks[self.i] = Some(self.dec.as_ref().unwrap().enc().clone());
+        auxs[self.i] = Some(self.aux.as_ref().unwrap().0.clone());
+        self.state = PartyState::BroadcastingVss {
+            commits: commits,
+            vsss: vsss,
+            eks: eks,
+            auxs: auxs,
+        };
+        Ok(())
+    }
+
+    pub fn broadcast_message(&self) -> KeyGenBroadcast<C> {
+        KeyGenBroadcast {
+            vss: self.vss.clone(),
+            ek: self.dec.as_ref().unwrap().enc().clone(),
+            ek_proof: self.ek_proof.clone().unwrap(),
+            aux: self.aux.as_ref().unwrap().0.clone(),
+            aux_proof: self.aux.as_ref().unwrap().1.clone(),
+        }
+    }
+
+    // f_i(j+1), sent privately to j
+    pub fn share_for(&self, j: usize) -> C::Scalar {
+        vss::evaluate(&self.poly, j + 1)
+    }
+
+    pub fn on_broadcast(&mut self, from: usize, msg: KeyGenBroadcast<C>) -> Result<()> {
+        let sij = self.share_for(self.i);
+        let (vsss, eks, auxs) = if let PartyState::BroadcastingVss {
+            ref commits,
+            ref mut vsss,
+            ref mut eks,
+            ref mut auxs,
+        } = self.state
+        {
+            let len = if self.old.is_some() {
+                self.t
+            } else {
+                self.t + 1
+            };
+            if msg.vss.len() != len || commitment::<C>(from, &msg.vss) != commits[from] {
+                return Err(Error::InvalidCommitment { party: from });
+            }
+            if !msg.ek_proof.verify(&msg.ek, &party_salt(from)) {
+                return Err(Error::InvalidPaillierKey { party: from });
+            }
+            if !msg.aux.verify(&msg.aux_proof) {
+                return Err(Error::InvalidAuxParams { party: from });
+            }
+            vsss[from] = Some(msg.vss);
+            eks[from] = Some(msg.ek);
+            auxs[from] = Some(msg.aux);
+            if vsss.iter().find(|o| o.is_none()).is_some() {
+                return Ok(());
+            }
+            (
+                vsss.iter().map(|o| o.clone().unwrap()).collect(),
+                eks.iter().map(|o| o.clone().unwrap()).collect(),
+                auxs.iter().map(|o| o.clone().unwrap()).collect(),
+            )
+        } else {
+            return Err(Error::InvalidState);
+        };
+        let mut sijs: Vec<Option<C::Scalar>> = vec![None; self.n];
+        sijs[self.i] = Some(sij);
+        self.state = PartyState::SendingShares {
+            vsss: vsss,
+            eks: eks,
+            auxs: auxs,
+            sijs: sijs,
+        };
+        Ok(())
+    }
+
+    pub fn on_share(&mut self, from: usize, sij: C::Scalar) -> Result<()> {
+        let share = if let PartyState::SendingShares {
+            ref vsss,
+            ref eks,
+            ref auxs,
+            ref mut sijs,
+        } = self.state
+        {
+            let valid = match self.old {
+                Some(_) => vss::validate_zero(&vsss[from], self.i + 1, &sij),
+                None => vss::validate(&vsss[from], self.i + 1, &sij),
+            };
+            if !valid {
+                return Err(Error::InvalidShare { party: from });
+            }
+            sijs[from] = Some(sij);
+            if sijs.iter().find(|o| o.is_none()).is_some() {
+                return Ok(());
+            }
+            let xi = sijs[1..]
+                .iter()
+                .fold(sijs[0].unwrap(), |acc, s| acc + s.unwrap());
+            let (xi, joint) = match self.old {
+                // the old commitments, with a1..at of every zero polynomial added
+                Some(ref old) => {
+                    let mut joint = old.vss.clone();
+                    for k in 1..joint.len() {
+                        let mut cs: Vec<C::Point> = vsss.iter().map(|v| v[k - 1]).collect();
+                        cs.push(joint[k]);
+                        joint[k] = vss::sum_points(&cs);
+                    }
+                    (old.xi + xi, joint)
+                }
+                None => {
+                    let joint = (0..(self.t + 1))
+                        .map(|k| {
+                            let cs: Vec<C::Point> = vsss.iter().map(|v| v[k]).collect();
+                            vss::sum_points(&cs)
+                        })
+                        .collect();
+                    (xi, joint)
+                }
+            };
+            if C::Point::base_point() * xi
+                != vss::evaluate_in_exponent(&joint, self.i + 1, 0).unwrap()
+            {
+                return Err(Error::InvalidPublicKey);
+            }
+            KeyShare {
+                t: self.t,
+                n: self.n,
+                i: self.i,
+                xi: xi,
+                y: joint[0],
+                vss: joint,
+                dec: self.dec.clone().unwrap(),
+                eks: eks.clone(),
+                auxs: auxs.clone(),
+            }
+        } else {
+            return Err(Error::InvalidState);
+        };
+        self.state = PartyState::Fin { share: share };
+        Ok(())
+    }
+
+    pub fn key_share(&self) -> Option<&KeyShare<C>> {
+        match self.state {
+            PartyState::Fin { ref share } => Some(share),
+            _ => None,
+        }
+    }
+}
+
+impl<C: Curve> Handler for Party<C> {
+    type Message = KeyGenMessage<C>;
+    type Output = KeyShare<C>;
+
+    fn start<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<KeyGenMessage<C>>>> {
+        let mut out = vec![Outgoing::Broadcast(KeyGenMessage::Commit(self.begin(rng)?))];
+        out.extend(replay_backlog(self, rng)?);
+        Ok(out)
+    }
+
+    fn handle<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: KeyGenMessage<C>,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<KeyGenMessage<C>>>> {
+        handle_buffered(self, from, msg, rng)
+    }
+
+    fn output(&self) -> Option<KeyShare<C>> {
+        self.key_share().cloned()
+    }
+
+    fn round(&self) -> &'static str {
+        self.get_state_name()
+    }
+
+    fn missing(&self) -> Vec<usize> {
+        let delivered: Vec<bool> = match self.state {
+            PartyState::Committing { ref commits } => commits.iter().map(|o| o.is_some()).collect(),
+            PartyState::BroadcastingVss { ref vsss, .. } => {
+                vsss.iter().map(|o| o.is_some()).collect()
+            }
+            PartyState::SendingShares { ref sijs, .. } => {
+                sijs.iter().map(|o| o.is_some()).collect()
+            }
+            _ => return Vec::new(),
+        };
+        undelivered(self.i, &delivered)
+    }
+}
+
+impl<C: Curve> Buffered for Party<C> {
+    type Message = KeyGenMessage<C>;
+
+    fn party(&self) -> (usize, usize) {
+        (self.i, self.n)
+    }
+
+    fn round_of(msg: &KeyGenMessage<C>) -> usize {
+        match msg {
+            KeyGenMessage::Commit(_) => 1,
+            KeyGenMessage::Broadcast(_) => 2,
+            KeyGenMessage::Share(_) => 3,
+        }
+    }
+
+    fn round_no(&self) -> usize {
+        match self.state {
+            PartyState::Void {} => 0,
+            PartyState::Committing { .. } => 1,
+            PartyState::BroadcastingVss { .. } => 2,
+            PartyState::SendingShares { .. } => 3,
+            PartyState::Fin { .. } => 4,
+        }
+    }
+
+    fn backlog(&self) -> &Vec<(usize, KeyGenMessage<C>)> {
+        &self.backlog
+    }
+
+    fn backlog_mut(&mut self) -> &mut Vec<(usize, KeyGenMessage<C>)> {
+        &mut self.backlog
+    }
+
+    fn ready_for(&self, msg: &KeyGenMessage<C>) -> bool {
+        match (msg, &self.state) {
+            (KeyGenMessage::Commit(_), PartyState::Committing { .. }) => true,
+            (KeyGenMessage::Broadcast(_), PartyState::BroadcastingVss { .. }) => true,
+            (KeyGenMessage::Share(_), PartyState::SendingShares { .. }) => true,
+            _ => false,
+        }
+    }
+
+    fn handle_now<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: KeyGenMessage<C>,
+        _rng: &mut R,
+    ) -> Result<Vec<Outgoing<KeyGenMessage<C>>>> {
+        match msg {
+            KeyGenMessage::Commit(c) => {
+                self.on_commit(from, c)?;
+                if let PartyState::BroadcastingVss { .. } = self.state {
+                    let msg = KeyGenMessage::Broadcast(self.broadcast_message());
+                    return Ok(vec![Outgoing::Broadcast(msg)]);
+                }
+            }
+            KeyGenMessage::Broadcast(msg) => {
+                self.on_broadcast(from, msg)?;
+                if let PartyState::SendingShares { .. } = self.state {
+                    return Ok((0..self.n)
+                        .filter(|j| *j != self.i)
+                        .map(|j| Outgoing::To(j, KeyGenMessage::Share(self.share_for(j))))
+                        .collect());
+                }
+            }
+            KeyGenMessage::Share(sij) => self.on_share(from, sij)?,
+        }
+        Ok(Vec::new())
+    }
+}
+
+pub struct KeyGen<C: Curve = Secp256k1> {
+    n: usize,
+    parties: Vec<Party<C>>,
+}
+
+impl KeyGen {
+    pub fn new(t: usize, n: usize) -> Self {
+        Self::with_curve(t, n)
+    }
+}
+
+impl<C: Curve> KeyGen<C> {
+    pub fn with_curve(t: usize, n: usize) -> Self {
+        Self::with_parties((0..n).map(|i| Party::with_curve(i, t, n)).collect())
+    }
+
+    // parties 0..n, e.g. from Party::for_refresh
+    pub fn with_parties(parties: Vec<Party<C>>) -> Self {
+        Self {
+            n: parties.len(),
+            parties: parties,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<Vec<KeyShare<C>>> {
+        self.run_with_rng(&mut thread_rng())
+    }
+
+    // a seeded rng reproduces the whole run
+    pub fn run_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<KeyShare<C>>> {
+        let mut commits = Vec::with_capacity(self.n);
+        for p in self.parties.iter_mut() {
+            commits.push(p.begin(rng)?);
+        }
+        for i in 0..(self.n) {
+            for j in 0..(self.n) {
+                if i != j {
+                    self.parties[j].on_commit(i, commits[i].clone())?;
+                }
+            }
+        }
+        let msgs: Vec<KeyGenBroadcast<C>> =
+            self.parties.iter().map(|p| p.broadcast_message()).collect();
+        for i in 0..(self.n) {
+            for j in 0..(self.n) {
+                if i != j {
+                    self.parties[j].on_broadcast(i, msgs[i].clone())?;
+                }
+            }
+        }
+        for i in 0..(self.n) {
+            for j in 0..(self.n) {
+                if i != j {
+                    let sij = self.parties[i].share_for(j);
+                    self.parties[j].on_share(i, sij)?;
+                }
+            }
+        }
+        Ok(self
+            .parties
+            .iter()
+            .map(|p| p.key_share().unwrap().clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::{FE, GE};
+    use crate::curve::{P256Point, PointOps, P256};
+    use crate::error::Error;
+    use crate::etude::Etude;
+    use crate::keygen::*;
+    use crate::vss;
+
+    #[test]
+    fn test_keygen() {
+        let shares = KeyGen::new(1, 3).run().unwrap();
+        let y = shares[0].y;
+        for s in shares.iter() {
+            assert_eq!(y, s.y);
+            assert_eq!(GE::generator() * s.xi, shares[0].public_share(s.i));
+        }
+        let xs = [1, 3];
+        let x = shares[0].xi * vss::lagrange_coefficient::<FE>(1, &xs)
+            + shares[2].xi * vss::lagrange_coefficient::<FE>(3, &xs);
+        assert_eq!(y, GE::generator() * x);
+    }
+
+    #[test]
+    fn test_keygen_p256() {
+        let shares = KeyGen::<P256>::with_curve(1, 3).run().unwrap();
+        let g = P256Point::base_point();
+        for s in shares.iter() {
+            assert_eq!(g * s.xi, shares[0].public_share(s.i));
+        }
+        let xs = [2, 3];
+        let x = shares[1].xi * vss::lagrange_coefficient(2, &xs)
+            + shares[2].xi * vss::lagrange_coefficient(3, &xs);
+        assert_eq!(shares[0].y, g * x);
+    }
+
+    #[test]
+    fn test_sign_with_key_shares() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let shares = KeyGen::new(1, 3).run().unwrap();
+        let mut gg18 = Etude::with_key_shares(&shares[1..]).unwrap();
+        gg18.sign(&message);
+        assert_eq!(Some(shares[0].y), gg18.public_key());
+        assert!(gg18
+            .signature()
+            .unwrap()
+            .verify(&shares[0].y, &::etude::digest_message(&message)));
+    }
+
+    #[test]
+    fn test_reject_paillier_key() {
+        let mut rng = ::rand::thread_rng();
+        let mut p0 = Party::new(0, 1, 2);
+        let mut p1 = Party::new(1, 1, 2);
+        let c0 = p0.begin(&mut rng).unwrap();
+        let c1 = p1.begin(&mut rng).unwrap();
+        p0.on_commit(1, c1).unwrap();
+        p1.on_commit(0, c0).unwrap();
+        let mut msg = p1.broadcast_message();
+        msg.ek = p0.broadcast_message().ek;
+        assert_eq!(
+            Err(Error::InvalidPaillierKey { party: 1 }),
+            p0.on_broadcast(1, msg)
+        );
+    }
+
+    #[test]
+    fn test_reject_uncommitted_vss() {
+        let mut rng = ::rand::thread_rng();
+        let mut p0 = Party::new(0, 1, 2);
+        let mut p1 = Party::new(1, 1, 2);
+        p0.begin(&mut rng).unwrap();
+        let c1 = p1.begin(&mut rng).unwrap();
+        // the broadcast comes too early
+        assert_eq!(
+            Err(Error::InvalidState),
+            p0.on_broadcast(1, p1.broadcast_message())
+        );
+        p0.on_commit(1, c1).unwrap();
+        // a g^u1 chosen after the commitment
+        let mut msg = p1.broadcast_message();
+        msg.vss[0] = msg.vss[0] + GE::generator();
+        assert_eq!(
+            Err(Error::InvalidCommitment { party: 1 }),
+            p0.on_broadcast(1, msg)
+        );
+        assert_eq!(Ok(()), p0.on_broadcast(1, p1.broadcast_message()));
+    }
+}
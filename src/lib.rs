@@ -1,12 +1,39 @@
 //#[macro_use]
 //extern crate lazy_static;
-//#[macro_use]
-//extern crate serde_derive;
-//extern crate serde;
-//extern crate serde_json;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
 
 extern crate curv;
+extern crate hmac;
 extern crate paillier;
+extern crate rand;
+extern crate rayon;
+extern crate sha2;
+extern crate tiny_keccak;
 
+pub mod airgap;
+pub mod auth;
+pub mod bip32;
+pub mod channel;
+pub mod curve;
+pub mod dlog_proof;
+pub mod error;
+pub mod eth;
 pub mod etude;
+pub mod keygen;
 pub mod mta;
+pub mod network;
+pub mod paillier_proof;
+pub mod party_id;
+pub mod refresh;
+pub mod relay;
+pub mod reshare;
+pub mod ring_pedersen;
+pub mod rng;
+pub mod rounds;
+pub mod schnorr;
+pub mod simulator;
+pub mod two_party;
+pub mod vss;
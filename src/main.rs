@@ -1,3 +1,569 @@
+extern crate clap;
+extern crate curv;
+extern crate gg18_etude;
+extern crate rand;
+extern crate serde_json;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use curv::elliptic::curves::traits::ECScalar;
+use curv::{BigInt, FE, GE};
+use std::fs;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use gg18_etude::airgap::{self, AirGapped};
+use gg18_etude::auth::IdentityKey;
+use gg18_etude::curve;
+use gg18_etude::eth::{self, to_hex};
+use gg18_etude::etude::{self, digest_message, Etude, Protocol, Signature};
+use gg18_etude::keygen::{self, KeyGen, KeyShare};
+use gg18_etude::network::{self, Network};
+use gg18_etude::party_id::PartyId;
+use gg18_etude::relay;
+
+type CliResult<T> = Result<T, String>;
+
+fn from_hex(s: &str) -> CliResult<Vec<u8>> {
+    eth::from_hex(s.trim_start_matches("0x")).ok_or_else(|| format!("not hex: {}", s))
+}
+
+// compressed SEC1
+fn encode_point(p: &GE) -> String {
+    to_hex(&curve::compress(p))
+}
+
+fn decode_point(s: &str) -> CliResult<GE> {
+    curve::decompress(&from_hex(s)?).ok_or_else(|| "expected a compressed public key".to_string())
+}
+
+// r || s || recid
+fn encode_signature(sig: &Signature) -> String {
+    let mut v = curve::to_bytes32(&sig.r.to_big_int()).to_vec();
+    v.extend_from_slice(&curve::to_bytes32(&sig.s.to_big_int()));
+    v.push(sig.recid);
+    to_hex(&v)
+}
+
+fn decode_signature(s: &str) -> CliResult<Signature> {
+    let bytes = from_hex(s)?;
+    if bytes.len() != 65 {
+        return Err("expected 65 bytes of r, s and recid".to_string());
+    }
+    let q = FE::q();
+    let r = BigInt::from(&bytes[..32]);
+    let s = BigInt::from(&bytes[32..64]);
+    if r >= q || s >= q {
+        return Err("signature out of range".to_string());
+    }
+    Ok(Signature {
+        r: ECScalar::from(&r),
+        s: ECScalar::from(&s),
+        recid: bytes[64],
+    })
+}
+
+fn read_share(path: &str) -> CliResult<KeyShare> {
+    let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn write_share(dir: &str, share: &KeyShare) -> CliResult<()> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let path = Path::new(dir).join(format!("share_{}.json", share.i));
+    // owner-only, like the air-gapped state
+    airgap::save(&path, share).map_err(|e| e.to_string())?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+fn parse_usize(m: &ArgMatches, name: &str) -> CliResult<usize> {
+    m.value_of(name)
+        .unwrap()
+        .parse()
+        .map_err(|_| format!("--{} must be a number", name))
+}
+
+fn round_timeout(m: &ArgMatches) -> CliResult<Duration> {
+    match m.value_of("timeout") {
+        Some(_) => Ok(Duration::from_secs(parse_usize(m, "timeout")? as u64)),
+        None => Ok(network::ROUND_TIMEOUT),
+    }
+}
+
+fn protocol(m: &ArgMatches) -> Protocol {
+    match m.value_of("protocol") {
+        Some("gg20") => Protocol::Gg20,
+        _ => Protocol::Gg18,
+    }
+}
+
+fn addrs(m: &ArgMatches) -> Option<Vec<String>> {
+    m.values_of("addrs")
+        .map(|v| v.map(|s| s.to_string()).collect())
+}
+
+fn keygen(m: &ArgMatches) -> CliResult<()> {
+    let n = parse_usize(m, "n")?;
+    let t = parse_usize(m, "t")?;
+    let dir = m.value_of("out-dir").unwrap();
+    if t >= n {
+        return Err("t must be smaller than n".to_string());
+    }
+    match addrs(m) {
+        None => {
+            let shares = KeyGen::new(t, n).run().map_err(|e| e.to_string())?;
+            for s in shares.iter() {
+                write_share(dir, s)?;
+            }
+        }
+        Some(addrs) => {
+            let i = parse_usize(m, "index")?;
+            if addrs.len() != n || i >= n {
+                return Err("--addrs must list n addresses and --index be below n".to_string());
+            }
+            let mut net = Network::connect(i, &addrs).map_err(|e| e.to_string())?;
+            let mut party = keygen::Party::new(i, t, n);
+            let timeout = round_timeout(m)?;
+            let share =
+                network::run_with_timeout(&mut party, &mut net, &mut rand::thread_rng(), timeout)
+                    .map_err(|e| e.to_string())?;
+            write_share(dir, &share)?;
+        }
+    }
+    Ok(())
+}
+
+// the position of the share among the signers, and their evaluation points
+fn signer_points(signers: &[usize], share: &KeyShare) -> CliResult<(usize, Vec<usize>)> {
+    for (k, s) in signers.iter().enumerate() {
+        if *s >= share.n {
+            return Err(format!("--signers must be below {}", share.n));
+        }
+        if signers[..k].contains(s) {
+            return Err(format!("{} is twice in --signers", s));
+        }
+    }
+    let i = signers
+        .iter()
+        .position(|s| *s == share.i)
+        .ok_or("the share is not one of --signers")?;
+    Ok((i, signers.iter().map(|s| s + 1).collect()))
+}
+
+fn sign(m: &ArgMatches) -> CliResult<()> {
+    let shares = m
+        .values_of("share")
+        .unwrap()
+        .map(read_share)
+        .collect::<CliResult<Vec<KeyShare>>>()?;
+    let digest = digest_message(m.value_of("message").unwrap().as_bytes());
+    let sig = match addrs(m) {
+        None => {
+            if shares.iter().any(|s| s.y != shares[0].y) {
+                return Err("the --share files are of different keys".to_string());
+            }
+            let mut gg18 = Etude::with_key_shares(&shares).map_err(|e| e.to_string())?;
+            gg18.set_protocol(protocol(m));
+            gg18.sign_digest(&digest);
+            gg18.signature().unwrap()
+        }
+        Some(addrs) => {
+            // --signers are the indices of the shares taking part, in the order of --addrs
+            let share = &shares[0];
+            let signers = m
+                .values_of("signers")
+                .ok_or("--signers is needed with --addrs")?
+                .map(|s| s.parse::<usize>().map_err(|e| e.to_string()))
+                .collect::<CliResult<Vec<usize>>>()?;
+            if signers.len() != addrs.len() || signers.len() <= share.t {
+                return Err(
+                    "--signers and --addrs must list the same t+1 or more parties".to_string(),
+                );
+            }
+            let (i, points) = signer_points(&signers, share)?;
+            let mut party = etude::Party::with_key_share(i, signers.len(), share, &points);
+            party.set_digest(digest);
+            party.set_protocol(protocol(m));
+            let mut net = Network::connect(i, &addrs).map_err(|e| e.to_string())?;
+            let timeout = round_timeout(m)?;
+            network::run_with_timeout(&mut party, &mut net, &mut rand::thread_rng(), timeout)
+                .map_err(|e| e.to_string())?
+        }
+    };
+    println!("{}", encode_signature(&sig));
+    Ok(())
+}
+
+fn verify(m: &ArgMatches) -> CliResult<()> {
+    let y = decode_point(m.value_of("pubkey").unwrap())?;
+    let sig = decode_signature(m.value_of("sig").unwrap())?;
+    let digest = digest_message(m.value_of("message").unwrap().as_bytes());
+    if sig.verify(&y, &digest) {
+        println!("valid");
+        Ok(())
+    } else {
+        Err("invalid signature".to_string())
+    }
+}
+
+fn pubkey(m: &ArgMatches) -> CliResult<()> {
+    let share = read_share(m.value_of("share").unwrap())?;
+    println!("{}", encode_point(&share.y));
+    Ok(())
+}
+
+// public data only; xi and the Paillier primes are not printed
+fn inspect(m: &ArgMatches) -> CliResult<()> {
+    let share = read_share(m.value_of("share").unwrap())?;
+    println!("index: {}", share.i);
+    println!("threshold: {} of {}", share.t + 1, share.n);
+    println!("public key: {}", encode_point(&share.y));
+    for j in 0..share.n {
+        println!(
+            "party {}: g^x = {}, paillier N = {} bits",
+            j,
+            encode_point(&share.public_share(j)),
+            share.eks[j].n().bit_length()
+        );
+    }
+    Ok(())
+}
+
+// Air-gapped signing: the party is kept in the --state file between rounds, and its
+// messages go out and come in as files signed with each signer's identity key.
+fn round_identity(m: &ArgMatches) -> CliResult<()> {
+    let key = IdentityKey::new(&mut rand::thread_rng());
+    airgap::save(m.value_of("out").unwrap(), &key).map_err(|e| e.to_string())?;
+    println!("{}", key.party_id());
+    Ok(())
+}
+
+fn round_start(m: &ArgMatches) -> CliResult<()> {
+    let share = read_share(m.value_of("share").unwrap())?;
+    let signers = m
+        .values_of("signers")
+        .unwrap()
+        .map(|s| s.parse::<usize>().map_err(|e| e.to_string()))
+        .collect::<CliResult<Vec<usize>>>()?;
+    if signers.len() <= share.t {
+        return Err(format!("{} signers are needed", share.t + 1));
+    }
+    let peers = m
+        .values_of("peers")
+        .unwrap()
+        .map(|s| from_hex(s).map(PartyId))
+        .collect::<CliResult<Vec<PartyId>>>()?;
+    if peers.len() != signers.len() {
+        return Err("--peers must name one identity per signer".to_string());
+    }
+    let key: IdentityKey =
+        airgap::load(m.value_of("identity").unwrap()).map_err(|e| e.to_string())?;
+    let (i, points) = signer_points(&signers, &share)?;
+    if peers[i] != key.party_id() {
+        return Err("the identity is not this signer's in --peers".to_string());
+    }
+    let mut party = etude::Party::with_key_share(i, signers.len(), &share, &points);
+    party.set_digest(digest_message(m.value_of("message").unwrap().as_bytes()));
+    party.set_protocol(protocol(m));
+    let session = m.value_of("session").unwrap();
+    party.set_session(session.as_bytes());
+    let mut party = AirGapped::new(party, session, key, &peers).map_err(|e| e.to_string())?;
+    party
+        .start(&mut rand::thread_rng())
+        .map_err(|e| e.to_string())?;
+    airgap::save_state(m.value_of("state").unwrap(), &party).map_err(|e| e.to_string())
+}
+
+fn round_export(m: &ArgMatches) -> CliResult<()> {
+    let state = m.value_of("state").unwrap();
+    let mut party: AirGapped<etude::Party> =
+        airgap::load_state(state).map_err(|e| e.to_string())?;
+    let out = m.value_of("out").unwrap();
+    if party
+        .export_file(out, &mut rand::thread_rng())
+        .map_err(|e| e.to_string())?
+    {
+        println!("{}", out);
+    } else {
+        println!("nothing to send");
+    }
+    airgap::save_state(state, &party).map_err(|e| e.to_string())
+}
+
+// prints the signature once the last round is in
+fn round_import(m: &ArgMatches) -> CliResult<()> {
+    let state = m.value_of("state").unwrap();
+    let mut party: AirGapped<etude::Party> =
+        airgap::load_state(state).map_err(|e| e.to_string())?;
+    let mut sig = None;
+    for path in m.values_of("in").unwrap() {
+        sig = party
+            .import_file(path, &mut rand::thread_rng())
+            .map_err(|e| e.to_string())?;
+    }
+    airgap::save_state(state, &party).map_err(|e| e.to_string())?;
+    match sig {
+        Some(sig) => println!("{}", encode_signature(&sig)),
+        None => println!("waiting in {}", party.party().get_state_name()),
+    }
+    Ok(())
+}
+
+// the mailbox for parties that sign at different times, in memory unless --store is given
+fn relay(m: &ArgMatches) -> CliResult<()> {
+    let addr = m.value_of("listen").unwrap();
+    let mailbox = match m.value_of("store") {
+        Some(path) => relay::Mailbox::open(path).map_err(|e| e.to_string())?,
+        None => relay::Mailbox::new(),
+    };
+    let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+    relay::serve(listener, mailbox).map_err(|e| e.to_string())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let share = Arg::with_name("share")
+        .long("share")
+        .takes_value(true)
+        .required(true)
+        .help("key share file");
+    let addrs = Arg::with_name("addrs")
+        .long("addrs")
+        .takes_value(true)
+        .use_delimiter(true)
+        .help(
+            "host:port of every party; runs as one networked party instead of a local simulation",
+        );
+    let timeout = Arg::with_name("timeout")
+        .long("timeout")
+        .takes_value(true)
+        .requires("addrs")
+        .help("seconds to wait for the peers in each round");
+    let state = Arg::with_name("state")
+        .long("state")
+        .takes_value(true)
+        .required(true)
+        .help("file the party is kept in between rounds; it holds secrets");
+    let matches = App::new("gg18-etude")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("keygen")
+                .about("generates t-of-n key shares")
+                .arg(
+                    Arg::with_name("n")
+                        .long("n")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("t")
+                        .long("t")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("out-dir")
+                        .long("out-dir")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(addrs.clone())
+                .arg(timeout.clone())
+                .arg(
+                    Arg::with_name("index")
+                        .long("index")
+                        .takes_value(true)
+                        .requires("addrs")
+                        .help("own position in --addrs"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sign")
+                .about("signs a message with t+1 shares, or with one share over the network")
+                .arg(share.clone().multiple(true).number_of_values(1))
+                .arg(
+                    Arg::with_name("message")
+                        .long("message")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(addrs.clone())
+                .arg(timeout.clone())
+                .arg(
+                    Arg::with_name("protocol")
+                        .long("protocol")
+                        .takes_value(true)
+                        .possible_values(&["gg18", "gg20"])
+                        .default_value("gg18")
+                        .help("gg20 names the party behind a bad signature share"),
+                )
+                .arg(
+                    Arg::with_name("signers")
+                        .long("signers")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .requires("addrs")
+                        .help("share indices of the signers, in the order of --addrs"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("verifies a signature")
+                .arg(
+                    Arg::with_name("pubkey")
+                        .long("pubkey")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("sig")
+                        .long("sig")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("message")
+                        .long("message")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pubkey")
+                .about("prints the joint public key")
+                .arg(share.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("prints the public data of a key share")
+                .arg(share.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("round")
+                .about("signs on an air-gapped machine, one round at a time through files")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("identity")
+                        .about("creates an identity key to sign the files with")
+                        .arg(
+                            Arg::with_name("out")
+                                .long("out")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("start")
+                        .about("creates the party and its first messages")
+                        .arg(share.clone())
+                        .arg(
+                            Arg::with_name("message")
+                                .long("message")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("signers")
+                                .long("signers")
+                                .takes_value(true)
+                                .use_delimiter(true)
+                                .required(true)
+                                .help("share indices of the signers"),
+                        )
+                        .arg(
+                            Arg::with_name("peers")
+                                .long("peers")
+                                .takes_value(true)
+                                .use_delimiter(true)
+                                .required(true)
+                                .help("identities of the signers, in the order of --signers"),
+                        )
+                        .arg(
+                            Arg::with_name("identity")
+                                .long("identity")
+                                .takes_value(true)
+                                .required(true)
+                                .help("identity key file of this signer"),
+                        )
+                        .arg(
+                            Arg::with_name("session")
+                                .long("session")
+                                .takes_value(true)
+                                .required(true)
+                                .help("id the signers agreed on for this signature"),
+                        )
+                        .arg(
+                            Arg::with_name("protocol")
+                                .long("protocol")
+                                .takes_value(true)
+                                .possible_values(&["gg18", "gg20"])
+                                .default_value("gg18"),
+                        )
+                        .arg(state.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("writes the messages to send to a file")
+                        .arg(state.clone())
+                        .arg(
+                            Arg::with_name("out")
+                                .long("out")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("handles the messages in the peers' files")
+                        .arg(state.clone())
+                        .arg(
+                            Arg::with_name("in")
+                                .long("in")
+                                .takes_value(true)
+                                .required(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("relay")
+                .about("runs a store-and-forward relay for parties that are not online together")
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .takes_value(true)
+                        .required(true)
+                        .help("host:port to accept parties on"),
+                )
+                .arg(
+                    Arg::with_name("store")
+                        .long("store")
+                        .takes_value(true)
+                        .help("file to keep the mail in across restarts"),
+                ),
+        )
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("keygen", Some(m)) => keygen(m),
+        ("sign", Some(m)) => sign(m),
+        ("verify", Some(m)) => verify(m),
+        ("pubkey", Some(m)) => pubkey(m),
+        ("inspect", Some(m)) => inspect(m),
+        ("round", Some(m)) => match m.subcommand() {
+            ("identity", Some(m)) => round_identity(m),
+            ("start", Some(m)) => round_start(m),
+            ("export", Some(m)) => round_export(m),
+            ("import", Some(m)) => round_import(m),
+            _ => unreachable!(),
+        },
+        ("relay", Some(m)) => relay(m),
+        _ => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
 }
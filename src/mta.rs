@@ -1,31 +1,59 @@
+use curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use curv::cryptographic_primitives::hashing::traits::Hash;
+use curv::FE;
 use paillier::*;
+use rand::{CryptoRng, RngCore};
 use std::borrow::Cow;
+use std::cmp;
+
+use crate::curve::{Curve, PointOps, ScalarOps, Secp256k1};
+use crate::ring_pedersen::RingPedersenParams;
+use crate::rng;
 
 pub use paillier::RawCiphertext;
 
-#[derive(Clone, Debug)]
+// Bob's response a·b + β' stays below q^3·q + q^5 (a is only proven below q^3), so the
+// plaintext space needs a bit over 5|q| bits, and never less than a 2048 bit modulus
+pub fn plaintext_bits(q: &BigInt) -> usize {
+    cmp::max(2047, 5 * q.bit_length() + 64)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Enc {
     ek: EncryptionKey,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Dec {
     enc: Enc,
     dk: DecryptionKey,
 }
 
 impl Enc {
-    pub fn new(bs: usize) -> Self {
-        let (ek, _dk) = Paillier::keypair_with_modulus_size(bs + 1).keys(); // safe to multiply
+    pub fn new<R: CryptoRng + RngCore>(bs: usize, rng: &mut R) -> Self {
+        let (ek, _dk) = rng::paillier_keypair(rng, bs + 1).keys(); // safe to multiply
         Self { ek: ek }
     }
-    pub fn random_bigint(&self) -> paillier::BigInt {
-        use curv::arithmetic::traits::Samplable;
-        BigInt::sample_below(&self.ek.n)
+    pub fn n(&self) -> BigInt {
+        self.ek.n.clone()
     }
-    pub fn encrypt<'d>(&self, m: &BigInt) -> RawCiphertext<'d> {
-        let r = Paillier::encrypt(&self.ek, RawPlaintext(Cow::Borrowed(m)));
-        r
+    pub fn random_bigint<R: CryptoRng + RngCore>(&self, rng: &mut R) -> paillier::BigInt {
+        rng::random_below(rng, &self.ek.n)
+    }
+    pub fn encrypt<'d, R: CryptoRng + RngCore>(
+        &self,
+        m: &BigInt,
+        rng: &mut R,
+    ) -> RawCiphertext<'d> {
+        let r = self.random_bigint(rng);
+        self.encrypt_with_randomness(m, &r)
+    }
+    // (1+N)^m * r^N mod N^2
+    pub fn encrypt_with_randomness<'d>(&self, m: &BigInt, r: &BigInt) -> RawCiphertext<'d> {
+        let n = &self.ek.n;
+        let nn = n * n;
+        let gm = (BigInt::from(1) + m * n).mod_floor(&nn);
+        RawCiphertext(Cow::Owned((gm * r.powm(n, &nn)).mod_floor(&nn)))
     }
     pub fn add<'c1, 'c2, 'd>(
         &self,
@@ -40,95 +68,352 @@ impl Enc {
 }
 
 impl Dec {
-    pub fn new(bs: usize) -> Self {
+    pub fn new<R: CryptoRng + RngCore>(bs: usize, rng: &mut R) -> Self {
         let bs = if bs < 2047 { 2047 } else { bs };
-        let (ek, dk) = Paillier::keypair_with_modulus_size(bs + 1).keys(); // safe to multiply
+        let (ek, dk) = rng::paillier_keypair(rng, bs + 1).keys(); // safe to multiply
         Self {
             enc: Enc { ek: ek },
             dk: dk,
         }
     }
-    pub fn random_bigint(&self) -> paillier::BigInt {
-        self.enc.random_bigint()
+    pub fn enc(&self) -> &Enc {
+        &self.enc
+    }
+    pub fn primes(&self) -> (&BigInt, &BigInt) {
+        (&self.dk.p, &self.dk.q)
+    }
+    pub fn random_bigint<R: CryptoRng + RngCore>(&self, rng: &mut R) -> paillier::BigInt {
+        self.enc.random_bigint(rng)
     }
 
-    pub fn encrypt<'d>(&self, m: &BigInt) -> RawCiphertext<'d> {
-        self.enc.encrypt(m)
+    pub fn encrypt<'d, R: CryptoRng + RngCore>(
+        &self,
+        m: &BigInt,
+        rng: &mut R,
+    ) -> RawCiphertext<'d> {
+        self.enc.encrypt(m, rng)
     }
     pub fn decrypt<'d>(&self, m: RawCiphertext<'d>) -> BigInt {
         let r = Paillier::decrypt(&self.dk, m);
         r.0.into_owned()
     }
+    // m and r with c = (1+N)^m·r^N mod N^2; c mod N is r^N mod N
+    pub fn open(&self, c: &BigInt) -> (BigInt, BigInt) {
+        let n = &self.enc.ek.n;
+        let one = BigInt::from(1);
+        let phi = (&self.dk.p - &one) * (&self.dk.q - &one);
+        let e = n.invert(&phi).unwrap();
+        let m = self.decrypt(RawCiphertext(Cow::Borrowed(c)));
+        (m, c.mod_floor(n).powm(&e, n))
+    }
+}
+
+// GG18 A.1: c encrypts m < q^3, proven against the verifier's (Ñ, h1, h2)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AliceProof {
+    pub z: BigInt,
+    pub u: BigInt,
+    pub w: BigInt,
+    pub s: BigInt,
+    pub s1: BigInt,
+    pub s2: BigInt,
+}
+
+impl AliceProof {
+    // bound is hashed in as well, e.g. a point the proof is about
+    fn challenge(
+        q: &BigInt,
+        n: &BigInt,
+        c: &BigInt,
+        z: &BigInt,
+        u: &BigInt,
+        w: &BigInt,
+        bound: &[BigInt],
+    ) -> BigInt {
+        let gamma = n + &BigInt::from(1);
+        let mut v = vec![n, &gamma, c, z, u, w];
+        v.extend(bound.iter());
+        HSha256::create_hash(&v).mod_floor(q)
+    }
+
+    // q is the order of the curve the MtA runs for
+    pub fn prove<R: CryptoRng + RngCore>(
+        q: &BigInt,
+        enc: &Enc,
+        c: &BigInt,
+        m: &BigInt,
+        r: &BigInt,
+        aux: &RingPedersenParams,
+        rng: &mut R,
+    ) -> Self {
+        Self::prove_bound(q, enc, c, (m, r), aux, |_| Vec::new(), rng)
+    }
+
+    // bind gets the mask α of m and returns what the challenge is bound to
+    fn prove_bound<R: CryptoRng + RngCore, F: FnOnce(&BigInt) -> Vec<BigInt>>(
+        q: &BigInt,
+        enc: &Enc,
+        c: &BigInt,
+        (m, r): (&BigInt, &BigInt),
+        aux: &RingPedersenParams,
+        bind: F,
+        rng: &mut R,
+    ) -> Self {
+        let q3 = q.pow(3);
+        let n = &enc.ek.n;
+        let n_tilde = &aux.n_tilde;
+
+        let alpha = &rng::random_below(rng, &q3);
+        let bound = bind(alpha);
+        let beta = rng::random_below(rng, n);
+        let gamma = rng::random_below(rng, &(&q3 * n_tilde));
+        let rho = rng::random_below(rng, &(q * n_tilde));
+
+        let z = (aux.h1.powm(m, n_tilde) * aux.h2.powm(&rho, n_tilde)).mod_floor(n_tilde);
+        let u = enc.encrypt_with_randomness(alpha, &beta).0.into_owned();
+        let w = (aux.h1.powm(alpha, n_tilde) * aux.h2.powm(&gamma, n_tilde)).mod_floor(n_tilde);
+
+        let e = Self::challenge(q, n, c, &z, &u, &w, &bound);
+        let s = (r.powm(&e, n) * beta).mod_floor(n);
+        let s1 = &e * m + alpha;
+        let s2 = &e * &rho + &gamma;
+        Self {
+            z: z,
+            u: u,
+            w: w,
+            s: s,
+            s1: s1,
+            s2: s2,
+        }
+    }
+
+    pub fn verify(&self, q: &BigInt, enc: &Enc, c: &BigInt, aux: &RingPedersenParams) -> bool {
+        self.verify_bound(q, enc, c, aux, &[])
+    }
+
+    fn verify_bound(
+        &self,
+        q: &BigInt,
+        enc: &Enc,
+        c: &BigInt,
+        aux: &RingPedersenParams,
+        bound: &[BigInt],
+    ) -> bool {
+        // the responses are naturals; a negative s1 of any size would pass the bound
+        let zero = BigInt::from(0);
+        let n = &enc.ek.n;
+        if self.s1 < zero || self.s1 > q.pow(3) || self.s2 < zero {
+            return false;
+        }
+        if self.s < zero || self.s >= *n {
+            return false;
+        }
+        let nn = n * n;
+        let n_tilde = &aux.n_tilde;
+        let e = Self::challenge(q, n, c, &self.z, &self.u, &self.w, bound);
+
+        // u == Γ^s1 s^N c^-e mod N^2
+        let c_e_inv = match c.powm(&e, &nn).invert(&nn) {
+            Some(x) => x,
+            None => return false,
+        };
+        let u = (enc
+            .encrypt_with_randomness(&self.s1, &self.s)
+            .0
+            .into_owned()
+            * c_e_inv)
+            .mod_floor(&nn);
+        if u != self.u {
+            return false;
+        }
+
+        // w == h1^s1 h2^s2 z^-e mod Ñ
+        let z_e_inv = match self.z.powm(&e, n_tilde).invert(n_tilde) {
+            Some(x) => x,
+            None => return false,
+        };
+        let w =
+            (aux.h1.powm(&self.s1, n_tilde) * aux.h2.powm(&self.s2, n_tilde)).mod_floor(n_tilde);
+        (w * z_e_inv).mod_floor(n_tilde) == self.w
+    }
 }
 
-#[derive(Debug)]
-pub struct Alice {
+// A.1 for the discrete log of a point: c encrypts m with X = g^m, m < q. Lindell'17 calls
+// this PDL; here it is the range proof with g^α next to Enc(α), whose s1 = e·m + α also
+// has to satisfy g^s1 = g^α·X^e.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PdlProof<C: Curve = Secp256k1> {
+    pub range: AliceProof,
+    pub g_alpha: C::Point,
+}
+
+// (0, 0) for the identity
+fn point_coords<C: Curve>(x: &C::Point, g_alpha: &C::Point) -> Vec<BigInt> {
+    let zero = || BigInt::from(0);
+    vec![
+        x.affine_x().unwrap_or_else(zero),
+        x.affine_y().unwrap_or_else(zero),
+        g_alpha.affine_x().unwrap_or_else(zero),
+        g_alpha.affine_y().unwrap_or_else(zero),
+    ]
+}
+
+impl<C: Curve> PdlProof<C> {
+    pub fn prove<R: CryptoRng + RngCore>(
+        enc: &Enc,
+        c: &BigInt,
+        m: &C::Scalar,
+        r: &BigInt,
+        aux: &RingPedersenParams,
+        rng: &mut R,
+    ) -> Self {
+        let g = C::Point::base_point();
+        let x = g * *m;
+        let mut g_alpha = g;
+        let range = AliceProof::prove_bound(
+            &C::Scalar::order(),
+            enc,
+            c,
+            (&m.to_bigint(), r),
+            aux,
+            |alpha| {
+                g_alpha = g * C::Scalar::from_bigint(alpha);
+                point_coords::<C>(&x, &g_alpha)
+            },
+            rng,
+        );
+        PdlProof {
+            range: range,
+            g_alpha: g_alpha,
+        }
+    }
+
+    pub fn verify(&self, enc: &Enc, c: &BigInt, x: &C::Point, aux: &RingPedersenParams) -> bool {
+        let q = C::Scalar::order();
+        let bound = point_coords::<C>(x, &self.g_alpha);
+        if !self.range.verify_bound(&q, enc, c, aux, &bound) {
+            return false;
+        }
+        let e = AliceProof::challenge(
+            &q,
+            &enc.ek.n,
+            c,
+            &self.range.z,
+            &self.range.u,
+            &self.range.w,
+            &bound,
+        );
+        C::Point::base_point() * C::Scalar::from_bigint(&self.range.s1)
+            == self.g_alpha + *x * C::Scalar::from_bigint(&e)
+    }
+}
+
+// m < q for both sides; the outputs satisfy α + β = a·b mod q, q being S's order
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Alice<S: ScalarOps = FE> {
     dec: Dec,
     pub m: BigInt,
-    pub a: BigInt,
+    pub a: S,
     pub fin: bool,
 }
-#[derive(Debug)]
-pub struct Bob {
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Bob<S: ScalarOps = FE> {
     pub m: BigInt,
-    pub a: BigInt,
+    pub a: S,
     pub fin: bool,
+    // β' and the randomness of Enc(β'), to open the answer if Alice disputes it
+    pub beta: BigInt,
+    pub rho: BigInt,
 }
 
-impl Alice {
-    pub fn new(bs: usize, m: BigInt) -> Alice {
+impl<S: ScalarOps> Alice<S> {
+    pub fn new<R: CryptoRng + RngCore>(bs: usize, m: BigInt, rng: &mut R) -> Self {
+        Self::with_dec(Dec::new(bs, rng), m)
+    }
+    pub fn with_dec(dec: Dec, m: BigInt) -> Self {
         Self {
-            dec: Dec::new(bs),
+            dec: dec,
             m: m,
-            a: BigInt::from(0),
+            a: S::from_bigint(&BigInt::from(0)),
             fin: false,
         }
     }
 
-    pub fn to_bob(&self) -> (&Enc, RawCiphertext) {
-        (&self.dec.enc, self.dec.encrypt(&self.m))
+    pub fn to_bob<R: CryptoRng + RngCore>(&self, rng: &mut R) -> (&Enc, RawCiphertext<'static>) {
+        (&self.dec.enc, self.dec.encrypt(&self.m, rng))
+    }
+    // aux is Bob's
+    pub fn to_bob_with_proof<R: CryptoRng + RngCore>(
+        &self,
+        aux: &RingPedersenParams,
+        rng: &mut R,
+    ) -> (&Enc, RawCiphertext<'static>, AliceProof) {
+        let r = self.dec.random_bigint(rng);
+        let c = self.dec.enc.encrypt_with_randomness(&self.m, &r);
+        let proof = AliceProof::prove(&S::order(), &self.dec.enc, &c.0, &self.m, &r, aux, rng);
+        (&self.dec.enc, c, proof)
+    }
+    pub fn open(&self, c: &BigInt) -> (BigInt, BigInt) {
+        self.dec.open(c)
     }
     pub fn from_bob<'c>(&mut self, data: &RawCiphertext<'c>) {
-        self.a = self.dec.decrypt(data.clone());
+        self.a = S::from_bigint(&self.dec.decrypt(data.clone()));
         self.fin = true;
     }
 }
 
-impl Bob {
+impl<S: ScalarOps> Bob<S> {
     pub fn new(m: BigInt) -> Self {
         Self {
             m: m,
-            a: BigInt::from(0),
+            a: S::from_bigint(&BigInt::from(0)),
             fin: false,
+            beta: BigInt::from(0),
+            rho: BigInt::from(0),
         }
     }
 
-    pub fn from_alice<'c, 'd>(&mut self, enc: &Enc, data: &RawCiphertext<'c>) -> RawCiphertext<'d> {
-        let beta = enc.random_bigint();
+    pub fn from_alice<'c, 'd, R: CryptoRng + RngCore>(
+        &mut self,
+        enc: &Enc,
+        data: &RawCiphertext<'c>,
+        rng: &mut R,
+    ) -> RawCiphertext<'d> {
+        // β' < q^5 keeps a·b + β' far below N, so Alice's decryption never wraps
+        let q = S::order();
+        let beta = rng::random_below(rng, &q.pow(5));
 
-        let b = enc.encrypt(&beta);
+        let rho = enc.random_bigint(rng);
+        let b = enc.encrypt_with_randomness(&beta, &rho);
         let r = enc.add(enc.mul(data.clone(), &self.m), b);
 
-        self.a = -beta;
+        self.a = S::from_bigint(&(&q - &beta.mod_floor(&q)));
+        self.beta = beta;
+        self.rho = rho;
         self.fin = true;
         r
     }
 }
 
-enum Role {
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+enum Role<S: ScalarOps> {
     Init(),
-    A(Alice),
-    B(Bob),
+    A(Alice<S>),
+    B(Bob<S>),
 }
-impl Role {
-    pub fn as_alice_mut(&mut self) -> &mut Alice {
+impl<S: ScalarOps> Role<S> {
+    pub fn as_alice_mut(&mut self) -> &mut Alice<S> {
         if let Role::A(ref mut r) = self {
             return r;
         } else {
             panic!("not a Alice");
         }
     }
-    pub fn as_bob_mut(&mut self) -> &mut Bob {
+    pub fn as_bob_mut(&mut self) -> &mut Bob<S> {
         if let Role::B(ref mut r) = self {
             return r;
         } else {
@@ -137,15 +422,29 @@ impl Role {
     }
 }
 
-pub struct Party {
+// serializable, so that a signing party can be saved between rounds
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Party<S: ScalarOps = FE> {
     bitsize: usize,
+    dec: Option<Dec>,
     pub m: BigInt,
-    role: Role,
+    role: Role<S>,
 }
-impl Party {
+impl<S: ScalarOps> Party<S> {
     pub fn new(bs: usize, m: BigInt) -> Self {
         Self {
             bitsize: bs,
+            dec: None,
+            m: m,
+            role: Role::Init(),
+        }
+    }
+    // use a long-term paillier key instead of generating one per MtA
+    pub fn with_dec(dec: Dec, m: BigInt) -> Self {
+        Self {
+            bitsize: 0,
+            dec: Some(dec),
             m: m,
             role: Role::Init(),
         }
@@ -154,14 +453,27 @@ impl Party {
         &self.m
     }
 
-    pub fn as_alice(&mut self) -> &mut Alice {
+    pub fn as_alice(&mut self) -> &mut Alice<S> {
         self.role.as_alice_mut()
     }
-    pub fn as_bob(&mut self) -> &mut Bob {
+    pub fn as_bob(&mut self) -> &mut Bob<S> {
         self.role.as_bob_mut()
     }
 
-    pub fn get_result(&self) -> Option<(&BigInt, &BigInt)> {
+    pub fn alice(&self) -> Option<&Alice<S>> {
+        match &self.role {
+            Role::A(alice) => Some(alice),
+            _ => None,
+        }
+    }
+    pub fn bob(&self) -> Option<&Bob<S>> {
+        match &self.role {
+            Role::B(bob) => Some(bob),
+            _ => None,
+        }
+    }
+
+    pub fn get_result(&self) -> Option<(&BigInt, &S)> {
         match &self.role {
             Role::A(alice) if alice.fin == true => Some((&alice.m, &alice.a)),
             Role::B(bob) if bob.fin == true => Some((&bob.m, &bob.a)),
@@ -169,11 +481,14 @@ impl Party {
         }
     }
 
-    pub fn alicization(&mut self) -> &mut Alice {
-        self.role = Role::A(Alice::new(self.bitsize, self.m.clone()));
+    pub fn alicization<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> &mut Alice<S> {
+        self.role = Role::A(match self.dec {
+            Some(ref dec) => Alice::with_dec(dec.clone(), self.m.clone()),
+            None => Alice::new(self.bitsize, self.m.clone(), rng),
+        });
         self.role.as_alice_mut()
     }
-    pub fn bobization(&mut self) -> &mut Bob {
+    pub fn bobization(&mut self) -> &mut Bob<S> {
         self.role = Role::B(Bob::new(self.m.clone()));
         self.role.as_bob_mut()
     }
@@ -182,31 +497,68 @@ impl Party {
 #[cfg(test)]
 mod tests {
     extern crate paillier;
-    use ::mta::*;
+    use crate::etude::fe_from_bigint;
+    use curv::elliptic::curves::traits::ECScalar;
+    use curv::FE;
+    use mta::*;
     use paillier::BigInt;
 
+    #[test]
+    fn test_alice_proof() {
+        use crate::ring_pedersen::RingPedersenParams;
+        let mut rng = ::rand::thread_rng();
+        let (aux, _) = RingPedersenParams::generate(&mut rng);
+        let mut p: Party = Party::new(256, BigInt::from(12345));
+        let alice = p.alicization(&mut rng);
+        let (e, c, proof) = alice.to_bob_with_proof(&aux, &mut rng);
+        assert!(proof.verify(&FE::q(), e, &c.0, &aux));
+
+        let other = e.encrypt(&BigInt::from(12345), &mut rng);
+        assert!(!proof.verify(&FE::q(), e, &other.0, &aux));
+
+        // m = -q^3, far out of range, gives a negative s1
+        let q = FE::q();
+        let m = BigInt::from(0) - q.pow(3);
+        let r = BigInt::from(3);
+        let c = e.encrypt_with_randomness(&m.mod_floor(&e.ek.n), &r);
+        let proof = AliceProof::prove(&q, e, &c.0, &m, &r, &aux, &mut rng);
+        assert!(proof.s1 < BigInt::from(0));
+        assert!(!proof.verify(&q, e, &c.0, &aux));
+    }
+
+    #[test]
+    fn test_open() {
+        let mut rng = ::rand::thread_rng();
+        let dec = Dec::new(256, &mut rng);
+        let m = BigInt::from(12345);
+        let r = dec.random_bigint(&mut rng);
+        let c = dec.enc().encrypt_with_randomness(&m, &r);
+        assert_eq!(dec.open(&c.0), (m, r));
+    }
+
     #[test]
     fn test_mta() {
         fn gen_party() -> Party {
-            use curv::arithmetic::traits::{Converter, Samplable};
-            let v =
-                BigInt::sample_range(&BigInt::from_hex("80000000"), &BigInt::from_hex("ffffffff"));
+            use curv::arithmetic::traits::Samplable;
+            let v = BigInt::sample_below(&FE::q());
             Party::new(8, v)
         }
+        let mut rng = ::rand::thread_rng();
         let mut p1 = gen_party();
         let mut p2 = gen_party();
         let (alice, bob) = {
-            let alice = p1.alicization();
+            let alice = p1.alicization(&mut rng);
             let bob = p2.bobization();
 
             let x2 = {
-                let (e, x1) = alice.to_bob();
-                let x2 = bob.from_alice(e, &x1);
+                let (e, x1) = alice.to_bob(&mut rng);
+                let x2 = bob.from_alice(e, &x1, &mut rng);
                 x2
             };
             alice.from_bob(&x2);
 
-            assert_eq!(&alice.m * &bob.m, &alice.a + &bob.a);
+            let ab = fe_from_bigint(&(&alice.m * &bob.m).mod_floor(&FE::q()));
+            assert_eq!(ab, alice.a + bob.a);
             (
                 (alice.m.clone(), alice.a.clone()),
                 (bob.m.clone(), bob.a.clone()),
@@ -0,0 +1,413 @@
+use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+const CONNECT_RETRIES: usize = 600;
+const CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+pub const ROUND_TIMEOUT: Duration = Duration::from_secs(120);
+// messages of later rounds kept per peer; an honest peer is at most a round ahead
+pub const MAX_BACKLOG: usize = 8;
+
+pub enum Outgoing<M> {
+    Broadcast(M),
+    To(usize, M),
+}
+
+// A party of an interactive protocol driven by the messages of its peers.
+pub trait Handler {
+    type Message: Clone + Serialize + DeserializeOwned;
+    type Output;
+
+    fn start<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<Self::Message>>>;
+    // messages may come in any order; one for a later round is kept by the party
+    // and replayed once it gets there
+    fn handle<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: Self::Message,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<Self::Message>>>;
+    fn output(&self) -> Option<Self::Output>;
+    // name of the current round and the peers it still waits for
+    fn round(&self) -> &'static str;
+    fn missing(&self) -> Vec<usize>;
+}
+
+pub fn timeout<H: Handler>(party: &H) -> Error {
+    Error::Timeout {
+        round: party.round(),
+        missing: party.missing(),
+    }
+}
+
+// The round logic of a party that keeps a message of a later round until it gets there.
+// handle_buffered and replay_backlog do the keeping and the replaying for it.
+pub trait Buffered {
+    type Message;
+
+    // our index and the number of parties
+    fn party(&self) -> (usize, usize);
+    // rounds numbered in order, so that a message of a round already passed is dropped
+    fn round_of(msg: &Self::Message) -> usize;
+    fn round_no(&self) -> usize;
+    fn backlog(&self) -> &Vec<(usize, Self::Message)>;
+    fn backlog_mut(&mut self) -> &mut Vec<(usize, Self::Message)>;
+    // whether the current round takes msg
+    fn ready_for(&self, msg: &Self::Message) -> bool;
+    fn handle_now<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: Self::Message,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<Self::Message>>>;
+}
+
+// Handler::handle for a Buffered party
+pub fn handle_buffered<B: Buffered, R: CryptoRng + RngCore>(
+    party: &mut B,
+    from: usize,
+    msg: B::Message,
+    rng: &mut R,
+) -> Result<Vec<Outgoing<B::Message>>> {
+    let (i, n) = party.party();
+    if from >= n || from == i {
+        return Err(Error::UnknownParty);
+    }
+    if !party.ready_for(&msg) {
+        // e.g. a repeated delivery
+        if B::round_of(&msg) < party.round_no() {
+            return Ok(Vec::new());
+        }
+        if party.backlog().iter().filter(|(j, _)| *j == from).count() >= MAX_BACKLOG {
+            return Err(Error::TooManyMessages { party: from });
+        }
+        party.backlog_mut().push((from, msg));
+        return Ok(Vec::new());
+    }
+    let mut out = party.handle_now(from, msg, rng)?;
+    out.extend(replay_backlog(party, rng)?);
+    Ok(out)
+}
+
+// the round may have moved on; handle whatever was waiting for it
+pub fn replay_backlog<B: Buffered, R: CryptoRng + RngCore>(
+    party: &mut B,
+    rng: &mut R,
+) -> Result<Vec<Outgoing<B::Message>>> {
+    let mut out = Vec::new();
+    while let Some(k) = party.backlog().iter().position(|(_, m)| party.ready_for(m)) {
+        let (from, msg) = party.backlog_mut().remove(k);
+        out.extend(party.handle_now(from, msg, rng)?);
+    }
+    Ok(out)
+}
+
+// Handler::missing from which peers' messages of the round are in, our own at i
+pub fn undelivered(i: usize, delivered: &[bool]) -> Vec<usize> {
+    (0..delivered.len())
+        .filter(|j| *j != i && !delivered[*j])
+        .collect()
+}
+
+// Full mesh of TCP connections, one JSON message per line.
+// Party j listens on addrs[j] and every party connects to every other one.
+pub struct Network {
+    i: usize,
+    n: usize,
+    outs: Vec<Option<TcpStream>>,
+    inbox: Receiver<(usize, String)>,
+}
+
+fn transport_error<E: ToString>(e: E) -> Error {
+    Error::Transport(e.to_string())
+}
+
+impl Network {
+    pub fn connect(i: usize, addrs: &[String]) -> Result<Self> {
+        let listener = TcpListener::bind(&addrs[i][..]).map_err(transport_error)?;
+        Self::with_listener(i, listener, addrs)
+    }
+
+    // listener is already bound to addrs[i], e.g. to port 0 before the addresses were known
+    pub fn with_listener(i: usize, listener: TcpListener, addrs: &[String]) -> Result<Self> {
+        let n = addrs.len();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for _ in 1..n {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(_) => return,
+                };
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    let mut lines = BufReader::new(stream).lines();
+                    // the first line is the index of the peer
+                    let from: usize = match lines.next() {
+                        Some(Ok(line)) => match line.trim().parse() {
+                            Ok(from) if from < n && from != i => from,
+                            _ => return,
+                        },
+                        _ => return,
+                    };
+                    for line in lines {
+                        match line {
+                            Ok(line) => {
+                                if tx.send((from, line)).is_err() {
+                                    return;
+                                }
+                            }
+                            Err(_) => return,
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut outs = Vec::with_capacity(n);
+        for j in 0..n {
+            if j == i {
+                outs.push(None);
+                continue;
+            }
+            let mut retries = 0;
+            let mut stream = loop {
+                match TcpStream::connect(&addrs[j][..]) {
+                    Ok(stream) => break stream,
+                    Err(e) => {
+                        retries += 1;
+                        if retries > CONNECT_RETRIES {
+                            return Err(transport_error(e));
+                        }
+                        thread::sleep(CONNECT_INTERVAL);
+                    }
+                }
+            };
+            writeln!(stream, "{}", i).map_err(transport_error)?;
+            outs.push(Some(stream));
+        }
+        Ok(Self {
+            i: i,
+            n: n,
+            outs: outs,
+            inbox: rx,
+        })
+    }
+
+    pub fn send<M: Serialize>(&mut self, to: usize, msg: &M) -> Result<()> {
+        let line = serde_json::to_string(msg).map_err(transport_error)?;
+        match self.outs[to] {
+            Some(ref mut stream) => writeln!(stream, "{}", line).map_err(transport_error),
+            None => Err(Error::Transport(format!("no connection to {}", to))),
+        }
+    }
+
+    pub fn deliver<M: Serialize>(&mut self, out: Vec<Outgoing<M>>) -> Result<()> {
+        for o in out {
+            match o {
+                Outgoing::Broadcast(msg) => {
+                    for j in 0..self.n {
+                        if j != self.i {
+                            self.send(j, &msg)?;
+                        }
+                    }
+                }
+                Outgoing::To(j, msg) => self.send(j, &msg)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn recv<M: DeserializeOwned>(&self) -> Result<(usize, M)> {
+        let (from, line) = self.inbox.recv().map_err(transport_error)?;
+        let msg = serde_json::from_str(&line).map_err(transport_error)?;
+        Ok((from, msg))
+    }
+
+    // None if nothing came in time
+    pub fn recv_timeout<M: DeserializeOwned>(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<(usize, M)>> {
+        let (from, line) = match self.inbox.recv_timeout(timeout) {
+            Ok(received) => received,
+            Err(RecvTimeoutError::Timeout) => return Ok(None),
+            Err(e) => return Err(transport_error(e)),
+        };
+        let msg = serde_json::from_str(&line).map_err(transport_error)?;
+        Ok(Some((from, msg)))
+    }
+}
+
+// Runs one party to completion, giving each round ROUND_TIMEOUT.
+pub fn run<H: Handler, R: CryptoRng + RngCore>(
+    party: &mut H,
+    net: &mut Network,
+    rng: &mut R,
+) -> Result<H::Output> {
+    run_with_timeout(party, net, rng, ROUND_TIMEOUT)
+}
+
+// Fails with Error::Timeout if a round does not finish within `timeout`.
+pub fn run_with_timeout<H: Handler, R: CryptoRng + RngCore>(
+    party: &mut H,
+    net: &mut Network,
+    rng: &mut R,
+    timeout: Duration,
+) -> Result<H::Output> {
+    let out = party.start(rng)?;
+    net.deliver(out)?;
+    let mut round = party.round();
+    let mut since = Instant::now();
+    loop {
+        if let Some(output) = party.output() {
+            return Ok(output);
+        }
+        let left = match timeout.checked_sub(since.elapsed()) {
+            Some(left) => left,
+            None => return Err(self::timeout(party)),
+        };
+        if let Some((from, msg)) = net.recv_timeout(left)? {
+            let out = party.handle(from, msg, rng)?;
+            net.deliver(out)?;
+            if party.round() != round {
+                round = party.round();
+                since = Instant::now();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::etude::{self, digest_message};
+    use crate::keygen::{self, KeyShare};
+    use crate::network::*;
+
+    // n listeners on ports the OS picks, and their addresses
+    fn bind_local(n: usize) -> (Vec<TcpListener>, Vec<String>) {
+        let listeners: Vec<TcpListener> = (0..n)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs = listeners
+            .iter()
+            .map(|l| l.local_addr().unwrap().to_string())
+            .collect();
+        (listeners, addrs)
+    }
+
+    fn spawn_parties<H, F>(n: usize, party: F) -> Vec<H::Output>
+    where
+        H: Handler,
+        H::Output: Send + 'static,
+        F: Fn(usize) -> H + Send + Sync + Clone + 'static,
+    {
+        let (listeners, addrs) = bind_local(n);
+        let handles: Vec<_> = listeners
+            .into_iter()
+            .enumerate()
+            .map(|(i, listener)| {
+                let addrs = addrs.clone();
+                let party = party.clone();
+                thread::spawn(move || {
+                    let mut net = Network::with_listener(i, listener, &addrs).unwrap();
+                    run(&mut party(i), &mut net, &mut ::rand::thread_rng()).unwrap()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_networked_keygen_and_sign() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let shares: Vec<KeyShare> = spawn_parties(2, |i| keygen::Party::new(i, 1, 2));
+        assert_eq!(shares[0].y, shares[1].y);
+
+        let signing = shares.clone();
+        let sigs = spawn_parties(2, move |i| {
+            let mut p = etude::Party::with_key_share(i, 2, &signing[i], &[1, 2]);
+            p.set_digest(digest_message(&message));
+            p
+        });
+        assert_eq!(sigs[0], sigs[1]);
+        assert!(sigs[0].verify(&shares[0].y, &digest_message(&message)));
+    }
+
+    #[test]
+    fn test_handle_buffered() {
+        let mut rng = ::rand::thread_rng();
+        let mut parties: Vec<keygen::Party> = (0..3).map(|i| keygen::Party::new(i, 1, 3)).collect();
+        let commits: Vec<keygen::KeyGenMessage> = parties
+            .iter_mut()
+            .map(|p| match p.start(&mut rng).unwrap().pop() {
+                Some(Outgoing::Broadcast(msg)) => msg,
+                _ => panic!("expected a broadcast"),
+            })
+            .collect();
+        let mut p0 = keygen::Party::new(0, 1, 3);
+        assert_eq!(
+            p0.handle(3, commits[1].clone(), &mut rng).err(),
+            Some(Error::UnknownParty)
+        );
+        assert_eq!(
+            p0.handle(0, commits[0].clone(), &mut rng).err(),
+            Some(Error::UnknownParty)
+        );
+        // before start, the commitments wait, up to a limit per peer
+        for _ in 0..MAX_BACKLOG {
+            p0.handle(1, commits[1].clone(), &mut rng).unwrap();
+        }
+        assert_eq!(
+            p0.handle(1, commits[1].clone(), &mut rng).err(),
+            Some(Error::TooManyMessages { party: 1 })
+        );
+
+        // a commitment after its round is over is dropped
+        let p0 = &mut parties[0];
+        p0.handle(1, commits[1].clone(), &mut rng).unwrap();
+        p0.handle(2, commits[2].clone(), &mut rng).unwrap();
+        assert_eq!(p0.round(), "BroadcastingVss");
+        assert!(p0
+            .handle(1, commits[1].clone(), &mut rng)
+            .unwrap()
+            .is_empty());
+        assert!(p0.backlog().is_empty());
+    }
+
+    #[test]
+    fn test_round_timeout() {
+        let (mut listeners, addrs) = bind_local(2);
+        let silent = {
+            let addrs = addrs.clone();
+            let listener = listeners.pop().unwrap();
+            // connects but never says anything
+            thread::spawn(move || {
+                let _net = Network::with_listener(1, listener, &addrs).unwrap();
+                thread::sleep(Duration::from_secs(3));
+            })
+        };
+        let mut net = Network::with_listener(0, listeners.pop().unwrap(), &addrs).unwrap();
+        let mut party = etude::Party::new(0, 2);
+        party.set_digest(digest_message("Miku-san maji tenshi!".as_bytes()));
+        let timeout = Duration::from_secs(1);
+        let result = run_with_timeout(&mut party, &mut net, &mut ::rand::thread_rng(), timeout);
+        assert_eq!(
+            result.err(),
+            Some(Error::Timeout {
+                round: "BroadcastingGr",
+                missing: vec![1],
+            })
+        );
+        silent.join().unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+extern crate curv;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::BigInt;
+
+use crate::mta;
+
+// Non-interactive proof that gcd(N, φ(N)) = 1 (hence N is square-free), after
+// Gennaro-Goldfeder and Lindell: σi = ρi^(N^-1 mod φ(N)) for hashed challenges ρi.
+// Together with the absence of prime factors below ALPHA this rules out malformed moduli.
+const M: usize = 11;
+const ALPHA: u64 = 6370;
+const MIN_BITS: usize = 2047;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaillierKeyProof {
+    pub sigma: Vec<BigInt>,
+}
+
+// binds a proof to the index of the party publishing the key
+pub fn party_salt(i: usize) -> [u8; 8] {
+    (i as u64).to_be_bytes()
+}
+
+fn challenge(n: &BigInt, salt: &[u8], i: usize) -> BigInt {
+    let bits = n.bit_length() + 128;
+    let salt = BigInt::from(salt);
+    let shift = BigInt::from(2).pow(256);
+    let mut rho = BigInt::from(0);
+    let mut j = 0;
+    while j * 256 < bits {
+        let h = HSha256::create_hash(&vec![
+            n,
+            &salt,
+            &BigInt::from(i as u64),
+            &BigInt::from(j as u64),
+        ]);
+        rho = rho * &shift + h;
+        j += 1;
+    }
+    rho.mod_floor(n)
+}
+
+fn small_primes() -> Vec<u64> {
+    let mut sieve = vec![true; ALPHA as usize];
+    let mut primes = Vec::new();
+    for p in 2..(ALPHA as usize) {
+        if sieve[p] {
+            primes.push(p as u64);
+            let mut k = p * p;
+            while k < ALPHA as usize {
+                sieve[k] = false;
+                k += p;
+            }
+        }
+    }
+    primes
+}
+
+impl PaillierKeyProof {
+    pub fn prove(dec: &mta::Dec, salt: &[u8]) -> Self {
+        let (p, q) = dec.primes();
+        let n = &dec.enc().n();
+        let one = BigInt::from(1);
+        let phi = (p - &one) * (q - &one);
+        let n_inv = n.invert(&phi).unwrap();
+        let sigma = (0..M)
+            .map(|i| challenge(n, salt, i).powm(&n_inv, n))
+            .collect();
+        Self { sigma: sigma }
+    }
+
+    pub fn verify(&self, ek: &mta::Enc, salt: &[u8]) -> bool {
+        let n = &ek.n();
+        if n.bit_length() < MIN_BITS || self.sigma.len() != M {
+            return false;
+        }
+        let zero = BigInt::from(0);
+        if small_primes()
+            .iter()
+            .any(|p| n.mod_floor(&BigInt::from(*p)) == zero)
+        {
+            return false;
+        }
+        let one = BigInt::from(1);
+        self.sigma.iter().enumerate().all(|(i, sigma)| {
+            let rho = challenge(n, salt, i);
+            rho.gcd(n) == one && sigma.powm(n, n) == rho
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mta;
+    use crate::paillier_proof::*;
+
+    #[test]
+    fn test_paillier_key_proof() {
+        let dec = mta::Dec::new(256, &mut ::rand::thread_rng());
+        let proof = PaillierKeyProof::prove(&dec, b"0");
+        assert!(proof.verify(dec.enc(), b"0"));
+        assert!(!proof.verify(dec.enc(), b"1"));
+
+        let other = mta::Dec::new(256, &mut ::rand::thread_rng());
+        assert!(!proof.verify(other.enc(), b"0"));
+    }
+}
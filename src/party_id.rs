@@ -0,0 +1,222 @@
+extern crate curv;
+use self::curv::elliptic::curves::traits::ECPoint;
+use self::curv::GE;
+use std::fmt;
+
+use crate::curve;
+use crate::error::{Error, Result};
+use crate::eth::to_hex;
+
+// Parties are named by opaque ids, e.g. their identity public keys. The protocols still
+// index everything by position; a Committee and a Session translate between the two.
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PartyId(pub Vec<u8>);
+
+impl PartyId {
+    // compressed SEC1
+    pub fn from_public_key(pk: &GE) -> Self {
+        PartyId(Vec::<u8>::from(&pk.bytes_compressed_to_big_int()))
+    }
+
+    // None unless the id is a compressed point
+    pub fn public_key(&self) -> Option<GE> {
+        curve::decompress(&self.0)
+    }
+}
+
+impl fmt::Display for PartyId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0))
+    }
+}
+
+// The keygen committee. Ids are sorted, so every member derives the same position for
+// each of them, and the Shamir evaluation point of an id is its position plus one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Committee {
+    ids: Vec<PartyId>,
+}
+
+impl Committee {
+    pub fn new(ids: &[PartyId]) -> Result<Self> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        let n = ids.len();
+        ids.dedup();
+        if ids.len() != n {
+            return Err(Error::DuplicateParty);
+        }
+        Ok(Committee { ids: ids })
+    }
+
+    pub fn n(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn ids(&self) -> &[PartyId] {
+        &self.ids
+    }
+
+    // the keygen index, KeyShare::i
+    pub fn index_of(&self, id: &PartyId) -> Result<usize> {
+        self.ids.binary_search(id).map_err(|_| Error::UnknownParty)
+    }
+
+    pub fn evaluation_point(&self, id: &PartyId) -> Result<usize> {
+        self.index_of(id).map(|i| i + 1)
+    }
+
+    // signers may be any subset of the committee, given in any order
+    pub fn session(&self, signers: &[PartyId]) -> Result<Session> {
+        let mut points = signers
+            .iter()
+            .map(|id| self.evaluation_point(id))
+            .collect::<Result<Vec<usize>>>()?;
+        points.sort_unstable();
+        let n = points.len();
+        points.dedup();
+        if points.len() != n {
+            return Err(Error::DuplicateParty);
+        }
+        Ok(Session {
+            ids: points.iter().map(|x| self.ids[x - 1].clone()).collect(),
+            points: points,
+        })
+    }
+}
+
+// The signers of one run, ordered by evaluation point; a party's position here is the
+// index the protocol messages use.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    ids: Vec<PartyId>,
+    points: Vec<usize>,
+}
+
+impl Session {
+    pub fn n(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn ids(&self) -> &[PartyId] {
+        &self.ids
+    }
+
+    pub fn points(&self) -> &[usize] {
+        &self.points
+    }
+
+    pub fn index_of(&self, id: &PartyId) -> Result<usize> {
+        self.ids
+            .iter()
+            .position(|x| x == id)
+            .ok_or(Error::UnknownParty)
+    }
+
+    pub fn id(&self, i: usize) -> &PartyId {
+        &self.ids[i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::GE;
+    use crate::error::Error;
+    use crate::etude::{digest_message, Party};
+    use crate::keygen::{self, KeyShare};
+    use crate::party_id::*;
+    use crate::simulator::Simulator;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn id(name: &str) -> PartyId {
+        PartyId(name.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_committee() {
+        let names = ["dave", "alice", "erin", "carol", "bob"];
+        let ids: Vec<PartyId> = names.iter().map(|s| id(s)).collect();
+        let committee = Committee::new(&ids).unwrap();
+        let mut shuffled = ids.clone();
+        shuffled.reverse();
+        assert_eq!(committee, Committee::new(&shuffled).unwrap());
+        assert_eq!(committee.evaluation_point(&id("alice")), Ok(1));
+        assert_eq!(committee.evaluation_point(&id("erin")), Ok(5));
+        assert_eq!(committee.index_of(&id("mallory")), Err(Error::UnknownParty));
+        assert_eq!(
+            Committee::new(&[id("alice"), id("alice")]),
+            Err(Error::DuplicateParty)
+        );
+
+        let session = committee
+            .session(&[id("erin"), id("bob"), id("carol")])
+            .unwrap();
+        assert_eq!(session.points(), &[2, 3, 5]);
+        assert_eq!(session.index_of(&id("erin")), Ok(2));
+        assert_eq!(session.id(0), &id("bob"));
+        assert_eq!(
+            committee.session(&[id("bob"), id("erin"), id("bob")]),
+            Err(Error::DuplicateParty)
+        );
+
+        let pk = GE::generator();
+        assert_eq!(PartyId::from_public_key(&pk).0.len(), 33);
+    }
+
+    #[test]
+    fn test_sign_with_subset() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let ids: Vec<PartyId> = ["p0", "p1", "p2", "p3", "p4"]
+            .iter()
+            .map(|s| id(s))
+            .collect();
+        let committee = Committee::new(&ids).unwrap();
+        let keygen: Vec<keygen::Party> = ids
+            .iter()
+            .map(|me| keygen::Party::for_member(&committee, me, 2).unwrap())
+            .collect();
+        let shares: Vec<KeyShare> = Simulator::new(keygen)
+            .run(&mut rng)
+            .into_iter()
+            .collect::<crate::error::Result<Vec<KeyShare>>>()
+            .unwrap();
+
+        // {p1, p3, p4}: three of five, not the first three
+        let session = committee.session(&[id("p4"), id("p1"), id("p3")]).unwrap();
+        let parties: Vec<Party> = session
+            .ids()
+            .iter()
+            .map(|me| {
+                let share = &shares[committee.index_of(me).unwrap()];
+                let mut p = Party::for_session(&session, me, share).unwrap();
+                p.set_digest(digest_message(message));
+                p
+            })
+            .collect();
+        let mut sim = Simulator::new(parties);
+        let results = sim.run(&mut rng);
+        for r in results.iter() {
+            assert!(r
+                .as_ref()
+                .unwrap()
+                .verify(&shares[0].y, &digest_message(message)));
+        }
+
+        // a share only signs as the member it was made for
+        assert_eq!(
+            Party::for_session(&session, &id("p1"), &shares[3]).err(),
+            Some(Error::UnknownParty)
+        );
+        // and only with more than t signers
+        let session = committee.session(&[id("p4"), id("p1")]).unwrap();
+        assert_eq!(
+            Party::for_session(&session, &id("p1"), &shares[0]).err(),
+            Some(Error::NotEnoughShares)
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use rand::{thread_rng, CryptoRng, RngCore};
+
+use crate::curve::{Curve, Secp256k1};
+use crate::error::Result;
+use crate::keygen::{self, KeyGen, KeyShare};
+
+// Every party re-shares zero, so xi changes while y = g^f(0) is kept. These are the keygen
+// rounds on a zero secret (keygen::Party::for_refresh), which also run as a Handler.
+pub struct Refresh<C: Curve = Secp256k1> {
+    keygen: KeyGen<C>,
+}
+
+impl<C: Curve> Refresh<C> {
+    pub fn new(shares: Vec<KeyShare<C>>) -> Self {
+        let parties = shares.into_iter().map(keygen::Party::for_refresh).collect();
+        Self {
+            keygen: KeyGen::with_parties(parties),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<Vec<KeyShare<C>>> {
+        self.run_with_rng(&mut thread_rng())
+    }
+
+    pub fn run_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<KeyShare<C>>> {
+        self.keygen.run_with_rng(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::{FE, GE};
+    use crate::etude::Etude;
+    use crate::keygen::KeyGen;
+    use crate::refresh::*;
+    use crate::simulator::Simulator;
+    use crate::vss;
+
+    #[test]
+    fn test_refresh() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let old = KeyGen::new(1, 3).run().unwrap();
+        let new = Refresh::new(old.clone()).run().unwrap();
+        for i in 0..3 {
+            assert_eq!(old[i].y, new[i].y);
+            assert!(old[i].xi != new[i].xi);
+            assert!(old[i].dec.enc() != new[i].dec.enc());
+        }
+
+        let xs = [1, 2];
+        let mixed = old[0].xi * vss::lagrange_coefficient::<FE>(1, &xs)
+            + new[1].xi * vss::lagrange_coefficient::<FE>(2, &xs);
+        assert!(GE::generator() * mixed != old[0].y);
+
+        let mut gg18 = Etude::with_key_shares(&new[..2]).unwrap();
+        gg18.sign(&message);
+        assert!(gg18
+            .signature()
+            .unwrap()
+            .verify(&new[0].y, &::etude::digest_message(&message)));
+
+        // the same rounds driven as Handlers
+        let parties = new
+            .iter()
+            .cloned()
+            .map(keygen::Party::for_refresh)
+            .collect();
+        for (i, r) in Simulator::new(parties)
+            .run(&mut thread_rng())
+            .iter()
+            .enumerate()
+        {
+            let share = r.as_ref().unwrap();
+            assert_eq!(share.y, new[i].y);
+            assert!(share.xi != new[i].xi);
+        }
+    }
+}
@@ -0,0 +1,737 @@
+extern crate sha2;
+use self::sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::auth::IdentityKey;
+use crate::error::{Error, Result};
+use crate::network::{Handler, Outgoing};
+use crate::party_id::PartyId;
+use crate::schnorr;
+
+// Store-and-forward for parties that are rarely online together: each one deposits its
+// messages at the relay and fetches those addressed to it whenever it comes online, so a
+// session can take hours. Parties are named by their identity keys: a deposit is signed by
+// its sender, and only the recipient can fetch or acknowledge its mail. The payload is
+// still opaque JSON; wrap the parties in auth::Authenticated so that it is signed per
+// message and, for unicast, encrypted.
+
+// The relay numbers the envelopes of each recipient in a session, and keeps them until the
+// recipient acknowledges them, so a party that crashes before saving can fetch again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub session: String,
+    pub from: PartyId,
+    pub to: PartyId,
+    pub seq: u64,     // set by the relay
+    pub msg: String,  // JSON
+    pub sig: Vec<u8>, // BIP340 by `from` over session, to and msg
+}
+
+fn digest(parts: &[&[u8]]) -> Vec<u8> {
+    let mut h = Sha256::new();
+    for part in parts.iter() {
+        h.input(&(part.len() as u64).to_be_bytes());
+        h.input(part);
+    }
+    h.result().to_vec()
+}
+
+fn verify_by(id: &PartyId, digest: &[u8], sig: &[u8]) -> bool {
+    id.public_key().map_or(false, |pk| {
+        schnorr::verify(&schnorr::xonly(&pk), digest, sig)
+    })
+}
+
+// what the recipient signs to fetch from or acknowledge up to `seq`; a replayed request
+// gets nothing the recipient would not, as the mail is only readable by it
+fn request_digest(kind: &str, session: &str, seq: u64) -> Vec<u8> {
+    digest(&[kind.as_bytes(), session.as_bytes(), &seq.to_be_bytes()])
+}
+
+impl Envelope {
+    fn digest(&self) -> Vec<u8> {
+        digest(&[
+            b"deposit",
+            self.session.as_bytes(),
+            &self.to.0,
+            self.msg.as_bytes(),
+        ])
+    }
+
+    pub fn verify(&self) -> bool {
+        verify_by(&self.from, &self.digest(), &self.sig)
+    }
+}
+
+pub trait Relay {
+    // refused unless signed by env.from; the same message again is taken but not stored
+    fn deposit(&mut self, env: Envelope) -> Result<()>;
+    // what is waiting for `to` in the session from seq `after` on, oldest first; nothing
+    // is removed. `sig` is by `to`, over request_digest("fetch", session, after).
+    fn fetch(
+        &mut self,
+        session: &str,
+        to: &PartyId,
+        after: u64,
+        sig: &[u8],
+    ) -> Result<Vec<Envelope>>;
+    // drops everything below `upto`; `sig` is by `to`, over request_digest("ack", ..)
+    fn ack(&mut self, session: &str, to: &PartyId, upto: u64, sig: &[u8]) -> Result<()>;
+}
+
+// deposits a sender may make to one recipient in a session, far more than any protocol
+// here needs
+pub const MAX_DEPOSITS: usize = 64;
+
+// one recipient in one session; `next` and `seen` survive acks, so seq numbers never
+// repeat and an envelope is only stored once
+#[derive(Default, Serialize, Deserialize)]
+struct Slot {
+    next: u64,
+    envs: Vec<Envelope>,
+    #[serde(default)]
+    seen: Vec<(PartyId, Vec<u8>)>, // sender and digest of every deposit
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Mailbox {
+    slots: HashMap<String, Slot>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+// the hex id has no '/', so this cannot collide
+fn slot_key(session: &str, to: &PartyId) -> String {
+    format!("{}/{}", to, session)
+}
+
+fn transport_error<E: ToString>(e: E) -> Error {
+    Error::Transport(e.to_string())
+}
+
+impl Mailbox {
+    // in memory only: the mail is lost when the relay stops
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // loaded from `path` if it exists, and written back to it after every change
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut mailbox: Mailbox = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(transport_error)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Mailbox::default(),
+            Err(e) => return Err(transport_error(e)),
+        };
+        mailbox.path = Some(path);
+        Ok(mailbox)
+    }
+
+    // written aside and renamed over, so a crash leaves either the old or the new file
+    fn save(&self) -> Result<()> {
+        if let Some(ref path) = self.path {
+            let tmp = path.with_extension("tmp");
+            let json = serde_json::to_string(self).map_err(transport_error)?;
+            fs::write(&tmp, json).map_err(transport_error)?;
+            fs::rename(&tmp, path).map_err(transport_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Relay for Mailbox {
+    fn deposit(&mut self, mut env: Envelope) -> Result<()> {
+        if !env.verify() {
+            return Err(Error::Unauthenticated);
+        }
+        let slot = self
+            .slots
+            .entry(slot_key(&env.session, &env.to))
+            .or_insert_with(Slot::default);
+        let seen = (env.from.clone(), env.digest());
+        // a sender retrying a deposit the relay already took
+        if slot.seen.contains(&seen) {
+            return Ok(());
+        }
+        if slot
+            .seen
+            .iter()
+            .filter(|(from, _)| *from == env.from)
+            .count()
+            >= MAX_DEPOSITS
+        {
+            return Err(Error::MailboxFull);
+        }
+        slot.seen.push(seen);
+        env.seq = slot.next;
+        slot.next += 1;
+        slot.envs.push(env);
+        self.save()
+    }
+
+    fn fetch(
+        &mut self,
+        session: &str,
+        to: &PartyId,
+        after: u64,
+        sig: &[u8],
+    ) -> Result<Vec<Envelope>> {
+        if !verify_by(to, &request_digest("fetch", session, after), sig) {
+            return Err(Error::Unauthenticated);
+        }
+        Ok(self
+            .slots
+            .get(&slot_key(session, to))
+            .map_or(Vec::new(), |slot| {
+                slot.envs
+                    .iter()
+                    .filter(|env| env.seq >= after)
+                    .cloned()
+                    .collect()
+            }))
+    }
+
+    fn ack(&mut self, session: &str, to: &PartyId, upto: u64, sig: &[u8]) -> Result<()> {
+        if !verify_by(to, &request_digest("ack", session, upto), sig) {
+            return Err(Error::Unauthenticated);
+        }
+        if let Some(slot) = self.slots.get_mut(&slot_key(session, to)) {
+            slot.envs.retain(|env| env.seq >= upto);
+        }
+        self.save()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Deposit(Envelope),
+    Fetch {
+        session: String,
+        to: PartyId,
+        after: u64,
+        sig: Vec<u8>,
+    },
+    Ack {
+        session: String,
+        to: PartyId,
+        upto: u64,
+        sig: Vec<u8>,
+    },
+}
+
+fn answer(mailbox: &Mutex<Mailbox>, line: &str) -> Result<Vec<Envelope>> {
+    let req: Request = serde_json::from_str(line).map_err(transport_error)?;
+    let mut mailbox = mailbox.lock().unwrap();
+    match req {
+        Request::Deposit(env) => mailbox.deposit(env).map(|_| Vec::new()),
+        Request::Fetch {
+            session,
+            to,
+            after,
+            sig,
+        } => mailbox.fetch(&session, &to, after, &sig),
+        Request::Ack {
+            session,
+            to,
+            upto,
+            sig,
+        } => mailbox.ack(&session, &to, upto, &sig).map(|_| Vec::new()),
+    }
+}
+
+// the error goes back as text, so the client can tell a refusal from an empty mailbox
+type Reply = std::result::Result<Vec<Envelope>, String>;
+
+// A Mailbox over TCP, one JSON request and one JSON reply per line. Runs until the
+// listener fails.
+pub fn serve(listener: TcpListener, mailbox: Mailbox) -> Result<()> {
+    let mailbox = Arc::new(Mutex::new(mailbox));
+    for stream in listener.incoming() {
+        let stream = stream.map_err(transport_error)?;
+        let mailbox = mailbox.clone();
+        thread::spawn(move || {
+            let mut out = match stream.try_clone() {
+                Ok(out) => out,
+                Err(_) => return,
+            };
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                let reply: Reply = answer(&mailbox, &line).map_err(|e| e.to_string());
+                let reply = serde_json::to_string(&reply).unwrap();
+                if writeln!(out, "{}", reply).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+// Talks to `serve`, with a connection per request, as a device may go offline in between.
+pub struct RelayClient {
+    addr: String,
+}
+
+impl RelayClient {
+    pub fn new(addr: &str) -> Self {
+        RelayClient {
+            addr: addr.to_string(),
+        }
+    }
+
+    fn request(&self, req: &Request) -> Result<Vec<Envelope>> {
+        let mut stream = TcpStream::connect(&self.addr[..]).map_err(transport_error)?;
+        let line = serde_json::to_string(req).map_err(transport_error)?;
+        writeln!(stream, "{}", line).map_err(transport_error)?;
+        let mut reply = String::new();
+        BufReader::new(stream)
+            .read_line(&mut reply)
+            .map_err(transport_error)?;
+        let reply: Reply = serde_json::from_str(&reply).map_err(transport_error)?;
+        reply.map_err(Error::Transport)
+    }
+}
+
+impl Relay for RelayClient {
+    fn deposit(&mut self, env: Envelope) -> Result<()> {
+        self.request(&Request::Deposit(env)).map(|_| ())
+    }
+
+    fn fetch(
+        &mut self,
+        session: &str,
+        to: &PartyId,
+        after: u64,
+        sig: &[u8],
+    ) -> Result<Vec<Envelope>> {
+        self.request(&Request::Fetch {
+            session: session.to_string(),
+            to: to.clone(),
+            after: after,
+            sig: sig.to_vec(),
+        })
+    }
+
+    fn ack(&mut self, session: &str, to: &PartyId, upto: u64, sig: &[u8]) -> Result<()> {
+        self.request(&Request::Ack {
+            session: session.to_string(),
+            to: to.clone(),
+            upto: upto,
+            sig: sig.to_vec(),
+        })
+        .map(|_| ())
+    }
+}
+
+// One party's side of a session over a relay. Call start once, then sync each time the
+// party is online until it returns the output. Save the Mailer whenever the party is
+// saved: its cursor is how far the party has handled its mail, and the relay only drops
+// that mail once the next sync acknowledges it. What the party answered is kept in the
+// outbox until the relay took it, so a failed deposit is retried by the next sync.
+#[derive(Serialize, Deserialize)]
+pub struct Mailer {
+    i: usize,
+    session: String,
+    key: IdentityKey,
+    peers: Vec<PartyId>, // by position, ours included
+    cursor: u64,
+    acked: u64,
+    outbox: Vec<Envelope>,            // signed, oldest first
+    skipped: Vec<(Envelope, String)>, // with why
+}
+
+impl Mailer {
+    pub fn new(session: &str, key: IdentityKey, peers: &[PartyId]) -> Result<Self> {
+        let i = peers
+            .iter()
+            .position(|id| *id == key.party_id())
+            .ok_or(Error::UnknownParty)?;
+        Ok(Mailer {
+            i: i,
+            session: session.to_string(),
+            key: key,
+            peers: peers.to_vec(),
+            cursor: 0,
+            acked: 0,
+            outbox: Vec::new(),
+            skipped: Vec::new(),
+        })
+    }
+
+    // envelopes that made it past the relay but not to the party, e.g. from a stranger;
+    // they do not stop the session
+    pub fn skipped(&self) -> &[(Envelope, String)] {
+        &self.skipped
+    }
+
+    // signs out into the outbox
+    fn queue<M: Serialize, R: CryptoRng + RngCore>(
+        &mut self,
+        out: Vec<Outgoing<M>>,
+        rng: &mut R,
+    ) -> Result<()> {
+        for o in out {
+            let (to, msg): (Vec<usize>, M) = match o {
+                Outgoing::Broadcast(msg) => (
+                    (0..self.peers.len()).filter(|j| *j != self.i).collect(),
+                    msg,
+                ),
+                Outgoing::To(j, msg) => (vec![j], msg),
+            };
+            let msg = serde_json::to_string(&msg).map_err(transport_error)?;
+            for j in to {
+                let mut env = Envelope {
+                    session: self.session.clone(),
+                    from: self.peers[self.i].clone(),
+                    to: self.peers[j].clone(),
+                    seq: 0,
+                    msg: msg.clone(),
+                    sig: Vec::new(),
+                };
+                env.sig = self.key.sign(&env.digest(), rng);
+                self.outbox.push(env);
+            }
+        }
+        Ok(())
+    }
+
+    // deposits the outbox in order; what the relay has not taken stays
+    fn flush<T: Relay>(&mut self, relay: &mut T) -> Result<()> {
+        while !self.outbox.is_empty() {
+            relay.deposit(self.outbox[0].clone())?;
+            self.outbox.remove(0);
+        }
+        Ok(())
+    }
+
+    pub fn post<M: Serialize, T: Relay, R: CryptoRng + RngCore>(
+        &mut self,
+        relay: &mut T,
+        out: Vec<Outgoing<M>>,
+        rng: &mut R,
+    ) -> Result<()> {
+        self.queue(out, rng)?;
+        self.flush(relay)
+    }
+
+    pub fn start<H: Handler, T: Relay, R: CryptoRng + RngCore>(
+        &mut self,
+        party: &mut H,
+        relay: &mut T,
+        rng: &mut R,
+    ) -> Result<()> {
+        let out = party.start(rng)?;
+        self.post(relay, out, rng)
+    }
+
+    // the sender's position and the message, if the envelope is a peer's to us
+    fn open<M: DeserializeOwned>(&self, env: &Envelope) -> Result<(usize, M)> {
+        let from = self
+            .peers
+            .iter()
+            .position(|id| *id == env.from)
+            .ok_or(Error::UnknownParty)?;
+        if from == self.i || env.session != self.session || env.to != self.peers[self.i] {
+            return Err(Error::UnknownParty);
+        }
+        if !env.verify() {
+            return Err(Error::InvalidMessageSignature { party: from });
+        }
+        let msg = serde_json::from_str(&env.msg).map_err(transport_error)?;
+        Ok((from, msg))
+    }
+
+    // handles whatever is waiting; None if the party still needs its peers
+    pub fn sync<H: Handler, T: Relay, R: CryptoRng + RngCore>(
+        &mut self,
+        party: &mut H,
+        relay: &mut T,
+        rng: &mut R,
+    ) -> Result<Option<H::Output>> {
+        let me = self.peers[self.i].clone();
+        // left over from a sync that could not deposit everything
+        self.flush(relay)?;
+        if self.cursor > self.acked {
+            // handled by an earlier sync, and saved since
+            let sig = self
+                .key
+                .sign(&request_digest("ack", &self.session, self.cursor), rng);
+            relay.ack(&self.session, &me, self.cursor, &sig)?;
+            self.acked = self.cursor;
+        }
+        let sig = self
+            .key
+            .sign(&request_digest("fetch", &self.session, self.cursor), rng);
+        for env in relay.fetch(&self.session, &me, self.cursor, &sig)? {
+            if env.seq < self.cursor {
+                continue;
+            }
+            match self.open(&env) {
+                Ok((from, msg)) => {
+                    let out = party.handle(from, msg, rng)?;
+                    self.queue(out, rng)?;
+                }
+                Err(e) => {
+                    let why = e.to_string();
+                    self.skipped.push((env.clone(), why));
+                }
+            }
+            // only once the answers are in the outbox
+            self.cursor = env.seq + 1;
+        }
+        self.flush(relay)?;
+        Ok(party.output())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::GE;
+    use crate::auth::{Authenticated, IdentityKey};
+    use crate::etude::{digest_message, Party};
+    use crate::keygen::KeyGen;
+    use crate::relay::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::env;
+
+    fn envelope(key: &IdentityKey, to: &PartyId, msg: &str, rng: &mut StdRng) -> Envelope {
+        let mut env = Envelope {
+            session: "session 1".to_string(),
+            from: key.party_id(),
+            to: to.clone(),
+            seq: 0,
+            msg: msg.to_string(),
+            sig: Vec::new(),
+        };
+        env.sig = key.sign(&env.digest(), rng);
+        env
+    }
+
+    #[test]
+    fn test_relay() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+        let keys: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::new(&mut rng)).collect();
+        let pks: Vec<GE> = keys.iter().map(|k| k.public_key()).collect();
+        let ids: Vec<PartyId> = keys.iter().map(|k| k.party_id()).collect();
+        let mut parties: Vec<Authenticated<Party>> = (0..3)
+            .map(|i| {
+                let mut p = Party::with_key_share(i, 3, &shares[i], &[1, 2, 3]);
+                p.set_digest(digest_message(message));
+                Authenticated::new(p, i, b"session 1", keys[i].clone(), &pks)
+            })
+            .collect();
+        let mut mailers: Vec<Mailer> = (0..3)
+            .map(|i| Mailer::new("session 1", keys[i].clone(), &ids).unwrap())
+            .collect();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, Mailbox::new()));
+        let mut relay = RelayClient::new(&addr);
+
+        // a stranger and a peer's garbage are skipped, not fatal
+        let stranger = IdentityKey::new(&mut rng);
+        relay
+            .deposit(envelope(&stranger, &ids[0], "{}", &mut rng))
+            .unwrap();
+        relay
+            .deposit(envelope(&keys[2], &ids[0], "not json", &mut rng))
+            .unwrap();
+        let mut forged = envelope(&stranger, &ids[0], "{}", &mut rng);
+        forged.from = ids[1].clone();
+        assert!(relay.deposit(forged).is_err());
+
+        // never two parties online at once: each comes by in turn, does what it can and leaves
+        for i in 0..3 {
+            mailers[i]
+                .start(&mut parties[i], &mut relay, &mut rng)
+                .unwrap();
+        }
+        let mut sigs = vec![None, None, None];
+        for _ in 0..50 {
+            for i in 0..3 {
+                if sigs[i].is_none() {
+                    sigs[i] = mailers[i]
+                        .sync(&mut parties[i], &mut relay, &mut rng)
+                        .unwrap();
+                }
+            }
+        }
+        for sig in sigs {
+            assert!(sig.unwrap().verify(&shares[0].y, &digest_message(message)));
+        }
+        let skipped: Vec<&PartyId> = mailers[0].skipped().iter().map(|e| &e.0.from).collect();
+        assert_eq!(skipped, vec![&stranger.party_id(), &ids[2]]);
+
+        // the last sync acknowledges what the one before handled
+        for i in 0..3 {
+            mailers[i]
+                .sync(&mut parties[i], &mut relay, &mut rng)
+                .unwrap();
+        }
+        let sig = keys[0].sign(&request_digest("fetch", "session 1", 0), &mut rng);
+        assert_eq!(relay.fetch("session 1", &ids[0], 0, &sig), Ok(vec![]));
+        let sig = keys[1].sign(&request_digest("fetch", "session 1", 0), &mut rng);
+        assert!(relay.fetch("session 1", &ids[0], 0, &sig).is_err());
+    }
+
+    // as if the relay went away between the fetch and the deposits
+    struct Offline<'a>(&'a mut Mailbox);
+
+    impl<'a> Relay for Offline<'a> {
+        fn deposit(&mut self, _: Envelope) -> Result<()> {
+            Err(Error::Transport("offline".to_string()))
+        }
+
+        fn fetch(
+            &mut self,
+            session: &str,
+            to: &PartyId,
+            after: u64,
+            sig: &[u8],
+        ) -> Result<Vec<Envelope>> {
+            self.0.fetch(session, to, after, sig)
+        }
+
+        fn ack(&mut self, session: &str, to: &PartyId, upto: u64, sig: &[u8]) -> Result<()> {
+            self.0.ack(session, to, upto, sig)
+        }
+    }
+
+    #[test]
+    fn test_mailer_outbox() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 2).run_with_rng(&mut rng).unwrap();
+        let keys: Vec<IdentityKey> = (0..2).map(|_| IdentityKey::new(&mut rng)).collect();
+        let ids: Vec<PartyId> = keys.iter().map(|k| k.party_id()).collect();
+        let mut parties: Vec<Party> = (0..2)
+            .map(|i| {
+                let mut p = Party::with_key_share(i, 2, &shares[i], &[1, 2]);
+                p.set_digest(digest_message(message));
+                p
+            })
+            .collect();
+        let mut mailers: Vec<Mailer> = (0..2)
+            .map(|i| Mailer::new("session 1", keys[i].clone(), &ids).unwrap())
+            .collect();
+        let mut mailbox = Mailbox::new();
+        for i in 0..2 {
+            mailers[i]
+                .start(&mut parties[i], &mut mailbox, &mut rng)
+                .unwrap();
+        }
+
+        // party 0 handles its mail but cannot deposit the answers; the next sync does
+        let result = mailers[0].sync(&mut parties[0], &mut Offline(&mut mailbox), &mut rng);
+        assert!(result.is_err());
+        assert!(!mailers[0].outbox.is_empty());
+        let mut sigs = vec![None, None];
+        for _ in 0..20 {
+            for i in 0..2 {
+                if sigs[i].is_none() {
+                    sigs[i] = mailers[i]
+                        .sync(&mut parties[i], &mut mailbox, &mut rng)
+                        .unwrap();
+                }
+            }
+        }
+        assert!(mailers[0].outbox.is_empty());
+        for sig in sigs {
+            assert!(sig.unwrap().verify(&shares[0].y, &digest_message(message)));
+        }
+    }
+
+    #[test]
+    fn test_mailbox() {
+        let mut rng = StdRng::seed_from_u64(39);
+        let alice = IdentityKey::new(&mut rng);
+        let bob = IdentityKey::new(&mut rng);
+        let to = bob.party_id();
+        let path = env::temp_dir().join("gg18_etude_test_mailbox.json");
+        let _ = fs::remove_file(&path);
+
+        let mut mailbox = Mailbox::open(&path).unwrap();
+        mailbox
+            .deposit(envelope(&alice, &to, "1", &mut rng))
+            .unwrap();
+        mailbox
+            .deposit(envelope(&alice, &to, "2", &mut rng))
+            .unwrap();
+        let mut forged = envelope(&alice, &to, "3", &mut rng);
+        forged.msg = "4".to_string();
+        assert_eq!(mailbox.deposit(forged), Err(Error::Unauthenticated));
+        // a retry of "2", signed anew, is not stored twice
+        mailbox
+            .deposit(envelope(&alice, &to, "2", &mut rng))
+            .unwrap();
+
+        // only bob can fetch, as often as he likes, until he acknowledges
+        let fetch = |seq: u64, key: &IdentityKey, rng: &mut StdRng| {
+            key.sign(&request_digest("fetch", "session 1", seq), rng)
+        };
+        let sig = fetch(0, &alice, &mut rng);
+        assert_eq!(
+            mailbox.fetch("session 1", &to, 0, &sig),
+            Err(Error::Unauthenticated)
+        );
+        let sig = fetch(0, &bob, &mut rng);
+        let got = mailbox.fetch("session 1", &to, 0, &sig).unwrap();
+        assert_eq!(got.iter().map(|e| e.seq).collect::<Vec<u64>>(), vec![0, 1]);
+        assert_eq!(mailbox.fetch("session 1", &to, 0, &sig), Ok(got.clone()));
+        assert_eq!(
+            mailbox.fetch("session 2", &to, 0, &sig),
+            Err(Error::Unauthenticated)
+        );
+
+        let sig = bob.sign(&request_digest("ack", "session 1", 1), &mut rng);
+        assert_eq!(
+            mailbox.ack("session 1", &to, 2, &sig),
+            Err(Error::Unauthenticated)
+        );
+        mailbox.ack("session 1", &to, 1, &sig).unwrap();
+
+        // reopened from disk, with the numbering carried on
+        let mut mailbox = Mailbox::open(&path).unwrap();
+        mailbox
+            .deposit(envelope(&alice, &to, "5", &mut rng))
+            .unwrap();
+        let sig = fetch(0, &bob, &mut rng);
+        let got = mailbox.fetch("session 1", &to, 0, &sig).unwrap();
+        assert_eq!(got.iter().map(|e| e.seq).collect::<Vec<u64>>(), vec![1, 2]);
+        assert_eq!(got[0].msg, "2");
+        fs::remove_file(&path).unwrap();
+
+        // and a sender only gets so much into one mailbox
+        let mut mailbox = Mailbox::new();
+        for k in 0..MAX_DEPOSITS {
+            mailbox
+                .deposit(envelope(&alice, &to, &k.to_string(), &mut rng))
+                .unwrap();
+        }
+        assert_eq!(
+            mailbox.deposit(envelope(&alice, &to, "full", &mut rng)),
+            Err(Error::MailboxFull)
+        );
+        let carol = IdentityKey::new(&mut rng);
+        mailbox
+            .deposit(envelope(&carol, &to, "1", &mut rng))
+            .unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+extern crate curv;
+use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use self::curv::{FE, GE};
+use rand::{thread_rng, CryptoRng, RngCore};
+
+use crate::error::{Error, Result};
+use crate::keygen::KeyShare;
+use crate::mta;
+use crate::paillier_proof::{party_salt, PaillierKeyProof};
+use crate::ring_pedersen::{RingPedersenParams, RingPedersenProof};
+use crate::vss;
+
+// Old signers share wi = λi*xi to the new committee with a degree t' polynomial.
+// Σ wi = x, so the new shares are of the same y while the old polynomial is abandoned.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReshareBroadcast {
+    pub vss: Vec<GE>,
+}
+
+// keys of a new committee member, broadcast within the new committee
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewPartyBroadcast {
+    pub ek: mta::Enc,
+    pub ek_proof: PaillierKeyProof,
+    pub aux: RingPedersenParams,
+    pub aux_proof: RingPedersenProof,
+}
+
+pub struct OldParty {
+    share: KeyShare,
+    points: Vec<usize>,
+    new_t: usize,
+    poly: Vec<FE>,
+}
+
+impl OldParty {
+    // points are the Shamir evaluation points of the participating old signers
+    pub fn new(share: KeyShare, points: Vec<usize>, new_t: usize) -> Self {
+        Self {
+            share: share,
+            points: points,
+            new_t: new_t,
+            poly: Vec::new(),
+        }
+    }
+
+    pub fn begin<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<ReshareBroadcast> {
+        if !self.poly.is_empty() {
+            return Err(Error::InvalidState);
+        }
+        let wi = self.share.xi * vss::lagrange_coefficient::<FE>(self.share.i + 1, &self.points);
+        self.poly = vss::sample_polynomial(self.new_t, wi, rng);
+        Ok(ReshareBroadcast {
+            vss: vss::commit(&self.poly),
+        })
+    }
+
+    pub fn share_for(&self, j: usize) -> FE {
+        vss::evaluate(&self.poly, j + 1)
+    }
+}
+
+pub struct NewParty {
+    pub t: usize,
+    pub n: usize,
+    pub i: usize,
+
+    y: GE,
+    old_vss: Vec<GE>,
+    old_points: Vec<usize>,
+    dec: Option<mta::Dec>,
+
+    state: PartyState,
+}
+
+enum PartyState {
+    Void {},
+    Collecting {
+        eks: Vec<Option<mta::Enc>>,
+        auxs: Vec<Option<RingPedersenParams>>,
+        vsss: Vec<Option<Vec<GE>>>,
+        sijs: Vec<Option<FE>>,
+    },
+    Fin {
+        share: KeyShare,
+    },
+}
+
+impl NewParty {
+    pub fn get_state_name(&self) -> &'static str {
+        match self.state {
+            PartyState::Void {} => "Void",
+            PartyState::Collecting { .. } => "Collecting",
+            PartyState::Fin { .. } => "Fin",
+        }
+    }
+
+    // y and old_vss are public data of the old committee
+    pub fn new(
+        i: usize,
+        t: usize,
+        n: usize,
+        y: GE,
+        old_vss: Vec<GE>,
+        old_points: Vec<usize>,
+    ) -> Self {
+        Self {
+            t: t,
+            n: n,
+            i: i,
+            y: y,
+            old_vss: old_vss,
+            old_points: old_points,
+            dec: None,
+            state: PartyState::Void {},
+        }
+    }
+
+    pub fn begin<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<NewPartyBroadcast> {
+        match self.state {
+            PartyState::Void {} => {}
+            _ => return Err(Error::InvalidState),
+        }
+        let dec = mta::Dec::new(mta::plaintext_bits(&FE::q()), rng);
+        let (aux, aux_proof) = RingPedersenParams::generate(rng);
+        let msg = NewPartyBroadcast {
+            ek: dec.enc().clone(),
+            ek_proof: PaillierKeyProof::prove(&dec, &party_salt(self.i)),
+            aux: aux,
+            aux_proof: aux_proof,
+        };
+        let mut eks: Vec<Option<mta::Enc>> = vec![None; self.n];
+        let mut auxs: Vec<Option<RingPedersenParams>> = vec![None; self.n];
+        eks[self.i] = Some(msg.ek.clone());
+        auxs[self.i] = Some(msg.aux.clone());
+        self.dec = Some(dec);
+        self.state = PartyState::Collecting {
+            eks: eks,
+            auxs: auxs,
+            vsss: vec![None; self.old_points.len()],
+            sijs: vec![None; self.old_points.len()],
+        };
+        Ok(msg)
+    }
+
+    pub fn on_new_party(&mut self, from: usize, msg: NewPartyBroadcast) -> Result<()> {
+        if from >= self.n {
+            return Err(Error::UnknownParty);
+        }
+        if !msg.ek_proof.verify(&msg.ek, &party_salt(from)) {
+            return Err(Error::InvalidPaillierKey { party: from });
+        }
+        if !msg.aux.verify(&msg.aux_proof) {
+            return Err(Error::InvalidAuxParams { party: from });
+        }
+        if let PartyState::Collecting {
+            ref mut eks,
+            ref mut auxs,
+            ..
+        } = self.state
+        {
+            eks[from] = Some(msg.ek);
+            auxs[from] = Some(msg.aux);
+        } else {
+            return Err(Error::InvalidState);
+        }
+        self.try_finish()
+    }
+
+    // from is the position of the old signer in old_points
+    pub fn on_broadcast(&mut self, from: usize, msg: ReshareBroadcast) -> Result<()> {
+        if from >= self.old_points.len() {
+            return Err(Error::UnknownParty);
+        }
+        if msg.vss.len() != self.t + 1 {
+            return Err(Error::InvalidCommitment { party: from });
+        }
+        // g^wi = (g^xi)^λi, computable from the old commitments
+        let lambda = vss::lagrange_coefficient::<FE>(self.old_points[from], &self.old_points);
+        let gxi = vss::evaluate_in_exponent(&self.old_vss, self.old_points[from], 0)
+            .ok_or(Error::InvalidPublicKey)?;
+        if msg.vss[0] != gxi * lambda {
+            return Err(Error::InvalidCommitment { party: from });
+        }
+        if let PartyState::Collecting { ref mut vsss, .. } = self.state {
+            vsss[from] = Some(msg.vss);
+        } else {
+            return Err(Error::InvalidState);
+        }
+        self.try_finish()
+    }
+
+    pub fn on_share(&mut self, from: usize, sij: FE) -> Result<()> {
+        if from >= self.old_points.len() {
+            return Err(Error::UnknownParty);
+        }
+        if let PartyState::Collecting { ref mut sijs, .. } = self.state {
+            sijs[from] = Some(sij);
+        } else {
+            return Err(Error::InvalidState);
+        }
+        self.try_finish()
+    }
+
+    fn try_finish(&mut self) -> Result<()> {
+        let share = if let PartyState::Collecting {
+            ref eks,
+            ref auxs,
+            ref vsss,
+            ref sijs,
+        } = self.state
+        {
+            if eks.iter().any(|o| o.is_none())
+                || auxs.iter().any(|o| o.is_none())
+                || vsss.iter().any(|o| o.is_none())
+                || sijs.iter().any(|o| o.is_none())
+            {
+                return Ok(());
+            }
+            for (k, (v, s)) in vsss.iter().zip(sijs.iter()).enumerate() {
+                if !vss::validate(v.as_ref().unwrap(), self.i + 1, &s.unwrap()) {
+                    return Err(Error::InvalidShare { party: k });
+                }
+            }
+            let xi = sijs[1..]
+                .iter()
+                .fold(sijs[0].unwrap(), |acc, s| acc + s.unwrap());
+            let joint: Vec<GE> = (0..(self.t + 1))
+                .map(|k| {
+                    let cs: Vec<GE> = vsss.iter().map(|v| v.as_ref().unwrap()[k]).collect();
+                    vss::sum_points(&cs)
+                })
+                .collect();
+            if joint[0] != self.y {
+                return Err(Error::InvalidPublicKey);
+            }
+            KeyShare {
+                t: self.t,
+                n: self.n,
+                i: self.i,
+                xi: xi,
+                y: self.y,
+                vss: joint,
+                dec: self.dec.clone().unwrap(),
+                eks: eks.iter().map(|o| o.clone().unwrap()).collect(),
+                auxs: auxs.iter().map(|o| o.clone().unwrap()).collect(),
+            }
+        } else {
+            return Err(Error::InvalidState);
+        };
+        self.state = PartyState::Fin { share: share };
+        Ok(())
+    }
+
+    pub fn key_share(&self) -> Option<&KeyShare> {
+        match self.state {
+            PartyState::Fin { ref share } => Some(share),
+            _ => None,
+        }
+    }
+}
+
+pub struct Reshare {
+    old_parties: Vec<OldParty>,
+    new_parties: Vec<NewParty>,
+}
+
+impl Reshare {
+    // old_shares must be of t+1 or more members of the old committee
+    pub fn new(old_shares: Vec<KeyShare>, new_t: usize, new_n: usize) -> Result<Self> {
+        if old_shares.is_empty() || old_shares.len() <= old_shares[0].t {
+            return Err(Error::NotEnoughShares);
+        }
+        let points: Vec<usize> = old_shares.iter().map(|s| s.i + 1).collect();
+        let mut sorted = points.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        if sorted.len() != points.len() {
+            return Err(Error::DuplicateParty);
+        }
+        let y = old_shares[0].y;
+        let old_vss = old_shares[0].vss.clone();
+        let new_parties = (0..new_n)
+            .map(|i| NewParty::new(i, new_t, new_n, y, old_vss.clone(), points.clone()))
+            .collect();
+        let old_parties = old_shares
+            .into_iter()
+            .map(|s| OldParty::new(s, points.clone(), new_t))
+            .collect();
+        Ok(Self {
+            old_parties: old_parties,
+            new_parties: new_parties,
+        })
+    }
+
+    pub fn run(&mut self) -> Result<Vec<KeyShare>> {
+        self.run_with_rng(&mut thread_rng())
+    }
+
+    pub fn run_with_rng<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<Vec<KeyShare>> {
+        let new_n = self.new_parties.len();
+        let mut msgs = Vec::with_capacity(new_n);
+        for p in self.new_parties.iter_mut() {
+            msgs.push(p.begin(rng)?);
+        }
+        for i in 0..new_n {
+            for j in 0..new_n {
+                if i != j {
+                    self.new_parties[j].on_new_party(i, msgs[i].clone())?;
+                }
+            }
+        }
+        for k in 0..self.old_parties.len() {
+            let msg = self.old_parties[k].begin(rng)?;
+            for j in 0..new_n {
+                self.new_parties[j].on_broadcast(k, msg.clone())?;
+                let sij = self.old_parties[k].share_for(j);
+                self.new_parties[j].on_share(k, sij)?;
+            }
+        }
+        Ok(self
+            .new_parties
+            .iter()
+            .map(|p| p.key_share().unwrap().clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::{FE, GE};
+    use crate::etude::Etude;
+    use crate::keygen::KeyGen;
+    use crate::reshare::*;
+    use crate::vss;
+
+    #[test]
+    fn test_reshare() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let old = KeyGen::new(1, 3).run().unwrap();
+        let new = Reshare::new(vec![old[0].clone(), old[2].clone()], 2, 4)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(4, new.len());
+        for s in new.iter() {
+            assert_eq!(2, s.t);
+            assert_eq!(old[0].y, s.y);
+        }
+
+        let xs = [1, 2];
+        let mixed = old[0].xi * vss::lagrange_coefficient::<FE>(1, &xs)
+            + new[1].xi * vss::lagrange_coefficient::<FE>(2, &xs);
+        assert!(GE::generator() * mixed != old[0].y);
+
+        let mut gg18 = Etude::with_key_shares(&new[1..]).unwrap();
+        gg18.sign(&message);
+        assert!(gg18
+            .signature()
+            .unwrap()
+            .verify(&old[0].y, &::etude::digest_message(&message)));
+    }
+
+    #[test]
+    fn test_reject_bad_old_shares() {
+        let old = KeyGen::new(1, 3).run().unwrap();
+        assert_eq!(
+            Reshare::new(vec![], 1, 3).err(),
+            Some(Error::NotEnoughShares)
+        );
+        assert_eq!(
+            Reshare::new(vec![old[0].clone()], 1, 3).err(),
+            Some(Error::NotEnoughShares)
+        );
+        assert_eq!(
+            Reshare::new(vec![old[1].clone(), old[1].clone()], 1, 3).err(),
+            Some(Error::DuplicateParty)
+        );
+    }
+
+    #[test]
+    fn test_reject_unknown_sender() {
+        let mut rng = ::rand::thread_rng();
+        let old = KeyGen::new(1, 3).run().unwrap();
+        let mut old_party = OldParty::new(old[0].clone(), vec![1, 3], 1);
+        let msg = old_party.begin(&mut rng).unwrap();
+        let mut p = NewParty::new(0, 1, 2, old[0].y, old[0].vss.clone(), vec![1, 3]);
+        let keys = p.begin(&mut rng).unwrap();
+        assert_eq!(Err(Error::UnknownParty), p.on_broadcast(2, msg));
+        assert_eq!(
+            Err(Error::UnknownParty),
+            p.on_share(2, old_party.share_for(0))
+        );
+        assert_eq!(Err(Error::UnknownParty), p.on_new_party(2, keys));
+    }
+}
@@ -0,0 +1,139 @@
+extern crate curv;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::BigInt;
+use rand::{CryptoRng, RngCore};
+
+use crate::rng;
+
+// Auxiliary parameters (Ñ, h1, h2) for the MtA range proofs of GG18.
+// Each party publishes its own; the party proving to it commits with them.
+const N_TILDE_BITS: usize = 2048;
+const CHALLENGE_BITS: usize = 256;
+const SECURITY_BITS: usize = 128;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RingPedersenParams {
+    pub n_tilde: BigInt,
+    pub h1: BigInt,
+    pub h2: BigInt,
+}
+
+// proof of knowledge of x such that h2 = h1^x mod Ñ
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompositeDLogProof {
+    pub a: BigInt,
+    pub z: BigInt,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RingPedersenProof {
+    pub h2_h1: CompositeDLogProof,
+    pub h1_h2: CompositeDLogProof,
+}
+
+fn challenge(n_tilde: &BigInt, g: &BigInt, h: &BigInt, a: &BigInt) -> BigInt {
+    HSha256::create_hash(&vec![n_tilde, g, h, a])
+}
+
+impl CompositeDLogProof {
+    // h = g^x mod Ñ
+    pub fn prove<R: CryptoRng + RngCore>(
+        n_tilde: &BigInt,
+        g: &BigInt,
+        h: &BigInt,
+        x: &BigInt,
+        rng: &mut R,
+    ) -> Self {
+        let r = rng::random_bits(rng, N_TILDE_BITS + CHALLENGE_BITS + SECURITY_BITS);
+        let a = g.powm(&r, n_tilde);
+        let e = challenge(n_tilde, g, h, &a);
+        let z = r + e * x;
+        Self { a: a, z: z }
+    }
+
+    pub fn verify(&self, n_tilde: &BigInt, g: &BigInt, h: &BigInt) -> bool {
+        // z = r + e·x is a natural
+        if self.z < BigInt::from(0) {
+            return false;
+        }
+        let e = challenge(n_tilde, g, h, &self.a);
+        let lhs = g.powm(&self.z, n_tilde);
+        let rhs = (&self.a * h.powm(&e, n_tilde)).mod_floor(n_tilde);
+        lhs == rhs
+    }
+}
+
+impl RingPedersenParams {
+    pub fn generate<R: CryptoRng + RngCore>(rng: &mut R) -> (Self, RingPedersenProof) {
+        // Ñ = pq with safe primes, so QR_Ñ is cyclic of order p'q' and a random square
+        // generates it unless it is 1 mod p or mod q
+        let p = rng::random_safe_prime(rng, N_TILDE_BITS / 2);
+        let q = loop {
+            let q = rng::random_safe_prime(rng, N_TILDE_BITS / 2);
+            if q != p {
+                break q;
+            }
+        };
+        let one = BigInt::from(1);
+        let n_tilde = &p * &q;
+        let order = ((&p - &one) / BigInt::from(2)) * ((&q - &one) / BigInt::from(2));
+
+        let h1 = loop {
+            let r = rng::random_below(rng, &n_tilde);
+            let h1 = (&r * &r).mod_floor(&n_tilde);
+            if (&h1 - &one).gcd(&n_tilde) == one {
+                break h1;
+            }
+        };
+        let (alpha, beta) = loop {
+            let alpha = rng::random_below(rng, &order);
+            if let Some(beta) = alpha.invert(&order) {
+                break (alpha, beta);
+            }
+        };
+        let h2 = h1.powm(&alpha, &n_tilde);
+        let proof = RingPedersenProof {
+            h2_h1: CompositeDLogProof::prove(&n_tilde, &h1, &h2, &alpha, rng),
+            h1_h2: CompositeDLogProof::prove(&n_tilde, &h2, &h1, &beta, rng),
+        };
+        let params = Self {
+            n_tilde: n_tilde,
+            h1: h1,
+            h2: h2,
+        };
+        (params, proof)
+    }
+
+    pub fn verify(&self, proof: &RingPedersenProof) -> bool {
+        let one = BigInt::from(1);
+        if self.n_tilde.bit_length() < N_TILDE_BITS - 1
+            || self.h1 <= one
+            || self.h2 <= one
+            || self.h1 >= self.n_tilde
+            || self.h2 >= self.n_tilde
+            || self.h1 == self.h2
+            || self.h1.gcd(&self.n_tilde) != one
+            || self.h2.gcd(&self.n_tilde) != one
+        {
+            return false;
+        }
+        proof.h2_h1.verify(&self.n_tilde, &self.h1, &self.h2)
+            && proof.h1_h2.verify(&self.n_tilde, &self.h2, &self.h1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ring_pedersen::*;
+
+    #[test]
+    fn test_ring_pedersen() {
+        let (params, proof) = RingPedersenParams::generate(&mut ::rand::thread_rng());
+        assert!(params.verify(&proof));
+
+        let mut forged = params.clone();
+        forged.h2 = (&params.h2 * &params.h2).mod_floor(&params.n_tilde);
+        assert!(!forged.verify(&proof));
+    }
+}
@@ -0,0 +1,130 @@
+extern crate curv;
+extern crate gmp;
+use self::curv::elliptic::curves::traits::ECScalar;
+use self::curv::{BigInt, FE};
+use self::gmp::mpz::ProbabPrimeResult;
+use paillier::Keypair;
+use rand::{CryptoRng, RngCore};
+
+use crate::curve::ScalarOps;
+use crate::etude::fe_from_bigint;
+
+// Sampling helpers driven by a caller supplied RNG, so that a seeded RNG
+// reproduces keys, nonces and proofs exactly.
+
+pub fn random_bits<R: CryptoRng + RngCore>(rng: &mut R, bits: usize) -> BigInt {
+    let mut buf = vec![0u8; (bits + 7) / 8];
+    rng.fill_bytes(&mut buf);
+    if bits % 8 != 0 {
+        buf[0] &= 0xff >> (8 - bits % 8);
+    }
+    BigInt::from(&buf[..])
+}
+
+// uniform in [0, bound) by rejection
+pub fn random_below<R: CryptoRng + RngCore>(rng: &mut R, bound: &BigInt) -> BigInt {
+    let bits = bound.bit_length();
+    loop {
+        let x = random_bits(rng, bits);
+        if &x < bound {
+            return x;
+        }
+    }
+}
+
+pub fn random_fe<R: CryptoRng + RngCore>(rng: &mut R) -> FE {
+    fe_from_bigint(&random_below(rng, &FE::q()))
+}
+
+pub fn random_scalar<S: ScalarOps, R: CryptoRng + RngCore>(rng: &mut R) -> S {
+    S::from_bigint(&random_below(rng, &S::order()))
+}
+
+// the two top bits are set so that a product of two such primes has exactly 2*bits bits
+pub fn random_prime<R: CryptoRng + RngCore>(rng: &mut R, bits: usize) -> BigInt {
+    let top = BigInt::from(3) * BigInt::from(2).pow((bits - 2) as u32);
+    loop {
+        let p = (random_bits(rng, bits - 2) + &top).nextprime();
+        if p.bit_length() == bits {
+            return p;
+        }
+    }
+}
+
+fn is_probable_prime(x: &BigInt) -> bool {
+    x.probab_prime(40) != ProbabPrimeResult::NotPrime
+}
+
+// p = 2p' + 1 with p' prime, bits long with the two top bits set. Candidates for p' walk
+// up from a random start, skipping those where p' or p has a small factor.
+pub fn random_safe_prime<R: CryptoRng + RngCore>(rng: &mut R, bits: usize) -> BigInt {
+    let small: Vec<u64> = (3..2000u64)
+        .filter(|k| (2..*k).take_while(|d| d * d <= *k).all(|d| k % d != 0))
+        .collect();
+    let top = BigInt::from(3) * BigInt::from(2).pow((bits - 3) as u32);
+    loop {
+        let start = (random_bits(rng, bits - 3) + &top) | BigInt::from(1);
+        let rs: Vec<u64> = small
+            .iter()
+            .map(|k| Option::<u64>::from(&start.mod_floor(&BigInt::from(*k))).unwrap())
+            .collect();
+        for step in (0..(1u64 << 20)).step_by(2) {
+            let sieved = small.iter().zip(rs.iter()).all(|(k, r)| {
+                let q = (r + step) % k;
+                q != 0 && (2 * q + 1) % k != 0
+            });
+            if !sieved {
+                continue;
+            }
+            let q = &start + BigInt::from(step);
+            let p = BigInt::from(2) * &q + BigInt::from(1);
+            if p.bit_length() != bits {
+                break;
+            }
+            if is_probable_prime(&q) && is_probable_prime(&p) {
+                return p;
+            }
+        }
+    }
+}
+
+pub fn paillier_keypair<R: CryptoRng + RngCore>(rng: &mut R, bits: usize) -> Keypair {
+    let p = random_prime(rng, bits / 2);
+    let q = loop {
+        let q = random_prime(rng, bits - bits / 2);
+        if q != p {
+            break q;
+        }
+    };
+    Keypair { p: p, q: q }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::BigInt;
+    use crate::rng::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_seeded_sampling() {
+        let bound = BigInt::from(1000);
+        let mut r1 = StdRng::seed_from_u64(39);
+        let mut r2 = StdRng::seed_from_u64(39);
+        for _ in 0..32 {
+            let x = random_below(&mut r1, &bound);
+            assert!(x < bound);
+            assert_eq!(x, random_below(&mut r2, &bound));
+        }
+        let p = random_prime(&mut r1, 128);
+        assert_eq!(128, p.bit_length());
+        assert_eq!(p, random_prime(&mut r2, 128));
+
+        let p = random_safe_prime(&mut r1, 128);
+        assert_eq!(128, p.bit_length());
+        let q: BigInt = (&p - BigInt::from(1)) / BigInt::from(2);
+        assert_eq!(q, (&q - BigInt::from(1)).nextprime());
+        assert_eq!(p, random_safe_prime(&mut r2, 128));
+    }
+}
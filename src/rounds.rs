@@ -0,0 +1,643 @@
+extern crate curv;
+use self::curv::BigInt;
+use rand::{CryptoRng, RngCore};
+
+use crate::curve::{Curve, PointOps, ScalarOps, Secp256k1};
+use crate::dlog_proof::{session_salt, DLogProof};
+use crate::error::{Error, Result};
+use crate::etude::{
+    self, blame_reveals, check_echo, check_mta_request, check_mta_response, check_r, compute_r,
+};
+use crate::etude::{local_si, MtaPeer, MtaRequest, Reveal, Signature};
+use crate::keygen::KeyShare;
+use crate::mta;
+use crate::ring_pedersen::RingPedersenParams;
+use crate::rng;
+use crate::vss;
+
+// Signing as a typestate: every round consumes the previous one and only offers what is
+// valid at that point, so running rounds out of order does not compile. The math after the
+// MtA (R, the echo and R^ki check, the blame from reveals, si) is etude::Party's.
+// The messages of a round are passed in all at once, indexed by sender, None for ourselves.
+// The curve comes with the key share.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct GammaMessage<C: Curve = Secp256k1> {
+    pub gu: C::Point,
+    pub gr: C::Point,
+    pub gu_proof: DLogProof<C>,
+    pub gr_proof: DLogProof<C>,
+}
+
+struct Session<C: Curve> {
+    i: usize,
+    n: usize,
+    id: Vec<u8>,
+    points: Vec<usize>,
+    y: C::Point,
+    pubs: Vec<C::Point>, // g^wj every signer must send, from the key share
+    digest: C::Scalar,
+    wi: C::Scalar,
+    ki: C::Scalar,
+    gamma_i: C::Scalar,
+    eks: Vec<mta::Enc>,
+    auxs: Vec<RingPedersenParams>,
+}
+
+// everyone's message but our own
+fn collect<C: Curve, M>(
+    s: &Session<C>,
+    round: &'static str,
+    msgs: Vec<Option<M>>,
+) -> Result<Vec<(usize, M)>> {
+    if msgs.len() != s.n {
+        return Err(Error::InvalidState);
+    }
+    let missing: Vec<usize> = (0..s.n)
+        .filter(|j| *j != s.i && msgs[*j].is_none())
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::Timeout {
+            round: round,
+            missing: missing,
+        });
+    }
+    Ok(msgs
+        .into_iter()
+        .enumerate()
+        .filter(|(j, _)| *j != s.i)
+        .map(|(j, m)| (j, m.unwrap()))
+        .collect())
+}
+
+// Commits to γi and proves knowledge of wi and γi.
+pub struct Round1<C: Curve = Secp256k1> {
+    s: Session<C>,
+    dec: mta::Dec,
+    message: GammaMessage<C>,
+}
+
+impl<C: Curve> Round1<C> {
+    // i is the position among the signers, points their Shamir evaluation points; session
+    // is an id all signers agree on for this run
+    pub fn new<R: CryptoRng + RngCore>(
+        i: usize,
+        share: &KeyShare<C>,
+        points: &[usize],
+        session: &[u8],
+        digest: C::Scalar,
+        rng: &mut R,
+    ) -> Self {
+        let lambda = |x: usize| vss::lagrange_coefficient::<C::Scalar>(x, points);
+        let s = Session {
+            i: i,
+            n: points.len(),
+            id: session.to_vec(),
+            points: points.to_vec(),
+            y: share.y,
+            pubs: points
+                .iter()
+                .map(|x| share.public_share(x - 1) * lambda(*x))
+                .collect(),
+            digest: digest,
+            wi: share.xi * lambda(share.i + 1),
+            ki: rng::random_scalar(rng),
+            gamma_i: rng::random_scalar(rng),
+            eks: points.iter().map(|x| share.eks[x - 1].clone()).collect(),
+            auxs: points.iter().map(|x| share.auxs[x - 1].clone()).collect(),
+        };
+        let g = C::Point::base_point();
+        let salt = session_salt(session, points, i);
+        let message = GammaMessage {
+            gu: g * s.wi,
+            gr: g * s.gamma_i,
+            gu_proof: DLogProof::prove(&s.wi, &salt, rng),
+            gr_proof: DLogProof::prove(&s.gamma_i, &salt, rng),
+        };
+        Self {
+            s: s,
+            dec: share.dec.clone(),
+            message: message,
+        }
+    }
+
+    pub fn message(&self) -> &GammaMessage<C> {
+        &self.message
+    }
+
+    pub fn next<R: CryptoRng + RngCore>(
+        self,
+        msgs: Vec<Option<GammaMessage<C>>>,
+        rng: &mut R,
+    ) -> Result<Round2<C>> {
+        let s = self.s;
+        let msgs = collect(&s, "Round1", msgs)?;
+        let mut gus = vec![self.message.gu; s.n];
+        let mut grs = vec![self.message.gr; s.n];
+        for (j, m) in msgs {
+            let salt = session_salt(&s.id, &s.points, j);
+            if !m.gu_proof.verify(&m.gu, &salt) || !m.gr_proof.verify(&m.gr, &salt) {
+                return Err(Error::InvalidDLogProof { party: j });
+            }
+            if m.gu != s.pubs[j] {
+                return Err(Error::InvalidShare { party: j });
+            }
+            gus[j] = m.gu;
+            grs[j] = m.gr;
+        }
+        let (ki, gamma_i, wi) = (s.ki.to_bigint(), s.gamma_i.to_bigint(), s.wi.to_bigint());
+        let mut peers: Vec<MtaPeer<C::Scalar>> = (0..s.n)
+            .map(|_| MtaPeer::new(&self.dec, &ki, &gamma_i, &wi))
+            .collect();
+        // Alice towards every peer after us
+        let requests = ((s.i + 1)..s.n)
+            .map(|j| (j, peers[j].to_bob(Some(&s.auxs[j]), rng)))
+            .collect();
+        Ok(Round2 {
+            y: s.y,
+            s: s,
+            gus: gus,
+            grs: grs,
+            peers: peers,
+            requests: requests,
+        })
+    }
+}
+
+// Answers the MtA requests of the peers before us.
+pub struct Round2<C: Curve = Secp256k1> {
+    s: Session<C>,
+    y: C::Point,
+    gus: Vec<C::Point>,
+    grs: Vec<C::Point>,
+    peers: Vec<MtaPeer<C::Scalar>>,
+    requests: Vec<(usize, Vec<MtaRequest>)>,
+}
+
+impl<C: Curve> Round2<C> {
+    pub fn public_key(&self) -> &C::Point {
+        &self.y
+    }
+
+    // to the peers after us
+    pub fn requests(&self) -> &[(usize, Vec<MtaRequest>)] {
+        &self.requests
+    }
+
+    // requests[j] is Some exactly for the peers before us
+    pub fn next<R: CryptoRng + RngCore>(
+        self,
+        requests: Vec<Option<Vec<MtaRequest>>>,
+        rng: &mut R,
+    ) -> Result<Round3<C>> {
+        let Round2 {
+            s,
+            y,
+            gus,
+            grs,
+            mut peers,
+            ..
+        } = self;
+        if requests.len() != s.n {
+            return Err(Error::InvalidState);
+        }
+        let mut responses = Vec::with_capacity(s.i);
+        for (j, req) in requests.into_iter().enumerate().take(s.i) {
+            let req = match req {
+                Some(req) => req,
+                None => {
+                    return Err(Error::Timeout {
+                        round: "Round2",
+                        missing: vec![j],
+                    })
+                }
+            };
+            check_mta_request(
+                &C::Scalar::order(),
+                Some(&s.eks),
+                Some(&s.auxs),
+                (s.i, s.n),
+                j,
+                &req,
+            )?;
+            responses.push((j, peers[j].from_alice(&req, rng)));
+        }
+        Ok(Round3 {
+            s: s,
+            y: y,
+            gus: gus,
+            grs: grs,
+            peers: peers,
+            responses: responses,
+        })
+    }
+}
+
+// Finishes the MtA with the responses of the peers after us.
+pub struct Round3<C: Curve = Secp256k1> {
+    s: Session<C>,
+    y: C::Point,
+    gus: Vec<C::Point>,
+    grs: Vec<C::Point>,
+    peers: Vec<MtaPeer<C::Scalar>>,
+    responses: Vec<(usize, Vec<mta::RawCiphertext<'static>>)>,
+}
+
+impl<C: Curve> Round3<C> {
+    // to the peers before us
+    pub fn responses(&self) -> &[(usize, Vec<mta::RawCiphertext<'static>>)] {
+        &self.responses
+    }
+
+    // responses[j] is Some exactly for the peers after us
+    pub fn next(
+        self,
+        responses: Vec<Option<Vec<mta::RawCiphertext<'static>>>>,
+    ) -> Result<Round4<C>> {
+        let Round3 {
+            s,
+            y,
+            gus,
+            grs,
+            mut peers,
+            ..
+        } = self;
+        if responses.len() != s.n {
+            return Err(Error::InvalidState);
+        }
+        for (j, resp) in responses.into_iter().enumerate().skip(s.i + 1) {
+            match resp {
+                Some(resp) => {
+                    check_mta_response((s.i, s.n), j, &resp)?;
+                    peers[j].from_bob(&resp)
+                }
+                None => {
+                    return Err(Error::Timeout {
+                        round: "Round3",
+                        missing: vec![j],
+                    })
+                }
+            }
+        }
+        let zero = C::Scalar::from_bigint(&BigInt::from(0));
+        let mut kgamma = vec![zero; s.n];
+        let mut gammak = vec![zero; s.n];
+        let mut delta_i = s.ki * s.gamma_i;
+        let mut sigma_i = s.ki * s.wi;
+        for j in (0..s.n).filter(|j| *j != s.i) {
+            let (kr, rk, ku, uk) = peers[j].shares().ok_or(Error::InvalidState)?;
+            kgamma[j] = kr;
+            gammak[j] = rk;
+            delta_i = delta_i + kr + rk;
+            sigma_i = sigma_i + ku + uk;
+        }
+        Ok(Round4 {
+            s: s,
+            y: y,
+            gus: gus,
+            grs: grs,
+            kgamma: kgamma,
+            gammak: gammak,
+            delta_i: delta_i,
+            sigma_i: sigma_i,
+        })
+    }
+}
+
+// Opens δi = ki·γi + Σ MtA shares.
+pub struct Round4<C: Curve = Secp256k1> {
+    s: Session<C>,
+    y: C::Point,
+    gus: Vec<C::Point>,
+    grs: Vec<C::Point>,
+    kgamma: Vec<C::Scalar>,
+    gammak: Vec<C::Scalar>,
+    delta_i: C::Scalar,
+    sigma_i: C::Scalar,
+}
+
+impl<C: Curve> Round4<C> {
+    pub fn delta(&self) -> C::Scalar {
+        self.delta_i
+    }
+
+    pub fn next(self, deltas: Vec<Option<C::Scalar>>) -> Result<Round5<C>> {
+        let Round4 {
+            s,
+            y,
+            gus,
+            grs,
+            kgamma,
+            gammak,
+            delta_i,
+            sigma_i,
+        } = self;
+        let mut all = vec![delta_i; s.n];
+        for (j, d) in collect(&s, "Round4", deltas)? {
+            all[j] = d;
+        }
+        let delta = all[1..].iter().fold(all[0], |acc, d| acc + *d);
+        let sign_r = compute_r(&grs, &delta);
+        let message = RiMessage {
+            ri: sign_r * s.ki,
+            echo: etude::echo(&gus, &grs, &all),
+        };
+        Ok(Round5 {
+            s: s,
+            y: y,
+            grs: grs,
+            kgamma: kgamma,
+            gammak: gammak,
+            deltas: all,
+            sigma_i: sigma_i,
+            sign_r: sign_r,
+            message: message,
+        })
+    }
+}
+
+// R^ki, with a hash of every broadcast received so far
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct RiMessage<C: Curve = Secp256k1> {
+    pub ri: C::Point,
+    pub echo: Vec<BigInt>,
+}
+
+// Opens R^ki; the echoes must agree and Π R^kj = g before any si goes out.
+pub struct Round5<C: Curve = Secp256k1> {
+    s: Session<C>,
+    y: C::Point,
+    grs: Vec<C::Point>,
+    kgamma: Vec<C::Scalar>,
+    gammak: Vec<C::Scalar>,
+    deltas: Vec<C::Scalar>,
+    sigma_i: C::Scalar,
+    sign_r: C::Point,
+    message: RiMessage<C>,
+}
+
+// what follows the R check
+pub enum Checked<C: Curve = Secp256k1> {
+    Passed(Round6<C>),
+    Failed(Revealing<C>),
+}
+
+impl<C: Curve> Round5<C> {
+    pub fn message(&self) -> &RiMessage<C> {
+        &self.message
+    }
+
+    pub fn next(self, msgs: Vec<Option<RiMessage<C>>>) -> Result<Checked<C>> {
+        let s = self.s;
+        let mut ris = vec![self.message.ri; s.n];
+        for (j, m) in collect(&s, "Round5", msgs)? {
+            check_echo(s.i, j, &m.echo, &self.message.echo)?;
+            ris[j] = m.ri;
+        }
+        if check_r(&ris) {
+            return Ok(Checked::Passed(Round6 {
+                si: local_si(&s.digest, &s.ki, &self.sign_r, &self.sigma_i),
+                s: s,
+                y: self.y,
+                sign_r: self.sign_r,
+            }));
+        }
+        // ki and γi are thrown away with this run
+        let reveal = Reveal {
+            k: s.ki,
+            gamma: s.gamma_i,
+            kgamma: self.kgamma,
+            gammak: self.gammak,
+        };
+        Ok(Checked::Failed(Revealing {
+            s: s,
+            grs: self.grs,
+            deltas: self.deltas,
+            sign_r: self.sign_r,
+            ris: ris,
+            reveal: reveal,
+        }))
+    }
+}
+
+// After the R check failed: everyone opens ki, γi and its δ MtA shares.
+pub struct Revealing<C: Curve = Secp256k1> {
+    s: Session<C>,
+    grs: Vec<C::Point>,
+    deltas: Vec<C::Scalar>,
+    sign_r: C::Point,
+    ris: Vec<C::Point>,
+    reveal: Reveal<C::Scalar>,
+}
+
+impl<C: Curve> Revealing<C> {
+    pub fn reveal(&self) -> &Reveal<C::Scalar> {
+        &self.reveal
+    }
+
+    // the party to blame
+    pub fn finish(self, reveals: Vec<Option<Reveal<C::Scalar>>>) -> Error {
+        let mut all = vec![self.reveal.clone(); self.s.n];
+        match collect(&self.s, "Revealing", reveals) {
+            Ok(reveals) => reveals.into_iter().for_each(|(j, r)| all[j] = r),
+            Err(e) => return e,
+        }
+        let all: Vec<&Reveal<C::Scalar>> = all.iter().collect();
+        blame_reveals(&self.grs, &self.deltas, &self.sign_r, &self.ris, &all)
+    }
+}
+
+// Opens si; the sum is the signature.
+pub struct Round6<C: Curve = Secp256k1> {
+    s: Session<C>,
+    y: C::Point,
+    sign_r: C::Point,
+    si: C::Scalar,
+}
+
+impl<C: Curve> Round6<C> {
+    pub fn si(&self) -> C::Scalar {
+        self.si
+    }
+
+    pub fn finish(self, sis: Vec<Option<C::Scalar>>) -> Result<Signature<C::Scalar>> {
+        let sign_s = collect(&self.s, "Round6", sis)?
+            .into_iter()
+            .fold(self.si, |acc, (_, si)| acc + si);
+        let sig = Signature::new(&self.sign_r, &sign_s);
+        if !sig.verify(&self.y, &self.s.digest) {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(sig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::BigInt;
+    use crate::curve::{hash_to_scalar, P256};
+    use crate::etude::digest_message;
+    use crate::keygen::{KeyGen, KeyShare};
+    use crate::rounds::*;
+
+    fn others<M: Clone>(i: usize, all: &[M]) -> Vec<Option<M>> {
+        (0..all.len())
+            .map(|j| if i == j { None } else { Some(all[j].clone()) })
+            .collect()
+    }
+
+    // runs the rounds for the given signers, routing every message by hand; `bad` adds
+    // one to the δi of that signer
+    fn sign<C: Curve>(
+        signers: &[&KeyShare<C>],
+        points: &[usize],
+        m: C::Scalar,
+        bad: Option<usize>,
+    ) -> Vec<Result<Signature<C::Scalar>>> {
+        let mut rng = ::rand::thread_rng();
+        let n = points.len();
+
+        let r1: Vec<Round1<C>> = signers
+            .iter()
+            .enumerate()
+            .map(|(i, share)| Round1::new(i, share, points, b"session 1", m, &mut rng))
+            .collect();
+        let gammas: Vec<GammaMessage<C>> = r1.iter().map(|r| r.message().clone()).collect();
+        let r2: Vec<Round2<C>> = r1
+            .into_iter()
+            .enumerate()
+            .map(|(i, r)| r.next(others(i, &gammas), &mut rng).unwrap())
+            .collect();
+        assert_eq!(*r2[0].public_key(), signers[0].y);
+
+        let mut requests: Vec<Vec<Option<Vec<MtaRequest>>>> =
+            (0..n).map(|_| (0..n).map(|_| None).collect()).collect();
+        for (i, r) in r2.iter().enumerate() {
+            for (j, req) in r.requests() {
+                requests[*j][i] = Some(req.clone());
+            }
+        }
+        let r3: Vec<Round3<C>> = r2
+            .into_iter()
+            .zip(requests.into_iter())
+            .map(|(r, reqs)| r.next(reqs, &mut rng).unwrap())
+            .collect();
+
+        let mut responses: Vec<Vec<Option<Vec<_>>>> =
+            (0..n).map(|_| (0..n).map(|_| None).collect()).collect();
+        for (j, r) in r3.iter().enumerate() {
+            for (i, resp) in r.responses() {
+                responses[*i][j] = Some(resp.clone());
+            }
+        }
+        let mut r4: Vec<Round4<C>> = r3
+            .into_iter()
+            .zip(responses.into_iter())
+            .map(|(r, resps)| r.next(resps).unwrap())
+            .collect();
+        if let Some(b) = bad {
+            r4[b].delta_i = r4[b].delta_i + C::Scalar::from_bigint(&BigInt::from(1));
+        }
+
+        let deltas: Vec<C::Scalar> = r4.iter().map(|r| r.delta()).collect();
+        let r5: Vec<Round5<C>> = r4
+            .into_iter()
+            .enumerate()
+            .map(|(i, r)| r.next(others(i, &deltas)).unwrap())
+            .collect();
+
+        let ris: Vec<RiMessage<C>> = r5.iter().map(|r| r.message().clone()).collect();
+        let checked: Vec<Checked<C>> = r5
+            .into_iter()
+            .enumerate()
+            .map(|(i, r)| r.next(others(i, &ris)).unwrap())
+            .collect();
+
+        match checked[0] {
+            Checked::Passed(_) => {
+                let r6: Vec<Round6<C>> = checked
+                    .into_iter()
+                    .map(|c| match c {
+                        Checked::Passed(r) => r,
+                        Checked::Failed(_) => panic!("the R check differs"),
+                    })
+                    .collect();
+                let sis: Vec<C::Scalar> = r6.iter().map(|r| r.si()).collect();
+                r6.into_iter()
+                    .enumerate()
+                    .map(|(i, r)| r.finish(others(i, &sis)))
+                    .collect()
+            }
+            Checked::Failed(_) => {
+                let revealing: Vec<Revealing<C>> = checked
+                    .into_iter()
+                    .map(|c| match c {
+                        Checked::Failed(r) => r,
+                        Checked::Passed(_) => panic!("the R check differs"),
+                    })
+                    .collect();
+                let reveals: Vec<Reveal<C::Scalar>> =
+                    revealing.iter().map(|r| r.reveal().clone()).collect();
+                revealing
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, r)| Err(r.finish(others(i, &reveals))))
+                    .collect()
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounds() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let shares = KeyGen::new(1, 3).run().unwrap();
+        let m = digest_message(message);
+        let sigs = sign(&[&shares[0], &shares[2]], &[1, 3], m, None);
+        let sig = sigs[0].clone().unwrap();
+        assert_eq!(sig, sigs[1].clone().unwrap());
+        assert!(sig.verify(&shares[0].y, &m));
+    }
+
+    #[test]
+    fn test_rounds_blame() {
+        let shares = KeyGen::new(2, 3).run().unwrap();
+        let m = digest_message("Miku-san maji tenshi!".as_bytes());
+        let signers: Vec<&KeyShare> = shares.iter().collect();
+        for r in sign(&signers, &[1, 2, 3], m, Some(1)) {
+            match r {
+                Err(Error::InvalidDelta { party: 1 }) => (),
+                r => panic!("unexpected {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounds_reject_wrong_public_share() {
+        let mut rng = ::rand::thread_rng();
+        let shares = KeyGen::new(1, 2).run().unwrap();
+        let other = KeyGen::new(1, 2).run().unwrap();
+        let m = digest_message("Miku-san maji tenshi!".as_bytes());
+        // signer 1 brings a share of another key, with valid proofs for it
+        let r0 = Round1::new(0, &shares[0], &[1, 2], b"session 1", m, &mut rng);
+        let r1 = Round1::new(1, &other[1], &[1, 2], b"session 1", m, &mut rng);
+        match r0.next(vec![None, Some(r1.message().clone())], &mut rng) {
+            Err(Error::InvalidShare { party: 1 }) => (),
+            Err(e) => panic!("unexpected {:?}", e),
+            Ok(_) => panic!("accepted a foreign share"),
+        }
+    }
+
+    #[test]
+    fn test_rounds_p256() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let shares = KeyGen::<P256>::with_curve(1, 3).run().unwrap();
+        let m = hash_to_scalar(message);
+        let sigs = sign(&[&shares[1], &shares[2]], &[2, 3], m, None);
+        let sig = sigs[0].clone().unwrap();
+        assert_eq!(sig, sigs[1].clone().unwrap());
+        assert!(sig.verify(&shares[0].y, &m));
+    }
+}
@@ -0,0 +1,684 @@
+extern crate curv;
+extern crate sha2;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use self::curv::{BigInt, FE, GE};
+use self::sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+
+use crate::curve::{self, to_bytes32, ScalarOps};
+use crate::error::{Error, Result};
+use crate::keygen::KeyShare;
+use crate::network::{handle_buffered, replay_backlog, undelivered, Buffered, Handler, Outgoing};
+use crate::rng;
+use crate::vss;
+
+// Threshold BIP340 signing with the keygen shares. No MtA: every signer commits to
+// Ri = g^ki, opens it, and sends si = ki + e·λi·xi, negated as BIP340's x-only R and y
+// require. Each si is checked against g^(λi·xi), so a bad one names its sender.
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let t = Sha256::digest(tag.as_bytes());
+    let mut h = Sha256::new();
+    h.input(&t);
+    h.input(&t);
+    for d in data {
+        h.input(d);
+    }
+    let mut r = [0u8; 32];
+    r.copy_from_slice(&h.result());
+    r
+}
+
+// the x-only public key of BIP340
+pub fn xonly(p: &GE) -> [u8; 32] {
+    to_bytes32(&p.x_coor().unwrap())
+}
+
+fn has_even_y(p: &GE) -> bool {
+    p.y_coor().unwrap().mod_floor(&BigInt::from(2)) == BigInt::from(0)
+}
+
+// the point with this x coordinate and an even y
+pub fn lift_x(x: &[u8; 32]) -> Option<GE> {
+    curve::lift_x(&BigInt::from(&x[..]), false)
+}
+
+fn challenge(rx: &[u8; 32], px: &[u8; 32], m: &[u8]) -> FE {
+    let e = tagged_hash("BIP0340/challenge", &[rx, px, m]);
+    FE::from_bigint(&BigInt::from(&e[..]))
+}
+
+fn negate_if(x: FE, neg: bool) -> FE {
+    if neg {
+        FE::from_bigint(&(FE::q() - x.to_big_int()))
+    } else {
+        x
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SchnorrSignature {
+    pub rx: [u8; 32],
+    pub s: FE,
+}
+
+impl SchnorrSignature {
+    // rx || s
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = self.rx.to_vec();
+        v.extend_from_slice(&to_bytes32(&self.s.to_big_int()));
+        v
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 64 {
+            return None;
+        }
+        let s = BigInt::from(&bytes[32..]);
+        if s >= FE::q() {
+            return None;
+        }
+        let mut rx = [0u8; 32];
+        rx.copy_from_slice(&bytes[..32]);
+        Some(SchnorrSignature {
+            rx: rx,
+            s: FE::from_bigint(&s),
+        })
+    }
+
+    // BIP340 verification: s·G == lift_x(rx) + e·P
+    pub fn verify(&self, pk: &[u8; 32], m: &[u8]) -> bool {
+        let (p, r) = match (lift_x(pk), lift_x(&self.rx)) {
+            (Some(p), Some(r)) => (p, r),
+            _ => return false,
+        };
+        // curv cannot multiply by zero, and no valid signature has s = 0 in practice
+        if self.s.to_big_int() == BigInt::from(0) {
+            return false;
+        }
+        let e = challenge(&self.rx, pk, m);
+        GE::generator() * self.s == r + p * e
+    }
+}
+
+pub fn verify(pk: &[u8; 32], m: &[u8], sig: &[u8]) -> bool {
+    match SchnorrSignature::from_bytes(sig) {
+        Some(sig) => sig.verify(pk, m),
+        None => false,
+    }
+}
+
+// single-signer BIP340 with a random nonce, e.g. for identity keys
+pub fn sign<R: CryptoRng + RngCore>(secret: &FE, m: &[u8], rng: &mut R) -> SchnorrSignature {
+    let p = GE::generator() * secret;
+    let d = negate_if(*secret, !has_even_y(&p));
+    let k = rng::random_fe(rng);
+    let r = GE::generator() * k;
+    let k = negate_if(k, !has_even_y(&r));
+    let e = challenge(&xonly(&r), &xonly(&p), m);
+    SchnorrSignature {
+        rx: xonly(&r),
+        s: k + e * d,
+    }
+}
+
+fn commitment(i: usize, r: &GE) -> BigInt {
+    HSha256::create_hash(&vec![
+        &BigInt::from(i as u64),
+        &r.x_coor().unwrap(),
+        &r.y_coor().unwrap(),
+    ])
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SchnorrMessage {
+    Commit(BigInt),
+    Nonce(GE),
+    Partial(FE),
+}
+
+enum PartyState {
+    Void {},
+    Committing {
+        commits: Vec<Option<BigInt>>,
+    },
+    Revealing {
+        commits: Vec<BigInt>,
+        nonces: Vec<Option<GE>>,
+    },
+    Signing {
+        nonces: Vec<GE>,
+        e: FE,
+        sis: Vec<Option<FE>>,
+    },
+    Fin {
+        sig: SchnorrSignature,
+    },
+}
+
+pub struct Party {
+    pub n: usize,
+    pub i: usize,
+
+    wi: FE,
+    y: GE,
+    pubs: Vec<GE>, // g^wj of every signer
+    message: Vec<u8>,
+    ki: Option<FE>,
+    ri: Option<GE>,
+    // BIP340 wants an even y for both the key and R; the shares are negated to match
+    neg_y: bool,
+    neg_r: bool,
+
+    state: PartyState,
+    backlog: Vec<(usize, SchnorrMessage)>,
+}
+
+impl Party {
+    // i is the position among the signers, points their Shamir evaluation points
+    pub fn new(i: usize, share: &KeyShare, points: &[usize], message: &[u8]) -> Self {
+        let lambda = |x: usize| vss::lagrange_coefficient::<FE>(x, points);
+        Party {
+            n: points.len(),
+            i: i,
+            wi: share.xi * lambda(share.i + 1),
+            y: share.y,
+            pubs: points
+                .iter()
+                .map(|x| share.public_share(x - 1) * lambda(*x))
+                .collect(),
+            message: message.to_vec(),
+            ki: None,
+            ri: None,
+            neg_y: !has_even_y(&share.y),
+            neg_r: false,
+            state: PartyState::Void {},
+            backlog: Vec::new(),
+        }
+    }
+
+    pub fn get_state_name(&self) -> &'static str {
+        match self.state {
+            PartyState::Void {} => "Void",
+            PartyState::Committing { .. } => "Committing",
+            PartyState::Revealing { .. } => "Revealing",
+            PartyState::Signing { .. } => "Signing",
+            PartyState::Fin { .. } => "Fin",
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        xonly(&self.y)
+    }
+
+    pub fn signature(&self) -> Option<SchnorrSignature> {
+        match self.state {
+            PartyState::Fin { ref sig } => Some(*sig),
+            _ => None,
+        }
+    }
+
+    // the partial signature of party j, given R and e
+    fn partial_ok(&self, j: usize, nonce: &GE, e: &FE, sj: &FE) -> bool {
+        let r = *nonce * negate_if(FE::from_bigint(&BigInt::from(1)), self.neg_r);
+        let w = self.pubs[j] * negate_if(*e, self.neg_y);
+        GE::generator() * *sj == r + w
+    }
+
+    fn on_commit(&mut self, from: usize, c: BigInt) -> Result<Vec<Outgoing<SchnorrMessage>>> {
+        let commits = match self.state {
+            PartyState::Committing { ref mut commits } => {
+                if commits[from].is_some() {
+                    return Ok(Vec::new());
+                }
+                commits[from] = Some(c);
+                if commits.iter().any(|c| c.is_none()) {
+                    return Ok(Vec::new());
+                }
+                commits.iter().map(|c| c.clone().unwrap()).collect()
+            }
+            _ => return Err(Error::InvalidState),
+        };
+        let ri = self.ri.unwrap();
+        let mut nonces = vec![None; self.n];
+        nonces[self.i] = Some(ri);
+        self.state = PartyState::Revealing {
+            commits: commits,
+            nonces: nonces,
+        };
+        Ok(vec![Outgoing::Broadcast(SchnorrMessage::Nonce(ri))])
+    }
+
+    fn on_nonce(&mut self, from: usize, r: GE) -> Result<Vec<Outgoing<SchnorrMessage>>> {
+        let nonces: Vec<GE> = match self.state {
+            PartyState::Revealing {
+                ref commits,
+                ref mut nonces,
+            } => {
+                if nonces[from].is_some() {
+                    return Ok(Vec::new());
+                }
+                if commitment(from, &r) != commits[from] {
+                    return Err(Error::InvalidCommitment { party: from });
+                }
+                nonces[from] = Some(r);
+                if nonces.iter().any(|r| r.is_none()) {
+                    return Ok(Vec::new());
+                }
+                nonces.iter().map(|r| r.unwrap()).collect()
+            }
+            _ => return Err(Error::InvalidState),
+        };
+        let sign_r = vss::sum_points(&nonces);
+        self.neg_r = !has_even_y(&sign_r);
+        let e = challenge(&xonly(&sign_r), &xonly(&self.y), &self.message);
+        let si = negate_if(self.ki.unwrap(), self.neg_r) + e * negate_if(self.wi, self.neg_y);
+        let mut sis = vec![None; self.n];
+        sis[self.i] = Some(si);
+        self.state = PartyState::Signing {
+            nonces: nonces,
+            e: e,
+            sis: sis,
+        };
+        Ok(vec![Outgoing::Broadcast(SchnorrMessage::Partial(si))])
+    }
+
+    fn on_partial(&mut self, from: usize, sj: FE) -> Result<()> {
+        let ok = match self.state {
+            PartyState::Signing {
+                ref nonces,
+                ref e,
+                ref sis,
+            } => {
+                if sis[from].is_some() {
+                    return Ok(());
+                }
+                self.partial_ok(from, &nonces[from], e, &sj)
+            }
+            _ => return Err(Error::InvalidState),
+        };
+        if !ok {
+            return Err(Error::InvalidPartialSignature { party: from });
+        }
+        let sig = if let PartyState::Signing {
+            ref nonces,
+            ref mut sis,
+            ..
+        } = self.state
+        {
+            sis[from] = Some(sj);
+            if sis.iter().any(|s| s.is_none()) {
+                return Ok(());
+            }
+            SchnorrSignature {
+                rx: xonly(&vss::sum_points(nonces)),
+                s: sis[1..]
+                    .iter()
+                    .fold(sis[0].unwrap(), |acc, s| acc + s.unwrap()),
+            }
+        } else {
+            return Err(Error::InvalidState);
+        };
+        if !sig.verify(&self.public_key(), &self.message) {
+            return Err(Error::InvalidSignature);
+        }
+        self.state = PartyState::Fin { sig: sig };
+        Ok(())
+    }
+}
+
+impl Handler for Party {
+    type Message = SchnorrMessage;
+    type Output = SchnorrSignature;
+
+    fn start<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SchnorrMessage>>> {
+        match self.state {
+            PartyState::Void {} => {}
+            _ => return Err(Error::InvalidState),
+        }
+        let ki = rng::random_fe(rng);
+        let ri = GE::generator() * ki;
+        let c = commitment(self.i, &ri);
+        let mut commits = vec![None; self.n];
+        commits[self.i] = Some(c.clone());
+        self.ki = Some(ki);
+        self.ri = Some(ri);
+        self.state = PartyState::Committing { commits: commits };
+        let mut out = vec![Outgoing::Broadcast(SchnorrMessage::Commit(c))];
+        out.extend(replay_backlog(self, rng)?);
+        Ok(out)
+    }
+
+    fn handle<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: SchnorrMessage,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<SchnorrMessage>>> {
+        handle_buffered(self, from, msg, rng)
+    }
+
+    fn output(&self) -> Option<SchnorrSignature> {
+        self.signature()
+    }
+
+    fn round(&self) -> &'static str {
+        self.get_state_name()
+    }
+
+    fn missing(&self) -> Vec<usize> {
+        let delivered: Vec<bool> = match self.state {
+            PartyState::Committing { ref commits } => commits.iter().map(|o| o.is_some()).collect(),
+            PartyState::Revealing { ref nonces, .. } => {
+                nonces.iter().map(|o| o.is_some()).collect()
+            }
+            PartyState::Signing { ref sis, .. } => sis.iter().map(|o| o.is_some()).collect(),
+            _ => return Vec::new(),
+        };
+        undelivered(self.i, &delivered)
+    }
+}
+
+impl Buffered for Party {
+    type Message = SchnorrMessage;
+
+    fn party(&self) -> (usize, usize) {
+        (self.i, self.n)
+    }
+
+    fn round_of(msg: &SchnorrMessage) -> usize {
+        match msg {
+            SchnorrMessage::Commit(_) => 1,
+            SchnorrMessage::Nonce(_) => 2,
+            SchnorrMessage::Partial(_) => 3,
+        }
+    }
+
+    fn round_no(&self) -> usize {
+        match self.state {
+            PartyState::Void {} => 0,
+            PartyState::Committing { .. } => 1,
+            PartyState::Revealing { .. } => 2,
+            PartyState::Signing { .. } => 3,
+            PartyState::Fin { .. } => 4,
+        }
+    }
+
+    fn backlog(&self) -> &Vec<(usize, SchnorrMessage)> {
+        &self.backlog
+    }
+
+    fn backlog_mut(&mut self) -> &mut Vec<(usize, SchnorrMessage)> {
+        &mut self.backlog
+    }
+
+    fn ready_for(&self, msg: &SchnorrMessage) -> bool {
+        match (msg, &self.state) {
+            (SchnorrMessage::Commit(_), PartyState::Committing { .. }) => true,
+            (SchnorrMessage::Nonce(_), PartyState::Revealing { .. }) => true,
+            (SchnorrMessage::Partial(_), PartyState::Signing { .. }) => true,
+            _ => false,
+        }
+    }
+
+    fn handle_now<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: SchnorrMessage,
+        _rng: &mut R,
+    ) -> Result<Vec<Outgoing<SchnorrMessage>>> {
+        match msg {
+            SchnorrMessage::Commit(c) => self.on_commit(from, c),
+            SchnorrMessage::Nonce(r) => self.on_nonce(from, r),
+            SchnorrMessage::Partial(s) => {
+                self.on_partial(from, s)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::ECPoint;
+    use self::curv::{BigInt, FE, GE};
+    use crate::curve::ScalarOps;
+    use crate::error::Error;
+    use crate::keygen::{KeyGen, KeyShare};
+    use crate::network::Outgoing;
+    use crate::schnorr::*;
+    use crate::simulator::Simulator;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        ::eth::from_hex(s).unwrap()
+    }
+
+    #[test]
+    fn test_bip340_vectors() {
+        // test vectors 0 to 18 of BIP340
+        let vectors = [
+            (
+                "F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215\
+                 25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0",
+                true,
+            ),
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE3341\
+                 8906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A",
+                true,
+            ),
+            (
+                "DD308AFEC5777E13121FA72B9CC1B7CC0139715309B086C960E18FD969774EB8",
+                "7E2D58D8B3BCDF1ABADEC7829054F90DDA9805AAB56C77333024B9D0A508B75C",
+                "5831AAEED7B44BB74E5EAB94BA9D4294C49BCF2A60728D8B4C200F50DD313C1B\
+                 AB745879A5AD954A72C45A91C3A51D3C7ADEA98D82F8481E0E1E03674A6F3FB7",
+                true,
+            ),
+            // 3: fails if the message is reduced mod p or n
+            (
+                "25D1DFF95105F5253C4022F628A996AD3A0D95FBF21D468A1B33F8C160D8F517",
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+                "7EB0509757E246F19449885651611CB965ECC1A187DD51B64FDA1EDC9637D5EC\
+                 97582B9CB13DB3933705B32BA982AF5AF25FD78881EBB32771FC5922EFC66EA3",
+                true,
+            ),
+            // 4: R with leading zero bytes
+            (
+                "D69C3509BB99E412E68B0FE8544E72837DFA30746D8BE2AA65975F29D22DC7B9",
+                "4DF3C3F68FCC83B27E9D42C90431A72499F17875C81A599B566C9889B9696703",
+                "00000000000000000000003B78CE563F89A0ED9414F5AA28AD0D96D6795F9C63\
+                 76AFB1548AF603B3EB45C9F8207DEE1060CB71C04E80F593060B07D28308D7F4",
+                true,
+            ),
+            (
+                "EEFDEA4CDB677750A420FEE807EACF21EB9898AE79B9768766E4FAA04A2D4A34",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE3341\
+                 8906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A",
+                false,
+            ),
+            // 6: has_even_y(R) is false
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "FFF97BD5755EEEA420453A14355235D382F6472F8568A18B2F057A1460297556\
+                 3CC27944640AC607CD107AE10923D9EF7A73C643E166BE5EBEAFA34B1AC553E2",
+                false,
+            ),
+            // 7: negated message
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "1FA62E331EDBC21C394792D2AB1100A7B432B013DF3F6FF4F99FCB33E0E1515F\
+                 28890B3EDB6E7189B630448B515CE4F8622A954CFE545735AAEA5134FCCDB2BD",
+                false,
+            ),
+            // 8: negated s
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769\
+                 961764B3AA9B2FFCB6EF947B6887A226E8D7C93E00C5ED0C1834FF0D0C2E6DA6",
+                false,
+            ),
+            // 9: sG - eP is infinite, x(inf) as 0
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "0000000000000000000000000000000000000000000000000000000000000000\
+                 123DDA8328AF9C23A94C1FEECFD123BA4FB73476F0D594DCB65C6425BD186051",
+                false,
+            ),
+            // 10: sG - eP is infinite, x(inf) as 1
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "0000000000000000000000000000000000000000000000000000000000000001\
+                 7615FBAF5AE28864013C099742DEADB4DBA87F11AC6754F93780D5A1837CF197",
+                false,
+            ),
+            // 11: sig[0:32] is not an X coordinate on the curve
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "4A298DACAE57395A15D0795DDBFD1DCB564DA82B0F269BC70A74F8220429BA1D\
+                 69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+                false,
+            ),
+            // 12: sig[0:32] is equal to the field size
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F\
+                 69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+                false,
+            ),
+            // 13: sig[32:64] is equal to the curve order
+            (
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769\
+                 FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141",
+                false,
+            ),
+            // 14: public key exceeds the field size
+            (
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC30",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769\
+                 69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B",
+                false,
+            ),
+            // 15 to 18: messages of 0, 1, 17 and 100 bytes
+            (
+                "778CAA53B4393AC467774D09497A87224BF9FAB6F6E68B23086497324D6FD117",
+                "",
+                "71535DB165ECD9FBBC046E5FFAEA61186BB6AD436732FCCC25291A55895464CF\
+                 6069CE26BF03466228F19A3A62DB8A649F2D560FAC652827D1AF0574E427AB63",
+                true,
+            ),
+            (
+                "778CAA53B4393AC467774D09497A87224BF9FAB6F6E68B23086497324D6FD117",
+                "11",
+                "08A20A0AFEF64124649232E0693C583AB1B9934AE63B4C3511F3AE1134C6A303\
+                 EA3173BFEA6683BD101FA5AA5DBC1996FE7CACFC5A577D33EC14564CEC2BACBF",
+                true,
+            ),
+            (
+                "778CAA53B4393AC467774D09497A87224BF9FAB6F6E68B23086497324D6FD117",
+                "0102030405060708090A0B0C0D0E0F1011",
+                "5130F39A4059B43BC7CAC09A19ECE52B5D8699D1A71E3C52DA9AFDB6B50AC370\
+                 C4A482B77BF960F8681540E25B6771ECE1E5A37FD80E5A51897C5566A97EA5A5",
+                true,
+            ),
+            (
+                "778CAA53B4393AC467774D09497A87224BF9FAB6F6E68B23086497324D6FD117",
+                "99999999999999999999999999999999999999999999999999999999999999999999\
+                 99999999999999999999999999999999999999999999999999999999999999999999\
+                 9999999999999999999999999999999999999999999999999999999999999999",
+                "403B12B0D8555A344175EA7EC746566303321E5DBFA8BE6F091635163ECA79A8\
+                 585ED3E3170807E7C03B720FC54C7B23897FCBA0E9D0B4A06894CFD249F22367",
+                true,
+            ),
+        ];
+        for (pk, m, sig, ok) in vectors.iter() {
+            let mut sig = from_hex(sig);
+            assert_eq!(*ok, verify(&::eth::hex32(pk), &from_hex(m), &sig));
+            sig[63] ^= 1;
+            assert!(!verify(&::eth::hex32(pk), &from_hex(m), &sig));
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let mut rng = StdRng::seed_from_u64(39);
+        for _ in 0..4 {
+            let x = crate::rng::random_fe(&mut rng);
+            let pk = xonly(&(GE::generator() * x));
+            let sig = sign(&x, b"Miku-san maji tenshi!", &mut rng).to_bytes();
+            assert!(verify(&pk, b"Miku-san maji tenshi!", &sig));
+            assert!(!verify(&pk, b"another message", &sig));
+        }
+    }
+
+    fn signers(shares: &[&KeyShare], points: &[usize], m: &[u8]) -> Vec<Party> {
+        shares
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Party::new(i, s, points, m))
+            .collect()
+    }
+
+    #[test]
+    fn test_threshold_schnorr() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+        let signing = [&shares[0], &shares[2]];
+
+        let mut sim = Simulator::new(signers(&signing, &[1, 3], message));
+        let results = sim.run(&mut rng);
+        let sig = results[0].as_ref().unwrap();
+        assert_eq!(sig, results[1].as_ref().unwrap());
+        assert!(verify(&xonly(&shares[0].y), message, &sig.to_bytes()));
+        assert!(!verify(
+            &xonly(&shares[0].y),
+            b"another message",
+            &sig.to_bytes()
+        ));
+
+        // a wrong partial signature is pinned on its sender
+        let mut sim = Simulator::new(signers(&signing, &[1, 3], message));
+        let one = FE::from_bigint(&BigInt::from(1));
+        sim.set_tamper(
+            0,
+            Box::new(move |_: &mut Party, out: Vec<Outgoing<SchnorrMessage>>| {
+                out.into_iter()
+                    .map(|o| match o {
+                        Outgoing::Broadcast(SchnorrMessage::Partial(s)) => {
+                            Outgoing::Broadcast(SchnorrMessage::Partial(s + one))
+                        }
+                        o => o,
+                    })
+                    .collect()
+            }),
+        );
+        let results = sim.run(&mut rng);
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&Error::InvalidPartialSignature { party: 0 })
+        );
+    }
+}
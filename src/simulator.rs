@@ -0,0 +1,489 @@
+extern crate curv;
+use self::curv::elliptic::curves::traits::ECPoint;
+use self::curv::{BigInt, FE, GE};
+use rand::{CryptoRng, Rng, RngCore};
+
+use crate::error::Result;
+use crate::etude::{self, fe_from_bigint, MtaOpening, SignMessage};
+use crate::network::{self, Handler, Outgoing};
+
+// Network misbehavior applied to every message in flight.
+pub enum Fault {
+    // messages from a party arrive `ticks` deliveries later than they would
+    Delay { from: usize, ticks: usize },
+    // whatever is deliverable goes out in random order
+    Reorder,
+    Drop { from: usize, to: usize },
+    Duplicate,
+}
+
+// A party running the honest protocol but lying in what it sends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Misbehavior {
+    WrongDelta,
+    CorruptMtaCiphertext,
+    // δi + 1 to the first peer, δi to the others
+    InconsistentBroadcast,
+    // σi + 1 from the MtA on, caught by the R^σi check of GG20
+    WrongSigma,
+    // as Bob, σ0 + 1 in the answer to party 0, hidden in the σ reveal by moving the one
+    // onto the MtA with another party
+    WrongMtaAnswer,
+    WrongPartialSignature,
+}
+
+// rewrites what a party sends; it may also adjust the party so that it keeps its own lie
+pub type Tamper<H> = Box<
+    dyn Fn(
+        &mut H,
+        Vec<Outgoing<<H as Handler>::Message>>,
+    ) -> Vec<Outgoing<<H as Handler>::Message>>,
+>;
+
+impl Misbehavior {
+    pub fn tamper(self, i: usize, n: usize) -> Tamper<etude::Party> {
+        let one = fe_from_bigint(&BigInt::from(1));
+        let first = if i == 0 { 1 } else { 0 };
+        let other = (1..n).find(|j| *j != i).unwrap_or(0);
+        Box::new(
+            move |party: &mut etude::Party, out: Vec<Outgoing<SignMessage>>| {
+                let mut tampered = Vec::with_capacity(out.len());
+                for o in out {
+                    match (self, o) {
+                        (Misbehavior::WrongDelta, Outgoing::Broadcast(SignMessage::Delta(d))) => {
+                            party.set_delta_i(d + one);
+                            tampered.push(Outgoing::Broadcast(SignMessage::Delta(d + one)))
+                        }
+                        (
+                            Misbehavior::CorruptMtaCiphertext,
+                            Outgoing::To(j, SignMessage::MtaRequest(reqs)),
+                        ) => {
+                            let reqs = reqs
+                                .into_iter()
+                                .map(|(e, c, proof)| (e, c + BigInt::from(1), proof))
+                                .collect();
+                            tampered.push(Outgoing::To(j, SignMessage::MtaRequest(reqs)))
+                        }
+                        (
+                            Misbehavior::InconsistentBroadcast,
+                            Outgoing::Broadcast(SignMessage::Delta(d)),
+                        ) => {
+                            for j in (0..n).filter(|j| *j != i) {
+                                let d = if j == first { d + one } else { d };
+                                tampered.push(Outgoing::To(j, SignMessage::Delta(d)));
+                            }
+                        }
+                        (
+                            Misbehavior::WrongSigma,
+                            o @ Outgoing::Broadcast(SignMessage::Delta(_)),
+                        ) => {
+                            party.add_to_sigma_i(one);
+                            tampered.push(o)
+                        }
+                        (
+                            Misbehavior::WrongMtaAnswer,
+                            Outgoing::To(0, SignMessage::MtaResponse(cs, _)),
+                        ) => {
+                            let (cs, sig) = party.skew_mta_response(0, cs);
+                            tampered.push(Outgoing::To(0, SignMessage::MtaResponse(cs, sig)))
+                        }
+                        (
+                            Misbehavior::WrongMtaAnswer,
+                            Outgoing::Broadcast(SignMessage::SigmaReveal(mut r)),
+                        ) => {
+                            // β' + 1 still opens the answer and matches g^(-β'-1)
+                            let g = GE::generator();
+                            let minus_one = fe_from_bigint(&(FE::q() - BigInt::from(1)));
+                            r.kw[0] = r.kw[0].map(|p| p + g * &minus_one);
+                            r.wk[other] = r.wk[other].map(|p| p + g);
+                            if let Some(MtaOpening::Bob { beta, rb }) = r.openings[0].take() {
+                                r.openings[0] = Some(MtaOpening::Bob {
+                                    beta: beta + BigInt::from(1),
+                                    rb: rb,
+                                });
+                            }
+                            tampered.push(Outgoing::Broadcast(SignMessage::SigmaReveal(r)))
+                        }
+                        (
+                            Misbehavior::WrongPartialSignature,
+                            Outgoing::Broadcast(SignMessage::Si(s)),
+                        ) => tampered.push(Outgoing::Broadcast(SignMessage::Si(s + one))),
+                        (_, o) => tampered.push(o),
+                    }
+                }
+                tampered
+            },
+        )
+    }
+}
+
+struct Envelope<M> {
+    from: usize,
+    to: usize,
+    msg: M,
+    at: usize,
+}
+
+// Runs all parties in one process over a simulated network.
+pub struct Simulator<H: Handler> {
+    parties: Vec<H>,
+    faults: Vec<Fault>,
+    tampers: Vec<Option<Tamper<H>>>,
+    deadline: Option<usize>,
+}
+
+impl<H: Handler> Simulator<H> {
+    pub fn new(parties: Vec<H>) -> Self {
+        let tampers = parties.iter().map(|_| None).collect();
+        Self {
+            parties: parties,
+            faults: Vec::new(),
+            tampers: tampers,
+            deadline: None,
+        }
+    }
+
+    // a party still in the same round `ticks` deliveries later times out
+    pub fn set_deadline(&mut self, ticks: usize) {
+        self.deadline = Some(ticks);
+    }
+
+    pub fn add_fault(&mut self, fault: Fault) {
+        self.faults.push(fault);
+    }
+
+    pub fn set_tamper(&mut self, i: usize, tamper: Tamper<H>) {
+        self.tampers[i] = Some(tamper);
+    }
+
+    pub fn parties(&self) -> &[H] {
+        &self.parties
+    }
+
+    fn post(
+        &mut self,
+        from: usize,
+        out: Vec<Outgoing<H::Message>>,
+        clock: usize,
+        queue: &mut Vec<Envelope<H::Message>>,
+    ) {
+        let n = self.parties.len();
+        let out = match self.tampers[from] {
+            Some(ref tamper) => tamper(&mut self.parties[from], out),
+            None => out,
+        };
+        for o in out {
+            let msgs: Vec<(usize, H::Message)> = match o {
+                Outgoing::Broadcast(msg) => (0..n)
+                    .filter(|j| *j != from)
+                    .map(|j| (j, msg.clone()))
+                    .collect(),
+                Outgoing::To(j, msg) => vec![(j, msg)],
+            };
+            for (to, msg) in msgs {
+                let mut at = clock;
+                let mut copies = 1;
+                let mut dropped = false;
+                for fault in self.faults.iter() {
+                    match *fault {
+                        Fault::Delay { from: f, ticks } if f == from => at += ticks,
+                        Fault::Drop { from: f, to: t } if f == from && t == to => dropped = true,
+                        Fault::Duplicate => copies = 2,
+                        _ => {}
+                    }
+                }
+                if dropped {
+                    continue;
+                }
+                for c in 0..copies {
+                    queue.push(Envelope {
+                        from: from,
+                        to: to,
+                        msg: msg.clone(),
+                        at: at + c,
+                    });
+                }
+            }
+        }
+    }
+
+    fn next<R: CryptoRng + RngCore>(
+        &self,
+        queue: &mut Vec<Envelope<H::Message>>,
+        clock: &mut usize,
+        rng: &mut R,
+    ) -> Option<Envelope<H::Message>> {
+        let earliest = queue.iter().map(|e| e.at).min()?;
+        if earliest > *clock {
+            *clock = earliest;
+        }
+        let ready: Vec<usize> = (0..queue.len())
+            .filter(|k| queue[*k].at <= *clock)
+            .collect();
+        let reorder = self.faults.iter().any(|f| match f {
+            Fault::Reorder => true,
+            _ => false,
+        });
+        let k = if reorder {
+            ready[rng.gen_range(0, ready.len())]
+        } else {
+            ready[0]
+        };
+        *clock += 1;
+        Some(queue.remove(k))
+    }
+
+    // Outcome of every party. A party stops at its first error, which names the party
+    // it blames; one still waiting past the deadline or when the network goes quiet
+    // gets Error::Timeout.
+    pub fn run<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Vec<Result<H::Output>> {
+        let n = self.parties.len();
+        let mut results: Vec<Option<Result<H::Output>>> = (0..n).map(|_| None).collect();
+        let mut queue = Vec::new();
+        let mut clock = 0;
+        for i in 0..n {
+            match self.parties[i].start(rng) {
+                Ok(out) => self.post(i, out, clock, &mut queue),
+                Err(e) => results[i] = Some(Err(e)),
+            }
+        }
+        let mut rounds: Vec<(&'static str, usize)> =
+            self.parties.iter().map(|p| (p.round(), clock)).collect();
+        while let Some(env) = self.next(&mut queue, &mut clock, rng) {
+            for (i, p) in self.parties.iter().enumerate() {
+                if results[i].is_some() {
+                    continue;
+                }
+                if p.round() != rounds[i].0 {
+                    rounds[i] = (p.round(), clock);
+                } else if self.deadline.map_or(false, |d| clock > rounds[i].1 + d) {
+                    results[i] = Some(Err(network::timeout(p)));
+                }
+            }
+            let to = env.to;
+            if results[to].is_some() {
+                continue;
+            }
+            match self.parties[to].handle(env.from, env.msg, rng) {
+                Ok(out) => {
+                    self.post(to, out, clock, &mut queue);
+                    results[to] = self.parties[to].output().map(Ok);
+                }
+                Err(e) => results[to] = Some(Err(e)),
+            }
+        }
+        results
+            .into_iter()
+            .zip(self.parties.iter())
+            .map(|(r, p)| r.unwrap_or_else(|| Err(network::timeout(p))))
+            .collect()
+    }
+}
+
+impl Simulator<etude::Party> {
+    pub fn set_misbehavior(&mut self, i: usize, misbehavior: Misbehavior) {
+        let n = self.parties.len();
+        self.set_tamper(i, misbehavior.tamper(i, n));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::etude::{digest_message, Party, Protocol};
+    use crate::keygen::{KeyGen, KeyShare};
+    use crate::simulator::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn signers(shares: &[KeyShare], m: &[u8]) -> Vec<Party> {
+        signers_with(shares, m, Protocol::Gg18)
+    }
+
+    fn signers_with(shares: &[KeyShare], m: &[u8], protocol: Protocol) -> Vec<Party> {
+        let points: Vec<usize> = shares.iter().map(|s| s.i + 1).collect();
+        shares
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let mut p = Party::with_key_share(i, shares.len(), s, &points);
+                p.set_digest(digest_message(m));
+                p.set_protocol(protocol);
+                p
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_faulty_network() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+
+        let mut sim = Simulator::new(signers(&shares, message));
+        sim.add_fault(Fault::Reorder);
+        sim.add_fault(Fault::Duplicate);
+        sim.add_fault(Fault::Delay { from: 1, ticks: 5 });
+        let results = sim.run(&mut rng);
+        for r in results.iter() {
+            match r {
+                Ok(sig) => assert!(sig.verify(&shares[0].y, &digest_message(message))),
+                Err(e) => panic!("signing did not finish: {}", e),
+            }
+        }
+
+        // a lost message stalls the run, and the waiting parties say whom they wait for
+        let mut sim = Simulator::new(signers(&shares, message));
+        sim.add_fault(Fault::Drop { from: 0, to: 2 });
+        let results = sim.run(&mut rng);
+        assert_eq!(
+            results[2].as_ref().err(),
+            Some(&Error::Timeout {
+                round: "BroadcastingGr",
+                missing: vec![0],
+            })
+        );
+        assert!(results.iter().all(|r| r.is_err()));
+
+        // so does a late one past the deadline
+        let mut sim = Simulator::new(signers(&shares, message));
+        sim.add_fault(Fault::Delay { from: 1, ticks: 50 });
+        sim.set_deadline(20);
+        let results = sim.run(&mut rng);
+        assert_eq!(
+            results[0].as_ref().err(),
+            Some(&Error::Timeout {
+                round: "BroadcastingGr",
+                missing: vec![1],
+            })
+        );
+    }
+
+    #[test]
+    fn test_blame() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+        let cases = [
+            (Misbehavior::WrongDelta, Error::InvalidDelta { party: 0 }),
+            (
+                Misbehavior::CorruptMtaCiphertext,
+                Error::InvalidRangeProof { party: 0 },
+            ),
+        ];
+        for (misbehavior, blame) in cases.iter() {
+            let mut sim = Simulator::new(signers(&shares, message));
+            sim.add_fault(Fault::Reorder);
+            sim.set_misbehavior(0, *misbehavior);
+            let results = sim.run(&mut rng);
+            for r in results[1..].iter() {
+                match r {
+                    Err(e) => assert_eq!(e, blame),
+                    Ok(_) => panic!("{:?} went unnoticed", misbehavior),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_blame_echo() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+
+        // 0 sends 1 another δ0: each of 1 and 2 sees the other's echo of 0 differ, and
+        // cannot tell whether 0 or the echoing party lied
+        let mut sim = Simulator::new(signers(&shares, message));
+        sim.set_misbehavior(0, Misbehavior::InconsistentBroadcast);
+        let results = sim.run(&mut rng);
+        for j in 1..3 {
+            assert_eq!(
+                results[j].as_ref().err(),
+                Some(&Error::InconsistentEcho {
+                    party: 0,
+                    echoer: 3 - j,
+                })
+            );
+        }
+
+        // 2 misreports what 0 broadcast: 0 knows it is 2, 1 only that it is 0 or 2
+        let mut sim = Simulator::new(signers(&shares, message));
+        sim.set_tamper(
+            2,
+            Box::new(|_: &mut Party, out: Vec<Outgoing<SignMessage>>| {
+                out.into_iter()
+                    .map(|o| match o {
+                        Outgoing::Broadcast(SignMessage::Ri { ri, mut echo }) => {
+                            echo[0] = echo[0].clone() + BigInt::from(1);
+                            Outgoing::Broadcast(SignMessage::Ri { ri: ri, echo: echo })
+                        }
+                        o => o,
+                    })
+                    .collect()
+            }),
+        );
+        let results = sim.run(&mut rng);
+        assert_eq!(
+            results[0].as_ref().err(),
+            Some(&Error::InconsistentBroadcast { party: 2 })
+        );
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&Error::InconsistentEcho {
+                party: 0,
+                echoer: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_blame_gg20() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+
+        let mut sim = Simulator::new(signers_with(&shares, message, Protocol::Gg20));
+        sim.add_fault(Fault::Reorder);
+        for r in sim.run(&mut rng).iter() {
+            assert!(r
+                .as_ref()
+                .unwrap()
+                .verify(&shares[0].y, &digest_message(message)));
+        }
+
+        // the checks GG18 lacks name the party even after R is known
+        let cases = [
+            (Misbehavior::WrongDelta, Error::InvalidDelta { party: 0 }),
+            (Misbehavior::WrongSigma, Error::InvalidReveal { party: 0 }),
+            (
+                Misbehavior::WrongPartialSignature,
+                Error::InvalidPartialSignature { party: 0 },
+            ),
+        ];
+        for (misbehavior, blame) in cases.iter() {
+            let mut sim = Simulator::new(signers_with(&shares, message, Protocol::Gg20));
+            sim.add_fault(Fault::Reorder);
+            sim.set_misbehavior(0, *misbehavior);
+            let results = sim.run(&mut rng);
+            for r in results[1..].iter() {
+                match r {
+                    Err(e) => assert_eq!(e, blame),
+                    Ok(_) => panic!("{:?} went unnoticed", misbehavior),
+                }
+            }
+        }
+
+        // 1 lies to 0 in the MtA and then in its reveal, so that its MtA with 2 is the one
+        // that does not add up; 2's opening of k2·w1 shows that it is still 1
+        let mut sim = Simulator::new(signers_with(&shares, message, Protocol::Gg20));
+        sim.add_fault(Fault::Reorder);
+        sim.set_misbehavior(1, Misbehavior::WrongMtaAnswer);
+        let results = sim.run(&mut rng);
+        for j in [0, 2].iter() {
+            assert_eq!(
+                results[*j].as_ref().err(),
+                Some(&Error::InvalidMta { party: 1 })
+            );
+        }
+    }
+}
@@ -0,0 +1,572 @@
+extern crate curv;
+use self::curv::cryptographic_primitives::hashing::hash_sha256::HSha256;
+use self::curv::cryptographic_primitives::hashing::traits::Hash;
+use self::curv::BigInt;
+use rand::{CryptoRng, RngCore};
+use std::borrow::Cow;
+
+use crate::curve::{self, Curve, PointOps, ScalarOps, Secp256k1};
+use crate::dlog_proof::{session_salt, DLogProof};
+use crate::error::{Error, Result};
+use crate::etude::Signature;
+use crate::keygen::KeyShare;
+use crate::mta::{self, PdlProof};
+use crate::network::{handle_buffered, replay_backlog, Buffered, Handler, Outgoing};
+use crate::rng;
+use crate::vss;
+
+// Two-party ECDSA in the style of Lindell'17 for the shares of KeyGen::new(1, 2).
+// Party 0 holds the Paillier key and gives party 1 Enc(w0) once per key. To sign, party 0
+// commits to R0 = g^k0, party 1 answers with R1 = g^k1, party 0 opens R0, and with
+// R = R0^k1 = R1^k0 party 1 computes Enc(k1^-1 (m + r·w1) + ρq + k1^-1 r·w0) from Enc(w0).
+// Party 0 decrypts, multiplies by k0^-1 and sends s back. No MtA, one ciphertext per signature.
+//
+// Party 0 only learns whether party 1's ciphertext yields a valid signature, and a malicious
+// party 1 can craft one that does so depending on a bit of w0. Party 0 must therefore never
+// sign again with a key after such a failure: the EncryptedShare is marked aborted and
+// Party::new refuses it from then on.
+
+// w0 = λ0·x0 encrypted under party 0's key, with a proof that it is the discrete log of
+// g^w0 against party 1's aux params
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct EncryptedShare<C: Curve = Secp256k1> {
+    pub c: BigInt,
+    pub proof: PdlProof<C>,
+    // party 0's copy after an InvalidPartialSignature; save it back over the old one
+    #[serde(default)]
+    pub aborted: bool,
+}
+
+fn lambda<C: Curve>(i: usize) -> C::Scalar {
+    vss::lagrange_coefficient::<C::Scalar>(i + 1, &[1, 2])
+}
+
+// a share of a 2-of-2 key, for the role i
+fn check_share<C: Curve>(share: &KeyShare<C>, i: usize) -> Result<()> {
+    if share.t != 1 || share.n != 2 {
+        return Err(Error::InvalidState);
+    }
+    if share.i != i {
+        return Err(Error::UnknownParty);
+    }
+    Ok(())
+}
+
+fn weighted_share<C: Curve>(share: &KeyShare<C>) -> C::Scalar {
+    share.xi * lambda::<C>(share.i)
+}
+
+impl<C: Curve> EncryptedShare<C> {
+    // by party 0, once per key
+    pub fn new<R: CryptoRng + RngCore>(share: &KeyShare<C>, rng: &mut R) -> Self {
+        let w = weighted_share(share);
+        let enc = share.dec.enc();
+        let r = enc.random_bigint(rng);
+        let c = enc
+            .encrypt_with_randomness(&w.to_bigint(), &r)
+            .0
+            .into_owned();
+        let proof = PdlProof::prove(enc, &c, &w, &r, &share.auxs[1], rng);
+        EncryptedShare {
+            c: c,
+            proof: proof,
+            aborted: false,
+        }
+    }
+
+    // by party 1
+    pub fn verify(&self, share: &KeyShare<C>) -> bool {
+        let x0 = share.public_share(0) * lambda::<C>(0);
+        self.proof
+            .verify(&share.eks[0], &self.c, &x0, &share.auxs[1])
+    }
+}
+
+fn commitment<C: Curve>(r: &C::Point) -> BigInt {
+    HSha256::create_hash(&vec![
+        &BigInt::from(C::NAME.as_bytes()),
+        &r.affine_x().unwrap_or_else(|| BigInt::from(0)),
+        &r.affine_y().unwrap_or_else(|| BigInt::from(0)),
+    ])
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum TwoPartyMessage<C: Curve = Secp256k1> {
+    Commit(BigInt),                // 0 -> 1
+    Nonce(C::Point, DLogProof<C>), // 1 -> 0
+    Open(C::Point, DLogProof<C>),  // 0 -> 1
+    Cipher(BigInt),                // 1 -> 0
+    Sig(C::Scalar),                // 0 -> 1
+}
+
+enum PartyState<C: Curve> {
+    Void {},
+    WaitingCommit {},
+    WaitingNonce {},
+    WaitingOpen { commit: BigInt },
+    WaitingCipher { sign_r: C::Point },
+    WaitingSig { sign_r: C::Point },
+    Fin { sig: Signature<C::Scalar> },
+}
+
+pub struct Party<C: Curve = Secp256k1> {
+    pub i: usize,
+
+    wi: C::Scalar,
+    y: C::Point,
+    dec: Option<mta::Dec>,
+    ek: mta::Enc,
+    encrypted: EncryptedShare<C>,
+    digest: C::Scalar,
+    session: Vec<u8>,
+    ki: Option<C::Scalar>,
+    ri: Option<C::Point>,
+
+    state: PartyState<C>,
+    backlog: Vec<(usize, TwoPartyMessage<C>)>,
+}
+
+impl<C: Curve> Party<C> {
+    // party 0, the holder of the Paillier key
+    pub fn new(
+        share: &KeyShare<C>,
+        encrypted: &EncryptedShare<C>,
+        digest: C::Scalar,
+    ) -> Result<Self> {
+        check_share(share, 0)?;
+        if encrypted.aborted {
+            return Err(Error::AbortedKey);
+        }
+        Ok(Self::with_dec(
+            share,
+            Some(share.dec.clone()),
+            encrypted,
+            digest,
+        ))
+    }
+
+    // party 1, with the share party 0 encrypted for it
+    pub fn with_encrypted_share(
+        share: &KeyShare<C>,
+        encrypted: &EncryptedShare<C>,
+        digest: C::Scalar,
+    ) -> Result<Self> {
+        check_share(share, 1)?;
+        if !encrypted.verify(share) {
+            return Err(Error::InvalidRangeProof { party: 0 });
+        }
+        Ok(Self::with_dec(share, None, encrypted, digest))
+    }
+
+    fn with_dec(
+        share: &KeyShare<C>,
+        dec: Option<mta::Dec>,
+        encrypted: &EncryptedShare<C>,
+        digest: C::Scalar,
+    ) -> Self {
+        Party {
+            i: share.i,
+            wi: weighted_share(share),
+            y: share.y,
+            dec: dec,
+            ek: share.eks[0].clone(),
+            encrypted: encrypted.clone(),
+            digest: digest,
+            session: Vec::new(),
+            ki: None,
+            ri: None,
+            state: PartyState::Void {},
+            backlog: Vec::new(),
+        }
+    }
+
+    pub fn get_state_name(&self) -> &'static str {
+        match self.state {
+            PartyState::Void {} => "Void",
+            PartyState::WaitingCommit {} => "WaitingCommit",
+            PartyState::WaitingNonce {} => "WaitingNonce",
+            PartyState::WaitingOpen { .. } => "WaitingOpen",
+            PartyState::WaitingCipher { .. } => "WaitingCipher",
+            PartyState::WaitingSig { .. } => "WaitingSig",
+            PartyState::Fin { .. } => "Fin",
+        }
+    }
+
+    pub fn signature(&self) -> Option<Signature<C::Scalar>> {
+        match self.state {
+            PartyState::Fin { ref sig } => Some(*sig),
+            _ => None,
+        }
+    }
+
+    // Enc(w0), marked aborted on party 0 once party 1 sent a bad ciphertext
+    pub fn encrypted_share(&self) -> &EncryptedShare<C> {
+        &self.encrypted
+    }
+
+    // an id both parties agree on for this signature, which the nonce proofs are bound to
+    pub fn set_session(&mut self, session: &[u8]) {
+        self.session = session.to_vec();
+    }
+
+    fn salt(&self, i: usize) -> Vec<u8> {
+        session_salt(&self.session, &[1, 2], i)
+    }
+
+    fn peer(&self) -> usize {
+        1 - self.i
+    }
+
+    // party 0: checks R1 and opens R0
+    fn on_nonce<R: CryptoRng + RngCore>(
+        &mut self,
+        r1: C::Point,
+        proof: DLogProof<C>,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<TwoPartyMessage<C>>>> {
+        match self.state {
+            PartyState::WaitingNonce {} => {}
+            _ => return Err(Error::InvalidState),
+        }
+        if !proof.verify(&r1, &self.salt(1)) {
+            return Err(Error::InvalidDLogProof { party: 1 });
+        }
+        let k0 = self.ki.unwrap();
+        self.state = PartyState::WaitingCipher { sign_r: r1 * k0 };
+        let proof = DLogProof::prove(&k0, &self.salt(0), rng);
+        Ok(vec![Outgoing::To(
+            1,
+            TwoPartyMessage::Open(self.ri.unwrap(), proof),
+        )])
+    }
+
+    // party 1: checks the opening and sends its half of s
+    fn on_open<R: CryptoRng + RngCore>(
+        &mut self,
+        r0: C::Point,
+        proof: DLogProof<C>,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<TwoPartyMessage<C>>>> {
+        match self.state {
+            PartyState::WaitingOpen { ref commit } => {
+                if commitment::<C>(&r0) != *commit {
+                    return Err(Error::InvalidCommitment { party: 0 });
+                }
+            }
+            _ => return Err(Error::InvalidState),
+        }
+        if !proof.verify(&r0, &self.salt(0)) {
+            return Err(Error::InvalidDLogProof { party: 0 });
+        }
+        let k1_inv = self.ki.unwrap().inverse();
+        let sign_r = r0 * self.ki.unwrap();
+        let r: C::Scalar = curve::x_scalar(&sign_r).unwrap();
+
+        // ρq hides everything of k1^-1 (m + r·w1) but its value mod q
+        let q = C::Scalar::order();
+        let rho = rng::random_below(rng, &(&q * &q));
+        let a = (k1_inv * (self.digest + r * self.wi)).to_bigint() + rho * &q;
+        let c1 = self.ek.encrypt(&a, rng);
+        let c2 = self.ek.mul(
+            mta::RawCiphertext(Cow::Borrowed(&self.encrypted.c)),
+            &(k1_inv * r).to_bigint(),
+        );
+        let c = self.ek.add(c1, c2).0.into_owned();
+        self.state = PartyState::WaitingSig { sign_r: sign_r };
+        Ok(vec![Outgoing::To(0, TwoPartyMessage::Cipher(c))])
+    }
+
+    // party 0: finishes the signature
+    fn on_cipher(&mut self, c: BigInt) -> Result<Vec<Outgoing<TwoPartyMessage<C>>>> {
+        let sign_r = match self.state {
+            PartyState::WaitingCipher { sign_r } => sign_r,
+            _ => return Err(Error::InvalidState),
+        };
+        let s1 = self
+            .dec
+            .as_ref()
+            .unwrap()
+            .decrypt(mta::RawCiphertext(Cow::Owned(c)));
+        let s = self.ki.unwrap().inverse() * C::Scalar::from_bigint(&s1);
+        let sig = Signature::new(&sign_r, &s);
+        // our own part is right, so a bad signature is party 1's doing, and may have been
+        // made to depend on w0
+        if !sig.verify(&self.y, &self.digest) {
+            self.encrypted.aborted = true;
+            return Err(Error::InvalidPartialSignature { party: 1 });
+        }
+        self.state = PartyState::Fin { sig: sig };
+        Ok(vec![Outgoing::To(1, TwoPartyMessage::Sig(sig.s))])
+    }
+
+    // party 1
+    fn on_sig(&mut self, s: C::Scalar) -> Result<()> {
+        let sign_r = match self.state {
+            PartyState::WaitingSig { sign_r } => sign_r,
+            _ => return Err(Error::InvalidState),
+        };
+        let sig = Signature::new(&sign_r, &s);
+        if !sig.verify(&self.y, &self.digest) {
+            return Err(Error::InvalidSignature);
+        }
+        self.state = PartyState::Fin { sig: sig };
+        Ok(())
+    }
+}
+
+impl<C: Curve> Buffered for Party<C> {
+    type Message = TwoPartyMessage<C>;
+
+    fn party(&self) -> (usize, usize) {
+        (self.i, 2)
+    }
+
+    fn round_of(msg: &TwoPartyMessage<C>) -> usize {
+        match msg {
+            TwoPartyMessage::Commit(_) => 1,
+            TwoPartyMessage::Nonce(..) => 2,
+            TwoPartyMessage::Open(..) => 3,
+            TwoPartyMessage::Cipher(_) => 4,
+            TwoPartyMessage::Sig(_) => 5,
+        }
+    }
+
+    fn round_no(&self) -> usize {
+        match self.state {
+            PartyState::Void {} => 0,
+            PartyState::WaitingCommit {} => 1,
+            PartyState::WaitingNonce {} => 2,
+            PartyState::WaitingOpen { .. } => 3,
+            PartyState::WaitingCipher { .. } => 4,
+            PartyState::WaitingSig { .. } => 5,
+            PartyState::Fin { .. } => 6,
+        }
+    }
+
+    fn backlog(&self) -> &Vec<(usize, TwoPartyMessage<C>)> {
+        &self.backlog
+    }
+
+    fn backlog_mut(&mut self) -> &mut Vec<(usize, TwoPartyMessage<C>)> {
+        &mut self.backlog
+    }
+
+    fn ready_for(&self, msg: &TwoPartyMessage<C>) -> bool {
+        match (msg, &self.state) {
+            (TwoPartyMessage::Commit(_), PartyState::WaitingCommit {}) => true,
+            (TwoPartyMessage::Nonce(..), PartyState::WaitingNonce {}) => true,
+            (TwoPartyMessage::Open(..), PartyState::WaitingOpen { .. }) => true,
+            (TwoPartyMessage::Cipher(_), PartyState::WaitingCipher { .. }) => true,
+            (TwoPartyMessage::Sig(_), PartyState::WaitingSig { .. }) => true,
+            _ => false,
+        }
+    }
+
+    fn handle_now<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: TwoPartyMessage<C>,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<TwoPartyMessage<C>>>> {
+        if from != self.peer() {
+            return Err(Error::InvalidState);
+        }
+        match msg {
+            TwoPartyMessage::Commit(c) => {
+                let k1: C::Scalar = rng::random_scalar(rng);
+                let r1 = C::Point::base_point() * k1;
+                let proof = DLogProof::prove(&k1, &self.salt(1), rng);
+                self.ki = Some(k1);
+                self.ri = Some(r1);
+                self.state = PartyState::WaitingOpen { commit: c };
+                Ok(vec![Outgoing::To(0, TwoPartyMessage::Nonce(r1, proof))])
+            }
+            TwoPartyMessage::Nonce(r1, proof) => self.on_nonce(r1, proof, rng),
+            TwoPartyMessage::Open(r0, proof) => self.on_open(r0, proof, rng),
+            TwoPartyMessage::Cipher(c) => self.on_cipher(c),
+            TwoPartyMessage::Sig(s) => {
+                self.on_sig(s)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+impl<C: Curve> Handler for Party<C> {
+    type Message = TwoPartyMessage<C>;
+    type Output = Signature<C::Scalar>;
+
+    fn start<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<TwoPartyMessage<C>>>> {
+        match self.state {
+            PartyState::Void {} => {}
+            _ => return Err(Error::InvalidState),
+        }
+        let mut out = Vec::new();
+        if self.i == 0 {
+            let k0: C::Scalar = rng::random_scalar(rng);
+            let r0 = C::Point::base_point() * k0;
+            self.ki = Some(k0);
+            self.ri = Some(r0);
+            self.state = PartyState::WaitingNonce {};
+            out.push(Outgoing::To(
+                1,
+                TwoPartyMessage::Commit(commitment::<C>(&r0)),
+            ));
+        } else {
+            self.state = PartyState::WaitingCommit {};
+        }
+        out.extend(replay_backlog(self, rng)?);
+        Ok(out)
+    }
+
+    fn handle<R: CryptoRng + RngCore>(
+        &mut self,
+        from: usize,
+        msg: TwoPartyMessage<C>,
+        rng: &mut R,
+    ) -> Result<Vec<Outgoing<TwoPartyMessage<C>>>> {
+        handle_buffered(self, from, msg, rng)
+    }
+
+    fn output(&self) -> Option<Signature<C::Scalar>> {
+        self.signature()
+    }
+
+    fn round(&self) -> &'static str {
+        self.get_state_name()
+    }
+
+    fn missing(&self) -> Vec<usize> {
+        match self.state {
+            PartyState::Void {} | PartyState::Fin { .. } => Vec::new(),
+            _ => vec![self.peer()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::{BigInt, FE};
+    use crate::curve::{self, Curve, ScalarOps, P256};
+    use crate::error::Error;
+    use crate::etude::digest_message;
+    use crate::keygen::{KeyGen, KeyShare};
+    use crate::mta::PdlProof;
+    use crate::network::Outgoing;
+    use crate::simulator::{Fault, Simulator};
+    use crate::two_party::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn parties<C: Curve>(
+        shares: &[KeyShare<C>],
+        digest: C::Scalar,
+        rng: &mut StdRng,
+    ) -> Vec<Party<C>> {
+        let encrypted = EncryptedShare::new(&shares[0], rng);
+        vec![
+            Party::new(&shares[0], &encrypted, digest).unwrap(),
+            Party::with_encrypted_share(&shares[1], &encrypted, digest).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_two_party() {
+        let message = "Miku-san maji tenshi!".as_bytes();
+        let digest = digest_message(message);
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::new(1, 2).run_with_rng(&mut rng).unwrap();
+
+        let mut sim = Simulator::new(parties(&shares, digest, &mut rng));
+        sim.add_fault(Fault::Reorder);
+        let results = sim.run(&mut rng);
+        let sig = results[0].as_ref().unwrap();
+        assert_eq!(sig, results[1].as_ref().unwrap());
+        assert!(sig.verify(&shares[0].y, &digest));
+
+        // the ciphertext of party 1 is checked through the signature it yields
+        let mut sim = Simulator::new(parties(&shares, digest, &mut rng));
+        sim.set_tamper(
+            1,
+            Box::new(|_: &mut Party, out: Vec<Outgoing<TwoPartyMessage>>| {
+                out.into_iter()
+                    .map(|o| match o {
+                        Outgoing::To(j, TwoPartyMessage::Cipher(c)) => {
+                            Outgoing::To(j, TwoPartyMessage::Cipher(c + BigInt::from(1)))
+                        }
+                        o => o,
+                    })
+                    .collect()
+            }),
+        );
+        let results = sim.run(&mut rng);
+        assert_eq!(
+            results[0].as_ref().err(),
+            Some(&Error::InvalidPartialSignature { party: 1 })
+        );
+        // and party 0 never signs with that key again
+        let aborted = sim.parties()[0].encrypted_share().clone();
+        assert!(aborted.aborted);
+        assert_eq!(
+            Party::new(&shares[0], &aborted, digest).err(),
+            Some(Error::AbortedKey)
+        );
+
+        // only a share of a 2-of-2 key, in its own role
+        let encrypted = EncryptedShare::new(&shares[0], &mut rng);
+        assert_eq!(
+            Party::new(&shares[1], &encrypted, digest).err(),
+            Some(Error::UnknownParty)
+        );
+        assert_eq!(
+            Party::with_encrypted_share(&shares[0], &encrypted, digest).err(),
+            Some(Error::UnknownParty)
+        );
+        let three = KeyGen::new(1, 3).run_with_rng(&mut rng).unwrap();
+        assert_eq!(
+            Party::new(&three[0], &encrypted, digest).err(),
+            Some(Error::InvalidState)
+        );
+
+        // party 1 only takes Enc(w0) with a proof that it is the dlog of g^w0
+        let mut encrypted = EncryptedShare::new(&shares[0], &mut rng);
+        encrypted.c = encrypted.c + BigInt::from(1);
+        assert_eq!(
+            Party::with_encrypted_share(&shares[1], &encrypted, digest).err(),
+            Some(Error::InvalidRangeProof { party: 0 })
+        );
+        // nor with Enc of a value other than w0, even one in range
+        let enc = shares[0].dec.enc();
+        let wrong: FE = crate::rng::random_scalar(&mut rng);
+        let r = enc.random_bigint(&mut rng);
+        let mut forged = EncryptedShare::new(&shares[0], &mut rng);
+        forged.c = enc
+            .encrypt_with_randomness(&wrong.to_bigint(), &r)
+            .0
+            .into_owned();
+        forged.proof = PdlProof::prove(enc, &forged.c, &wrong, &r, &shares[0].auxs[1], &mut rng);
+        assert_eq!(
+            Party::with_encrypted_share(&shares[1], &forged, digest).err(),
+            Some(Error::InvalidRangeProof { party: 0 })
+        );
+    }
+
+    #[test]
+    fn test_two_party_p256() {
+        let mut rng = StdRng::seed_from_u64(39);
+        let shares = KeyGen::<P256>::with_curve(1, 2)
+            .run_with_rng(&mut rng)
+            .unwrap();
+        let digest = curve::hash_to_scalar(b"Miku-san maji tenshi!");
+        let results = Simulator::new(parties(&shares, digest, &mut rng)).run(&mut rng);
+        let sig = results[0].as_ref().unwrap();
+        assert!(sig.verify(&shares[0].y, &digest));
+        assert_eq!(sig.r, results[1].as_ref().unwrap().r);
+    }
+}
@@ -0,0 +1,114 @@
+extern crate curv;
+use self::curv::BigInt;
+use rand::{CryptoRng, RngCore};
+use std::ops::Add;
+
+use crate::curve::{PointOps, ScalarOps};
+use crate::rng;
+
+// Feldman VSS over Shamir evaluation points x = 1, 2, ...
+
+pub fn sample_polynomial<S: ScalarOps, R: CryptoRng + RngCore>(
+    t: usize,
+    secret: S,
+    rng: &mut R,
+) -> Vec<S> {
+    let mut coefficients = vec![secret];
+    coefficients.extend((0..t).map(|_| rng::random_scalar::<S, R>(rng)));
+    coefficients
+}
+
+pub fn evaluate<S: ScalarOps>(coefficients: &[S], x: usize) -> S {
+    let q = S::order();
+    let x = BigInt::from(x as u64);
+    let v = coefficients.iter().rev().fold(BigInt::from(0), |acc, c| {
+        (acc * &x + c.to_bigint()).mod_floor(&q)
+    });
+    S::from_bigint(&v)
+}
+
+pub fn commit<S: ScalarOps, P: PointOps<S>>(coefficients: &[S]) -> Vec<P> {
+    let g = P::base_point();
+    coefficients.iter().map(|c| g * *c).collect()
+}
+
+// Π commitments[k]^(x^(k+offset))
+pub fn evaluate_in_exponent<S: ScalarOps, P: PointOps<S>>(
+    commitments: &[P],
+    x: usize,
+    offset: usize,
+) -> Option<P> {
+    let q = S::order();
+    let x = BigInt::from(x as u64);
+    commitments.iter().enumerate().fold(None, |acc, (k, c)| {
+        let e = x.powm(&BigInt::from((k + offset) as u64), &q);
+        let term = *c * S::from_bigint(&e);
+        match acc {
+            Some(a) => Some(a + term),
+            None => Some(term),
+        }
+    })
+}
+
+pub fn validate<S: ScalarOps, P: PointOps<S>>(commitments: &[P], x: usize, share: &S) -> bool {
+    match evaluate_in_exponent(commitments, x, 0) {
+        Some(p) => p == P::base_point() * *share,
+        None => false,
+    }
+}
+
+// commitments to a_1..a_t of a polynomial whose constant term is zero
+pub fn validate_zero<S: ScalarOps, P: PointOps<S>>(commitments: &[P], x: usize, share: &S) -> bool {
+    match evaluate_in_exponent(commitments, x, 1) {
+        Some(p) => p == P::base_point() * *share,
+        None => share.to_bigint() == BigInt::from(0),
+    }
+}
+
+// λ_x = Π_{x_j != x} x_j / (x_j - x)
+pub fn lagrange_coefficient<S: ScalarOps>(x: usize, xs: &[usize]) -> S {
+    let q = S::order();
+    let (num, den) = xs.iter().filter(|xj| **xj != x).fold(
+        (BigInt::from(1), BigInt::from(1)),
+        |(num, den), xj| {
+            let d = BigInt::from(*xj as i64) - BigInt::from(x as i64);
+            (
+                (num * BigInt::from(*xj as u64)).mod_floor(&q),
+                (den * d).mod_floor(&q),
+            )
+        },
+    );
+    let den_inv = den.invert(&q).unwrap();
+    S::from_bigint(&(num * den_inv).mod_floor(&q))
+}
+
+pub fn sum_points<P: Copy + Add<Output = P>>(points: &[P]) -> P {
+    points[1..].iter().fold(points[0], |acc, p| acc + *p)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate curv;
+    use self::curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use self::curv::{FE, GE};
+    use crate::vss::*;
+
+    #[test]
+    fn test_vss() {
+        let secret: FE = FE::new_random();
+        let poly = sample_polynomial(2, secret, &mut ::rand::thread_rng());
+        let commitments: Vec<GE> = commit(&poly);
+        let shares: Vec<FE> = (1..6).map(|x| evaluate(&poly, x)).collect();
+        for x in 1..6 {
+            assert!(validate(&commitments, x, &shares[x - 1]));
+        }
+        assert!(!validate(&commitments, 1, &shares[1]));
+
+        let xs = [2, 4, 5];
+        let recovered = xs.iter().fold(FE::zero(), |acc, x| {
+            acc + shares[x - 1] * lagrange_coefficient::<FE>(*x, &xs)
+        });
+        assert_eq!(secret, recovered);
+        assert_eq!(GE::generator() * secret, commitments[0]);
+    }
+}